@@ -0,0 +1,35 @@
+// Throws arbitrary bytes at the frame parser. Whatever comes in, it has to answer with a frame or a
+// `FrameError`, never a panic
+//
+//     cargo +nightly fuzz run frame_parse
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+
+use backend::internal::dissect::dissect_capture;
+use backend::internal::frame_type::{Cmd, CmdRef, Frame, FrameRef};
+
+fuzz_target!(|bytes: &[u8]| {
+    if let Ok((frame, consumed)) = Frame::parse(bytes) {
+        assert!(usize::from(consumed) <= bytes.len());
+
+        // Anything that parsed has to go back on the line
+        let _ = frame.as_bytes();
+    }
+
+    // Records are only checked up front when read in place, iterating them has to hold up too
+    if let Ok((frame, _)) = FrameRef::parse(bytes) {
+        if let CmdRef::RecordRSSI { record_count, records, .. } = frame.get_cmd() {
+            assert_eq!(*record_count as usize, records.clone().count());
+        }
+    }
+
+    let _ = Frame::scan(bytes);
+    let _ = dissect_capture(bytes);
+
+    // Bodies on their own, the way reassembled fragments get parsed
+    if let Some((&cmd_nibble, body)) = bytes.split_first() {
+        let _ = Cmd::parse_body(cmd_nibble & 0x0F, body.len(), body);
+    }
+});
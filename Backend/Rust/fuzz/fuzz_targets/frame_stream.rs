@@ -0,0 +1,24 @@
+// Feeds arbitrary bytes through the receive path, the way they'd come off the serial line: resync,
+// window bookkeeping and reassembly of fragments
+//
+//     cargo +nightly fuzz run frame_stream
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+
+use backend::internal::frame_ops::rx_frame;
+use backend::internal::frame_type::{FrameError, FrameStack};
+use backend::internal::trace::{Direction, TraceEntry, TraceReplay};
+
+fuzz_target!(|bytes: &[u8]| {
+    let mut port = TraceReplay::from_entries(&[TraceEntry::new(Direction::Rx, bytes)]);
+    let mut frame_stack = FrameStack::new();
+
+    loop {
+        match rx_frame(&mut frame_stack, &mut port) {
+            Err(FrameError::ConnectionClosed) => break,
+            _ => continue
+        }
+    }
+});
@@ -0,0 +1,92 @@
+// Decodes frames from hex dumps, raw captures of the line or trace files, so the link can be debugged
+// without reading bytes against the frame definitions by hand
+//
+//     dissect 200000000001BBC6 "[0x40, 0x04, 0x00, 0x00, 0x00, 0x0A, 0x00, 0x00, 0x00, 0x05, 0x11, 0x18]"
+//     dissect --raw capture.bin
+//     dissect --trace res/session.trace
+//
+// With no arguments, hex frames are read from stdin, one line at a time
+
+// std imports
+use std::fs;
+use std::io::{self, BufRead};
+use std::path::Path;
+use std::process::ExitCode;
+
+// own imports
+use backend::internal::dissect::{dissect_capture, dissect_frames, parse_hex_input, Dissected};
+use backend::internal::trace::{load_trace, Direction};
+
+const USAGE: &str = "Usage: dissect [<hex frame>... | --raw <capture file> | --trace <trace file>]";
+
+/// Prints every dissection. Returns whether they were all valid frames
+fn print_dissected(dissected: &[Dissected]) -> bool {
+    let mut valid = true;
+    for dissected in dissected {
+        println!("{}", dissected);
+        valid &= matches!(dissected, Dissected::Frame(dissection) if dissection.is_valid());
+    }
+
+    valid
+}
+
+fn dissect_hex(input: &str) -> bool {
+    match parse_hex_input(input) {
+        Some(bytes) => print_dissected(&dissect_frames(&bytes)),
+        None => {
+            eprintln!("[ERROR]Not hex: '{}'", input);
+            false
+        }
+    }
+}
+
+fn dissect_raw(path: &Path) -> io::Result<bool> {
+    let bytes = fs::read(path)?;
+    Ok(print_dissected(&dissect_capture(&bytes)))
+}
+
+fn dissect_trace(path: &Path) -> io::Result<bool> {
+    let mut valid = true;
+    for entry in load_trace(path)? {
+        let direction = match entry.direction {
+            Direction::Rx => "rx",
+            Direction::Tx => "tx",
+        };
+
+        println!("{} {}", entry.timestamp_ms, direction);
+        valid &= print_dissected(&dissect_capture(&entry.bytes));
+    }
+
+    Ok(valid)
+}
+
+fn main() -> ExitCode {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+
+    let result = match args.iter().map(String::as_str).collect::<Vec<_>>().as_slice() {
+        ["--help" | "-h"] => {
+            println!("{}", USAGE);
+            return ExitCode::SUCCESS;
+        },
+        ["--raw"  , path] => dissect_raw  (Path::new(path)),
+        ["--trace", path] => dissect_trace(Path::new(path)),
+        [flag, ..] if flag.starts_with("--") => {
+            eprintln!("{}", USAGE);
+            return ExitCode::from(2);
+        },
+        [] => io::stdin().lock().lines()
+            .map(|line| line.map(|line| line.trim().is_empty() || dissect_hex(&line)))
+            .try_fold(true, |valid, line| line.map(|line| valid & line)),
+        hex => Ok(hex.iter().fold(true, |valid, input| valid & dissect_hex(input)))
+    };
+
+    // Anything that isn't a valid frame fails the run, so it can be scripted against
+    match result {
+        Ok(true ) => ExitCode::SUCCESS,
+        Ok(false) => ExitCode::FAILURE,
+        Err(e) => {
+            eprintln!("[ERROR]{}", e);
+            ExitCode::from(2)
+        }
+    }
+}
@@ -1,7 +1,6 @@
 use std::process::ExitStatus;
 use std::process::Command;
-use std::sync::{mpsc, Arc, Mutex};
-use std::thread;
+use std::sync::{Arc, Mutex};
 use std::time::Duration;
 
 use rocket::form::Form;
@@ -11,12 +10,18 @@ use rocket::{http::CookieJar, serde::json};
 use crate::internal::logger::Logger;
 use crate::internal::logger::Severity;
 use crate::internal::threading_comm::Message;
+use crate::internal::registry::{Rig, RigRegistry};
+use crate::internal::link::LinkState;
+use crate::internal::session::SessionState;
 use crate::model::db;
 
 
 pub const OAUTH2_TOKEN_COOKIE : & 'static str = "oauth_token";
 pub const OAUTH2_USER_ID      : & 'static str = "oauth_user_id";
 
+// How long a rig's backend has to answer a status inquiry. It's checked on between reads, a busy one takes a while
+const BACKEND_STATUS_TIMEOUT: Duration = Duration::from_millis(500);
+
 #[get("/api/<user_id>/project_list", rank=10)]
 pub async fn get_project_list(user_id: &str, cookies : &CookieJar<'_>) -> json::Value {
 
@@ -67,30 +72,9 @@ pub async fn get_project_list(user_id: &str, cookies : &CookieJar<'_>) -> json::
     )
 }
 
-type ThreadReceiver = mpsc::Receiver<Message>;
-type ThreadSender   = mpsc::Sender<Message>;
 // TODO: Check status on TTY Bind fail but ESP32 status up
 #[get("/api/connection_status")]
-pub async fn get_connection_status(threading_comm : &State<(ThreadSender, Mutex<ThreadReceiver>)>) -> json::Value {
-    // let (tx_web, rx_esp) = (threading_comm.0, threading_comm.1);
-
-    let backend_ready = {
-        // inquiry about the the status of the esp32 backend
-        if let Ok(receiver) = threading_comm.1.lock() {
-            println!("[INFO][LOCAL]requested backend status");
-            let mut msg = threading_comm.0.send(Message::BackendStatusRequest);
-            while let Err(_) = msg {
-                thread::sleep(Duration::from_millis(50));
-                
-                msg = threading_comm.0.send(Message::BackendStatusRequest);
-            }
-
-            Ok(Message::BackendReady(true)) == receiver.try_recv()
-        } else {
-            false
-        }
-    };
-
+pub async fn get_connection_status(logger: &LoggerMutex, rigs: &Rigs) -> json::Value {
     let config = crate::internal::config::load_config().unwrap_or_default();
 
     let esp32_cam_up = {
@@ -104,6 +88,16 @@ pub async fn get_connection_status(threading_comm : &State<(ThreadSender, Mutex<
             }
     };
 
+    // "esp32" is the first rig, for whoever only knows of one
+    let rig_status: Vec<json::Value> = rigs.rigs().iter().map(|rig| get_rig_status(rig, logger)).collect();
+    let esp32 = rig_status.first().cloned().unwrap_or(rocket::serde::json::json!({
+        "up": false,
+        "link": LinkState::Down.as_str(),
+        "session": SessionState::Disconnected.as_str(),
+        "ready": false,
+        "protocol": null
+    }));
+
     rocket::serde::json::json! (
         {
             "status": {
@@ -111,10 +105,8 @@ pub async fn get_connection_status(threading_comm : &State<(ThreadSender, Mutex<
                     "up": esp32_cam_up,
                     "ready": true
                 },
-                "esp32": {
-                    "up": true, // always true, since the backend runs on the same program as the backend web
-                    "ready": backend_ready
-                },
+                "esp32": esp32,
+                "rigs": rig_status,
                 "backend": {
                     "up": true,
                     "ready": true
@@ -124,6 +116,84 @@ pub async fn get_connection_status(threading_comm : &State<(ThreadSender, Mutex<
     )
 }
 
+/// How the ESP32 of `rig` is doing, as far as its backend thread knows
+fn get_rig_status(rig: &Rig, logger: &Arc<Mutex<Logger>>) -> json::Value {
+    let backend_ready = {
+        // inquiry about the the status of the esp32 backend
+        if let Ok(receiver) = rig.receiver().lock() {
+            // Answers to inquiries that timed out, or a backend saying it's done, would be taken for this one's
+            while receiver.try_recv().is_ok() {}
+
+            if let Ok(mut handle) = logger.lock() {
+                handle.log(Severity::DEBUG, &format!("Requested backend status of rig {}", rig.id()));
+            }
+
+            // A backend that's gone can't be ready
+            match rig.sender().send(Message::BackendStatusRequest) {
+                Ok(()) => Ok(Message::BackendReady(true)) == receiver.recv_timeout(BACKEND_STATUS_TIMEOUT),
+                Err(_) => false
+            }
+        } else {
+            false
+        }
+    };
+
+    // null until a handshake settles on one
+    let (protocol, link, session) = if let Ok(status) = rig.status().lock() {
+        let protocol = status.protocol().map(|protocol| rocket::serde::json::json!({
+            "version"     : protocol.version(),
+            "capabilities": protocol.capability_names()
+        }));
+
+        (protocol, status.link_state(), status.session_state())
+    } else {
+        (None, LinkState::Down, SessionState::Disconnected)
+    };
+
+    rocket::serde::json::json!({
+        "id": rig.id(),
+        "up": link != LinkState::Down,
+        "link": link.as_str(),
+        "session": session.as_str(),
+        "ready": backend_ready,
+        "protocol": protocol
+    })
+}
+
+/// Serial ports the backend looked for the ESP32 of `rig` on, the first rig's without, and the one
+/// it found it on
+#[get("/api/serial_ports?<rig>")]
+pub async fn get_serial_ports(rig: Option<&str>, rigs: &Rigs) -> json::Value {
+    let status = match rigs.get(rig) {
+        Some(rig) => rig.status(),
+        None => return rocket::serde::json::json!({
+            "code" : 404,
+            "comment": "No such rig",
+            "port" : null,
+            "ports": []
+        })
+    };
+
+    if let Ok(status) = status.lock() {
+        let ports: Vec<json::Value> = status.ports().iter().map(|port| rocket::serde::json::json!({
+            "path"  : port.path.display().to_string(),
+            "status": port.result.as_str()
+        })).collect();
+
+        rocket::serde::json::json!({
+            "code" : 200,
+            "port" : status.port().map(|port| port.display().to_string()),
+            "ports": ports
+        })
+    } else {
+        rocket::serde::json::json!({
+            "code" : 500,
+            "port" : null,
+            "ports": []
+        })
+    }
+}
+
 #[get("/api/terminal/<start>", rank=1)]
 pub async fn get_terminal_contents(start: usize, logger:  &State<Arc<Mutex<Logger>>>) -> json::Value {
     if let Ok(handle) = logger.lock() {
@@ -167,13 +237,17 @@ pub struct CaptureRequest {
     step_y_deg: u32,
 
     #[field(validate = range(1..=20))]
-    measurements_per_step: u8
+    measurements_per_step: u8,
+
+    /// Which rig runs the capture, the first one if none is picked
+    #[field()]
+    rig: Option<String>
 }
 
-type ThreadingComm = State<(ThreadSender, Mutex<ThreadReceiver>)>;
+type Rigs = State<RigRegistry>;
 type LoggerMutex = State<Arc<Mutex<Logger>>>;
 #[post("/api/start", data = "<params>")]
-pub async fn post_capture_request(params: Form<CaptureRequest>, logger:  &LoggerMutex, threading_comm : &ThreadingComm, cookies : &CookieJar<'_>) -> () {
+pub async fn post_capture_request(params: Form<CaptureRequest>, logger:  &LoggerMutex, rigs : &Rigs, cookies : &CookieJar<'_>) -> () {
     // let (tx_web, rx_esp) = (threading_comm.0, threading_comm.1);
     if cookies.get(&OAUTH2_TOKEN_COOKIE).is_none() {
         return;
@@ -184,6 +258,17 @@ pub async fn post_capture_request(params: Form<CaptureRequest>, logger:  &Logger
         handle.log(Severity::INFO, "==> Capture start requested! <==")
     }
 
+    // Before the project is made, so a capture no rig can run doesn't leave one behind
+    let rig = match rigs.get(params.rig.as_deref()) {
+        Some(rig) => rig,
+        None => {
+            if let Ok(mut handle) = logger.lock() {
+                handle.log(Severity::ERROR, &format!("No rig {:?} to run the capture on", params.rig));
+            }
+            return;
+        }
+    };
+
     let user_id = if let Some(user_id) = cookies.get(&OAUTH2_USER_ID) {
         // name=value
         let user_id = user_id.to_string();
@@ -216,14 +301,62 @@ pub async fn post_capture_request(params: Form<CaptureRequest>, logger:  &Logger
     };
 
     dbg!(&project);
-    let mut msg = threading_comm.0.send(Message::StartCapture(project.clone()));
-    while let Err(e) = msg {
+    // Only fails once the rig's backend is gone, sending again won't bring it back
+    if let Err(e) = rig.sender().send(Message::StartCapture(project)) {
         if let Ok(mut handle) = logger.lock() {
-            handle.log(Severity::ERROR, &format!("Failed to transmit backend status with error '{}'. Retrying in 50ms", e.to_string()));
-            thread::sleep(Duration::from_millis(50));
+            handle.log(Severity::ERROR, &format!("Failed to transmit the capture order to rig {} with error '{}'", rig.id(), e));
         }
-        msg = threading_comm.0.send(Message::StartCapture(project.clone()));
-    } 
+        return;
+    }
 
     println!("{:?}", params);
-}
\ No newline at end of file
+}
+/// Passes an order for the capture under way on to the backend of `rig`, which ignores it if there's none
+fn send_capture_order(order: Message, name: &str, rig: Option<&str>, logger: &LoggerMutex, rigs: &Rigs, cookies: &CookieJar<'_>) -> json::Value {
+    if cookies.get(OAUTH2_TOKEN_COOKIE).is_none() {
+        return rocket::serde::json::json!({
+            "code": 403,
+            "comment": "Must be logged in to control a capture"
+        });
+    }
+
+    if let Ok(mut handle) = logger.lock() {
+        handle.log(Severity::INFO, &format!("==> Capture {} requested! <==", name));
+    }
+
+    let rig = match rigs.get(rig) {
+        Some(rig) => rig,
+        None => return rocket::serde::json::json!({
+            "code": 404,
+            "comment": "No such rig"
+        })
+    };
+
+    if let Err(e) = rig.sender().send(order) {
+        if let Ok(mut handle) = logger.lock() {
+            handle.log(Severity::ERROR, &format!("Failed to transmit capture {} with error '{}'", name, e));
+        }
+
+        return rocket::serde::json::json!({
+            "code": 500,
+            "comment": "ESP32 backend is not running"
+        });
+    }
+
+    rocket::serde::json::json!({ "code": 200 })
+}
+
+#[post("/api/capture/pause?<rig>")]
+pub async fn post_capture_pause(rig: Option<&str>, logger: &LoggerMutex, rigs: &Rigs, cookies: &CookieJar<'_>) -> json::Value {
+    send_capture_order(Message::PauseCapture, "pause", rig, logger, rigs, cookies)
+}
+
+#[post("/api/capture/resume?<rig>")]
+pub async fn post_capture_resume(rig: Option<&str>, logger: &LoggerMutex, rigs: &Rigs, cookies: &CookieJar<'_>) -> json::Value {
+    send_capture_order(Message::ResumeCapture, "resume", rig, logger, rigs, cookies)
+}
+
+#[post("/api/capture/abort?<rig>")]
+pub async fn post_capture_abort(rig: Option<&str>, logger: &LoggerMutex, rigs: &Rigs, cookies: &CookieJar<'_>) -> json::Value {
+    send_capture_order(Message::AbortCapture, "abort", rig, logger, rigs, cookies)
+}
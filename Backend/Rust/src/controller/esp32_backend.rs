@@ -1,24 +1,53 @@
 // std imports
 use std::collections::HashMap;
-use std::{thread, time::Duration};
+use std::{path::{Path, PathBuf}, thread, time::Duration};
 use std::sync::{mpsc, Arc, Mutex};
 
 use rocket::serde::json;
 use rocket::tokio;
-use serde_json::json;
-use serial::unix::TTYPort;
+use tokio_util::sync::CancellationToken;
 
 // own imports
-use crate::{proc_rx_logs, proc_rx_request_ack, proc_tx_handshake, proc_tx_reset, rx_frame_blocking, Cmd, FrameStack, NetworkId, Position, BSSID};
-use crate::internal::threading_comm::Message;
+use crate::{proc_rx_logs, proc_rx_request_ack, proc_rx_request_retransmit, proc_tx_handshake_fallback, proc_tx_heartbeat, proc_tx_reset, proc_tx_reset_at, retx_expired_frames, rx_frame, rx_frame_ref, tx_new_frame, Cmd, FrameStack, NetworkId, Position, BSSID};
+use crate::internal::threading_comm::{Esp32Status, Message};
+use crate::internal::registry::{Rig, RigRegistry};
 use crate::internal::logger::{Logger, Severity};
 use crate::internal::frame_type::*;
-use crate::create_port_conn;
+use crate::internal::config::{Config, SerialConfig, TransportConfig};
+use crate::internal::transport::{discover_transport, open_transport, FrameTransport, Transport, READ_TIMEOUT};
+use crate::internal::link::LinkState;
+use crate::internal::framing::Framing;
+use crate::internal::trace::TraceRecorder;
+use crate::internal::picture::PictureAssembler;
+use crate::internal::position::{PositionMismatch, PositionTracker};
+use crate::internal::session::SessionState;
 use crate::model::{self, db};
 
 
 type ThreadReceiver = mpsc::Receiver<Message>;
 type ThreadSender   = mpsc::Sender<Message>;
+
+// How long to listen for the ESP32 between two checks for a capture order
+const IDLE_POLL_TIMEOUT: Duration = Duration::from_millis(300);
+
+// Times a position is measured again after the ESP32 failed at it, before giving up on the capture
+const MAX_POSITION_RETRIES: u32 = 2;
+
+// Times a capture is picked back up after losing the link, before it's given up on
+const MAX_RESUME_ATTEMPTS: u32 = 3;
+
+// Times the handshake and the reset are tried before the session is given up on
+const MAX_HANDSHAKE_ATTEMPTS: u32 = 5;
+const MAX_RESET_ATTEMPTS    : u32 = 3;
+
+// Failed reads in a row, and the longest the ESP32 may go without sending anything mid-capture, before
+// the link is taken as lost. Firmware without heartbeats never shows its link as down otherwise
+const MAX_FAILED_READS: u32 = 5;
+const MAX_SILENCE: Duration = READ_TIMEOUT;
+
+// How long a rig's backend waits before starting over on a session that failed
+const SESSION_RESTART_DELAY: Duration = Duration::from_secs(2);
+
 fn handle_thread_msg(logger : &Arc<Mutex<Logger>>, rx_thread: &ThreadReceiver, tx_thread: &ThreadSender, port_status: bool) -> Option<Message> {
     let msg = if let Ok(msg) = rx_thread.try_recv() {
         msg
@@ -28,16 +57,17 @@ fn handle_thread_msg(logger : &Arc<Mutex<Logger>>, rx_thread: &ThreadReceiver, t
 
     match msg {
         Message::StartCapture(_)      => {},
+        Message::PauseCapture         |
+        Message::ResumeCapture        |
+        Message::AbortCapture         => {},
         Message::BackendReady(_)      => panic!("Unreachable!"),
         Message::BackendStatusRequest => {
             println!("[INFO][LOCAL]handling request for backend status");
-            let mut msg = tx_thread.send(Message::BackendReady(true));
-            while let Err(e) = msg {
+            // Only fails once the web thread is gone, and then there's no one left to tell
+            if let Err(e) = tx_thread.send(Message::BackendReady(port_status)) {
                 if let Ok(mut handle) = logger.lock() {
-                    handle.log(Severity::ERROR, &format!("Failed to transmit backend status with error '{}'. Retrying in 50ms", e.to_string()));
-                    thread::sleep(Duration::from_millis(50));
+                    handle.log(Severity::ERROR, &format!("Failed to transmit backend status with error '{}'", e));
                 }
-                msg = tx_thread.send(Message::BackendReady(port_status));
             }
         },
     }
@@ -45,30 +75,220 @@ fn handle_thread_msg(logger : &Arc<Mutex<Logger>>, rx_thread: &ThreadReceiver, t
     Some(msg)
 }
 
-fn terminate_esp32_backend(logger : Arc<Mutex<Logger>>, tx_thread: ThreadSender) {
+/// Whether trying again can get anywhere. A closed line stays closed, and a window that's full only fills up further
+fn is_retryable(e: &FrameError) -> bool {
+    !matches!(e, FrameError::ConnectionClosed | FrameError::WindowFull)
+}
+
+/// Ends a session that can't go on, `step` being what failed, so Rocket doesn't wait on it
+fn abandon_session(logger : Arc<Mutex<Logger>>, frame_stack: &FrameStack, status: &Arc<Mutex<Esp32Status>>, tx_thread: &ThreadSender, step: &str, e: FrameError) -> Result<(), FrameError> {
+    if let Ok(mut handle) = logger.lock() {
+        handle.log(Severity::ERROR, &format!("Giving up on the ESP32, the {} failed with error '{:?}'", step, e));
+    }
+    enter_session_state(&logger, frame_stack, SessionState::Failed);
+    terminate_esp32_backend(logger, status, tx_thread);
+
+    Err(e)
+}
+
+fn terminate_esp32_backend(logger : Arc<Mutex<Logger>>, status: &Arc<Mutex<Esp32Status>>, tx_thread: &ThreadSender) {
+    if let Ok(mut status) = status.lock() {
+        status.set_protocol(None);
+        status.set_link(None);
+    }
+
     // Inform Rocket the backend is no longer ready
-    while let Err(e) = tx_thread.send(Message::BackendReady(false)) {
+    if let Err(e) = tx_thread.send(Message::BackendReady(false)) {
         if let Ok(mut handle) = logger.lock() {
-            handle.log(Severity::ERROR, &format!("Failed to transmit backend status with error '{}'. Retrying in 50ms", e.to_string()));
-            thread::sleep(Duration::from_millis(50));
+            handle.log(Severity::ERROR, &format!("Failed to transmit backend status with error '{}'", e));
         }
     }
 }
 
-fn capture_project_data(logger : &Arc<Mutex<Logger>>, project: model::types::Project, frame_stack: FrameStack, conn: TTYPort)  -> Result<(), sqlx::Error>{
-    let mut ssids       : HashMap<NetworkId, SSID       > = HashMap::new();
-    let mut bssids      : HashMap<NetworkId, BSSID      > = HashMap::new();
-    let mut rssi_records: HashMap<Position , Vec<Record>> = HashMap::new();
+/// Everything the ESP32 sent over one capture
+#[derive(Default, Debug)]
+pub struct CaptureData {
+    pub ssids       : HashMap<NetworkId, SSID       >,
+    pub bssids      : HashMap<NetworkId, BSSID      >,
 
-    let mut frame_stack = frame_stack;
-    let mut conn = conn;
+    /// Channel, auth mode and such of every access point, under the network id of its SSID
+    pub networks    : HashMap<NetworkId, Vec<NetworkInfo>>,
+    pub rssi_records: HashMap<Position , Vec<Record>>,
+    pub pictures    : Vec<(Position, Vec<u8>)>,
+
+    /// Faults the ESP32 reported along the way, and how the capture ended up because of them
+    pub errors      : Vec<DeviceError>,
+    pub outcome     : CaptureOutcome,
+
+    /// Last position whose records all came in. An interrupted capture is resumed from there
+    pub last_recorded: Option<Position>,
+
+    /// Positions the ESP32 reported that didn't match where the host sent the rig
+    pub position_mismatches: Vec<PositionMismatch>
+}
+
+#[derive(Default, Debug, PartialEq, Clone, Copy)]
+pub enum CaptureOutcome {
+    #[default]
+    Complete,
+    Aborted,
+    Failed,
+
+    /// The link to the ESP32 was lost before the end of the transmission
+    Interrupted
+}
+
+impl CaptureOutcome {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            CaptureOutcome::Complete => "complete",
+            CaptureOutcome::Aborted  => "aborted",
+            CaptureOutcome::Failed   => "failed",
+            CaptureOutcome::Interrupted => "interrupted",
+        }
+    }
+}
+
+/// Collects frames until the ESP32 ends the transmission or the connection closes. Generic over the
+/// transport, so a recorded trace can be replayed through it as well as a live ESP32
+#[allow(dead_code)]
+pub fn run_capture<T: FrameTransport>(logger : &Arc<Mutex<Logger>>, frame_stack: &mut FrameStack, conn: &mut T) -> CaptureData {
+    run_capture_with_control(logger, frame_stack, conn, None)
+}
+
+/// Same as `run_capture`, taking orders to pause, resume or abort from the web thread along the way
+/// and answering its status requests
+pub fn run_capture_with_control<T: FrameTransport>(logger : &Arc<Mutex<Logger>>, frame_stack: &mut FrameStack, conn: &mut T, control: Option<(&ThreadReceiver, &ThreadSender)>) -> CaptureData {
+    let mut data = CaptureData::default();
+    capture_into(logger, frame_stack, conn, control, &mut data);
+
+    data
+}
+
+/// Picks an interrupted capture back up over a new link: handshakes again, then has the ESP32 start
+/// over from the last position it fully recorded. What it sends from then on is added to `data`.
+/// The handshake falls back on `baud_rates` like the first one did
+pub fn resume_capture<T: FrameTransport>(logger : &Arc<Mutex<Logger>>, frame_stack: &mut FrameStack, conn: &mut T, control: Option<(&ThreadReceiver, &ThreadSender)>, baud_rates: &[u32], data: &mut CaptureData) -> Result<(), FrameError> {
+    enter_session_state(logger, frame_stack, SessionState::Handshaking);
+    proc_tx_handshake_fallback(conn, frame_stack, logger.clone(), baud_rates)?;
+    enter_session_state(logger, frame_stack, SessionState::Configuring);
+
+    // That position is measured again, so what came from it before makes way
+    proc_tx_reset_at(conn, frame_stack, data.last_recorded.as_ref())?;
+    if let Some(start) = &data.last_recorded {
+        data.rssi_records.remove(start);
+    }
+    enter_session_state(logger, frame_stack, SessionState::Capturing);
+
+    if let Ok(mut handle) = logger.lock() {
+        handle.log(Severity::INFO, &format!("Resuming capture from {:?}", data.last_recorded));
+    }
+
+    data.outcome = CaptureOutcome::Complete;
+    capture_into(logger, frame_stack, conn, control, data);
+
+    Ok(())
+}
+
+fn capture_into<T: FrameTransport>(logger : &Arc<Mutex<Logger>>, frame_stack: &mut FrameStack, conn: &mut T, control: Option<(&ThreadReceiver, &ThreadSender)>, data: &mut CaptureData) {
+    let mut pictures = PictureAssembler::new();
+    let mut retries: HashMap<Position, u32> = HashMap::new();
+    let mut tracker  = PositionTracker::new(frame_stack.grid().cloned());
+
+    // A resumed capture starts where the last one left off rather than at the origin
+    if let Some(start) = &data.last_recorded {
+        tracker.command(start.clone());
+    }
+
+    // Heartbeats keep a paused ESP32 talking, only a short read gets us back around to the orders in time
+    if control.is_some() {
+        if let Err(e) = conn.set_read_timeout(IDLE_POLL_TIMEOUT) {
+            if let Ok(mut handle) = logger.lock() {
+                handle.log(Severity::ERROR, &format!("Failed to set read timeout with error '{}'", e));
+            }
+        }
+    }
+
+    // The frame stack stays borrowed by whatever it hands out, so the link is checked on from here
+    let link = frame_stack.link_monitor();
+
+    // Where the session goes once the capture is over. Anything short of the ESP32 or the host ending it fails it
+    let mut ended = SessionState::Finished;
+
+    // A paused ESP32 without heartbeats has nothing to say, its silence isn't held against it
+    let mut failed_reads = 0;
+    let mut paused = false;
+
+    // Where records are coming in from. It's only known to be done with once the next one starts
+    let mut in_progress: Option<Position> = None;
 
     loop {
+        // Re-send whatever the ESP32 hasn't acked in time
+        if let Err(e) = retx_expired_frames(frame_stack, conn) {
+            if let Ok(mut handle) = logger.lock() {
+                handle.log(Severity::ERROR, &format!("Failed to retransmit frames with error '{:?}'", e));
+            }
+        }
+
+        if let Err(e) = proc_tx_heartbeat(conn, frame_stack) {
+            if let Ok(mut handle) = logger.lock() {
+                handle.log(Severity::ERROR, &format!("Failed to send heartbeat with error '{:?}'", e));
+            }
+        }
+
+        // An aborted capture still takes in what the ESP32 sends before its EndOfTransmission. Firmware
+        // that can't be told to abort is stopped right away
+        let order = control.and_then(|(rx_thread, tx_thread)| handle_thread_msg(logger, rx_thread, tx_thread, true));
+        match order.and_then(|order| tx_capture_order(logger, frame_stack, conn, order)) {
+            Some(Cmd::Pause) => paused = true,
+            Some(Cmd::Resume) => paused = false,
+            Some(Cmd::Abort) => data.outcome = CaptureOutcome::Aborted,
+            Some(Cmd::EndOfTransmission) => {
+                data.outcome = CaptureOutcome::Aborted;
+                break;
+            },
+            _ => {}
+        }
     
-        // Rx a frame or log the error and loop back
-        let frame = match rx_frame_blocking(&mut frame_stack,&mut conn) {
-            Ok(frame) => frame,
+        // Rx a frame or log the error and loop back. Nothing more is coming once the connection is gone
+        let frame = match rx_frame_ref(frame_stack, conn) {
+            Ok(frame) => {
+                failed_reads = 0;
+                frame
+            },
+            Err(FrameError::ConnectionClosed) => {
+                if let Ok(mut handle) = logger.lock() {
+                    handle.log(Severity::WARNING, "Connection closed before the end of the transmission");
+                }
+                data.outcome = interrupted(data.outcome);
+                ended = SessionState::Disconnected;
+                break;
+            },
+            // No point waiting on frames from an ESP32 that stopped answering heartbeats
+            Err(_) if link.lock().is_ok_and(|link| link.state() == LinkState::Down) => {
+                if let Ok(mut handle) = logger.lock() {
+                    handle.log(Severity::WARNING, "ESP32 stopped answering before the end of the transmission");
+                }
+                data.outcome = interrupted(data.outcome);
+                ended = SessionState::Disconnected;
+                break;
+            },
+            // Nor on a line that keeps failing, or that went quiet for longer than any read would wait
+            Err(e) if (failed_reads + 1 >= MAX_FAILED_READS && matches!(e, FrameError::FailedToTransmitFrame))
+                || (!paused && link.lock().is_ok_and(|link| link.quiet().is_some_and(|quiet| quiet >= MAX_SILENCE))) => {
+                if let Ok(mut handle) = logger.lock() {
+                    handle.log(Severity::WARNING, &format!("Lost the ESP32 before the end of the transmission with error '{:?}'", e));
+                }
+                data.outcome = interrupted(data.outcome);
+                ended = SessionState::Disconnected;
+                break;
+            },
+            Err(FrameError::TransmissionTimedOut) => continue,
             Err(e) => {
+                    // Only the line failing counts against it, a garbled frame says the ESP32 is still there
+                    if matches!(e, FrameError::FailedToTransmitFrame) {
+                        failed_reads += 1;
+                    }
                     if let Ok(mut handle) = logger.lock() {
                         handle.log(Severity::ERROR, &format!("Failed to receive frame with error '{:?}'. Retrying in 50ms", e));
                         thread::sleep(Duration::from_millis(50));
@@ -77,50 +297,428 @@ fn capture_project_data(logger : &Arc<Mutex<Logger>>, project: model::types::Pro
                 }
         };
         
+        // Records and picture data are read in place, the rest is owned so the stack is free to answer it
+        let cmd = match frame.into_cmd() {
+            CmdRef::RecordRSSI { position, record_count: _, records } => {
+                track_position(logger, &mut tracker, data, &position);
+
+                // add records to tally
+                data.rssi_records.entry(position.clone()).or_default().extend(records);
+                move_on_to(data, &mut in_progress, position);
+                continue;
+            },
+            CmdRef::PictureChunk { position, offset, total_size, data: chunk } => {
+                track_position(logger, &mut tracker, data, &position);
+                match pictures.add_chunk(&position, offset, total_size, chunk) {
+                    Ok(Some(picture)) => data.pictures.push((position, picture)),
+                    Ok(None) => {},
+                    Err(e) => {
+                        if let Ok(mut handle) = logger.lock() {
+                            handle.log(Severity::ERROR, &format!("Dropped picture chunk at {:?} with error '{:?}'", position, e));
+                        }
+                    }
+                }
+                continue;
+            },
+            CmdRef::Owned(cmd) => cmd
+        };
+
         // Act depending of the frame type
-        match frame.get_cmd() {
-            Cmd::EndOfTransmission => break,
-            Cmd::AddBSSID     { id, bssid } => { bssids.insert(id.clone(), bssid.clone()); },
-            Cmd::AddSSID      { id, ssid   } => { ssids.insert (id.clone(), ssid.clone() ); }
-            Cmd::RequestAck   { frame_id: _  } => proc_rx_request_ack(&mut conn, &mut frame_stack, logger.clone()).unwrap(),
+        match cmd {
+            Cmd::EndOfTransmission => {
+                // Nothing more is coming for the last position either
+                if let Some(position) = in_progress.take() {
+                    data.last_recorded = Some(position);
+                }
+                break;
+            },
+            Cmd::AddBSSID     { id, bssid } => { data.bssids.insert(id, bssid); },
+            Cmd::AddSSID      { id, ssid   } => { data.ssids.insert (id, ssid ); }
+            Cmd::DeviceError  { error } => {
+                track_position(logger, &mut tracker, data, error.position());
+                let outcome = handle_device_error(logger, frame_stack, conn, &mut retries, &error);
+
+                // Without an outcome, the rig was sent back to measure the position again
+                if outcome.is_none() {
+                    tracker.command(error.position().clone());
+                }
+                data.errors.push(error);
+
+                if let Some(outcome) = outcome {
+                    if outcome == CaptureOutcome::Failed {
+                        ended = SessionState::Failed;
+                    }
+                    data.outcome = outcome;
+                    break;
+                }
+            },
+            Cmd::NetworkInfo  { id, info } => {
+                // A rescan of the same access point replaces what the last one said
+                let networks = data.networks.entry(id).or_default();
+                networks.retain(|network| network.bssid() != info.bssid());
+                networks.push(info);
+            },
+            Cmd::RecordBatch  { entries } => {
+                match add_record_batch(data, frame_stack.grid(), entries) {
+                    Ok(positions) => {
+                        for position in positions {
+                            track_position(logger, &mut tracker, data, &position);
+                            move_on_to(data, &mut in_progress, position);
+                        }
+                    },
+                    Err(e) => {
+                        if let Ok(mut handle) = logger.lock() {
+                            handle.log(Severity::ERROR, &format!("Dropped record batch with error '{:?}'", e));
+                        }
+                    }
+                }
+            },
+            Cmd::RequestPosition => tx_known_position(logger, frame_stack, conn, &mut tracker, data),
+            Cmd::RequestAck   { frame_id: _  } => {
+                if let Err(e) = proc_rx_request_ack(conn, frame_stack, logger.clone()) {
+                    if let Ok(mut handle) = logger.lock() {
+                        handle.log(Severity::ERROR, &format!("Failed to ack frames with error '{:?}'", e));
+                    }
+                }
+            },
+            Cmd::RequestRetransmit { frame_id_start, frame_id_end } => {
+                if let Err(e) = proc_rx_request_retransmit(conn, frame_stack, frame_id_start, frame_id_end, logger.clone()) {
+                    if let Ok(mut handle) = logger.lock() {
+                        handle.log(Severity::ERROR, &format!("Failed to serve retransmit request with error '{:?}'", e));
+                    }
+                }
+            },
             Cmd::TransmitLogs { logs } => { proc_rx_logs(&mut logger.clone(), &logs); },
-            Cmd::RecordRSSI   { position, record_count: _, records } => { 
-                // add records to tally
-                // if key doesn't exist, create with records, otherwise, append it to running record
-                rssi_records
-                    .entry(position.clone())
-                    .and_modify(|position_vec| position_vec.append(&mut records.clone()))
-                    .or_insert(records.clone());
-            }
             _ => {}
         }
     }
 
-    let contents = json::json!({
-        "records": rssi_records,
-        "ssids"  : ssids,
-        "bssids" : bssids
-    });
+    // The position the link dropped in the middle of is measured again on resume, what came from it so far would be doubled
+    if let Some(position) = in_progress {
+        if data.outcome == CaptureOutcome::Interrupted {
+            data.rssi_records.remove(&position);
+        }
+    }
+
+    if frame_stack.dropped_bytes() > 0 {
+        if let Ok(mut handle) = logger.lock() {
+            handle.log(Severity::WARNING, &format!("Dropped {} bytes of line noise while resynchronizing", frame_stack.dropped_bytes()));
+        }
+    }
+
+    if frame_stack.duplicate_frames() > 0 {
+        if let Ok(mut handle) = logger.lock() {
+            handle.log(Severity::INFO, &format!("Ignored {} duplicate frames", frame_stack.duplicate_frames()));
+        }
+    }
+
+    if frame_stack.rejected_frames() > 0 {
+        if let Ok(mut handle) = logger.lock() {
+            handle.log(Severity::WARNING, &format!("Ignored {} frames the ESP32 sent at the wrong point of the session", frame_stack.rejected_frames()));
+        }
+    }
+
+    if pictures.pending() > 0 {
+        if let Ok(mut handle) = logger.lock() {
+            handle.log(Severity::WARNING, &format!("{} pictures were left incomplete", pictures.pending()));
+        }
+    }
 
+    enter_session_state(logger, frame_stack, ended);
+}
+
+/// Records came in for `position`. The one they were coming in for before, if it's another, is done with
+fn move_on_to(data: &mut CaptureData, in_progress: &mut Option<Position>, position: Position) {
+    if in_progress.as_ref() != Some(&position) {
+        if let Some(done) = in_progress.replace(position) {
+            data.last_recorded = Some(done);
+        }
+    }
+}
 
-    // db::update_project(project, contents).await?;
+/// How a capture that lost its link ended. One the host was already stopping needs no resuming
+fn interrupted(outcome: CaptureOutcome) -> CaptureOutcome {
+    match outcome {
+        CaptureOutcome::Complete => CaptureOutcome::Interrupted,
+        outcome => outcome
+    }
+}
+
+/// Moves the session on to `next`. One that can't get there from where it is stays put, and it's logged
+fn enter_session_state(logger : &Arc<Mutex<Logger>>, frame_stack: &FrameStack, next: SessionState) {
+    let current = frame_stack.session_state();
+    if frame_stack.enter_session_state(next).is_ok() {
+        return;
+    }
+
+    if let Ok(mut handle) = logger.lock() {
+        let current = current.map_or("unknown", |state| state.as_str());
+        handle.log(Severity::ERROR, &format!("Illegal session transition from {} to {}", current, next.as_str()));
+    }
+}
+
+/// Checks a position the ESP32 reported against the tracker, keeping note of what doesn't match
+fn track_position(logger : &Arc<Mutex<Logger>>, tracker: &mut PositionTracker, data: &mut CaptureData, position: &Position) {
+    if let Some(mismatch) = tracker.report(position) {
+        if let Ok(mut handle) = logger.lock() {
+            handle.log(Severity::WARNING, &format!("ESP32 isn't where the host thinks it is: {:?}", mismatch));
+        }
+        data.position_mismatches.push(mismatch);
+    }
+}
+
+/// Answers a `RequestPosition`, usually from an ESP32 that came back from a brownout, by sending it to
+/// the last position the host knows of. It measures it again, so what it sent from there is dropped
+fn tx_known_position<T: FrameTransport>(logger : &Arc<Mutex<Logger>>, frame_stack: &mut FrameStack, conn: &mut T, tracker: &mut PositionTracker, data: &mut CaptureData) {
+    let position = match tracker.current() {
+        Some(position) => position.clone(),
+        None => {
+            if let Ok(mut handle) = logger.lock() {
+                handle.log(Severity::WARNING, "ESP32 asked for its position, but the host doesn't know it either");
+            }
+            return;
+        }
+    };
+
+    if let Ok(mut handle) = logger.lock() {
+        handle.log(Severity::INFO, &format!("ESP32 lost its position, sending it back to {:?}", position));
+    }
+
+    match tx_new_frame(Cmd::SetPosition { position: position.clone() }, frame_stack, conn) {
+        Ok(()) => {
+            data.rssi_records.remove(&position);
+            tracker.command(position);
+        },
+        Err(e) => {
+            if let Ok(mut handle) = logger.lock() {
+                handle.log(Severity::ERROR, &format!("Failed to send the ESP32 its position with error '{:?}'", e));
+            }
+        }
+    }
+}
+
+/// Passes an order from the web thread on to the ESP32. Returns what was sent for it, if anything.
+/// Firmware without capture control can't be paused, only stopped with an EndOfTransmission
+fn tx_capture_order<T: FrameTransport>(logger : &Arc<Mutex<Logger>>, frame_stack: &mut FrameStack, conn: &mut T, order: Message) -> Option<Cmd> {
+    let controllable = frame_stack.protocol().supports(CAP_CAPTURE_CONTROL);
+    let cmd = match order {
+        Message::PauseCapture  if controllable => Cmd::Pause,
+        Message::ResumeCapture if controllable => Cmd::Resume,
+        Message::AbortCapture  if controllable => Cmd::Abort,
+        Message::AbortCapture  => Cmd::EndOfTransmission,
+        Message::PauseCapture  |
+        Message::ResumeCapture => {
+            if let Ok(mut handle) = logger.lock() {
+                handle.log(Severity::WARNING, "ESP32 firmware can't pause or resume a capture, ignoring the order");
+            }
+            return None;
+        },
+        _ => return None
+    };
+
+    if let Ok(mut handle) = logger.lock() {
+        handle.log(Severity::INFO, &format!("Sending {:?} to the ESP32", cmd));
+    }
+
+    match tx_new_frame(cmd.clone(), frame_stack, conn) {
+        Ok(()) => Some(cmd),
+        Err(e) => {
+            if let Ok(mut handle) = logger.lock() {
+                handle.log(Severity::ERROR, &format!("Failed to send {:?} with error '{:?}'", cmd, e));
+            }
+            None
+        }
+    }
+}
+
+/// Has the ESP32 measure the position again or stops the capture, depending on the error. Returns how
+/// the capture ended if it's over. A position that keeps failing aborts the capture
+fn handle_device_error<T: FrameTransport>(logger : &Arc<Mutex<Logger>>, frame_stack: &mut FrameStack, conn: &mut T, retries: &mut HashMap<Position, u32>, error: &DeviceError) -> Option<CaptureOutcome> {
+    if let Ok(mut handle) = logger.lock() {
+        handle.log(Severity::ERROR, &format!("ESP32 reported {:?} at {:?}: {}", error.code(), error.position(), error.context()));
+    }
+
+    let position_retries = retries.entry(error.position().clone()).or_insert(0);
+    let (cmd, outcome) = match error.code().action() {
+        ErrorAction::RetryPosition if *position_retries < MAX_POSITION_RETRIES => {
+            *position_retries += 1;
+            (Cmd::SetPosition { position: error.position().clone() }, None)
+        },
+        ErrorAction::RetryPosition |
+        ErrorAction::Abort         => (Cmd::EndOfTransmission, Some(CaptureOutcome::Aborted)),
+        ErrorAction::FailProject   => (Cmd::EndOfTransmission, Some(CaptureOutcome::Failed )),
+    };
+
+    if let Err(e) = tx_new_frame(cmd, frame_stack, conn) {
+        if let Ok(mut handle) = logger.lock() {
+            handle.log(Severity::ERROR, &format!("Failed to answer the ESP32's error with error '{:?}'", e));
+        }
+    }
+
+    outcome
+}
+
+/// Adds the records of a `RecordBatch` to the tally, at the positions their steps land on. A batch
+/// with a step off the grid is dropped whole. Returns the positions, in the order they were measured
+fn add_record_batch(data: &mut CaptureData, grid: Option<&Grid>, entries: Vec<GridRecords>) -> Result<Vec<Position>, FrameError> {
+    let grid = grid.ok_or(FrameError::InvalidCommandSequence)?;
+    let positions = entries.iter()
+        .map(|entry| grid.position(entry.step()))
+        .collect::<Result<Vec<Position>, FrameError>>()?;
+
+    for (position, entry) in positions.iter().zip(entries) {
+        data.rssi_records.entry(position.clone()).or_default().extend(entry.into_records());
+    }
+
+    Ok(positions)
+}
+
+fn capture_project_data(logger : &Arc<Mutex<Logger>>, project: model::types::Project, config: &Config, status: &Arc<Mutex<Esp32Status>>, frame_stack: FrameStack, conn: Transport, control: (&ThreadReceiver, &ThreadSender, &CancellationToken))  -> Result<(), sqlx::Error>{
+    let mut frame_stack = frame_stack;
+    let mut conn = conn;
+    let (rx_thread, tx_thread, cancel) = control;
+    let control = (rx_thread, tx_thread);
+
+    let mut data = run_capture_with_control(logger, &mut frame_stack, &mut conn, Some(control));
+
+    // A wiggled cable doesn't cost what was measured, the capture is picked back up over a new link. A
+    // replayed trace would only play out the same way again, and a backend shutting down doesn't start over
+    let resumable = !matches!(config.esp32_transport(), TransportConfig::Replay { .. });
+    let mut attempts = 0;
+    while resumable && data.outcome == CaptureOutcome::Interrupted && attempts < MAX_RESUME_ATTEMPTS && !cancel.is_cancelled() {
+        attempts += 1;
+        if let Ok(mut handle) = logger.lock() {
+            handle.log(Severity::WARNING, &format!("Lost the link to the ESP32 mid-capture, reconnecting to resume it (attempt {}/{})", attempts, MAX_RESUME_ATTEMPTS));
+        }
+
+        // The old line goes first, a serial port can't be held twice
+        drop(conn);
+        conn = match acquire_port(logger, rx_thread, tx_thread, status, config, frame_stack.framing(), cancel) {
+            Some(conn) => conn,
+            None => break
+        };
+
+        // Frame ids start over on the new link, the session and the trace carry on
+        let mut resumed = config.esp32_arq().frame_stack();
+        resumed.set_link_monitor(config.esp32_link().monitor());
+        resumed.set_framing(frame_stack.framing());
+        resumed.set_session(frame_stack.session());
+        resumed.set_trace_recorder(frame_stack.trace_recorder().cloned());
+        frame_stack = resumed;
+        if let Ok(mut status) = status.lock() {
+            status.set_link(Some(frame_stack.link_monitor()));
+        }
+
+        if let Err(e) = resume_capture(logger, &mut frame_stack, &mut conn, Some(control), &config.esp32_transport().baud_rates(), &mut data) {
+            if let Ok(mut handle) = logger.lock() {
+                handle.log(Severity::ERROR, &format!("Failed to resume the capture with error '{:?}'", e));
+            }
+        }
+    }
+
+    if data.outcome == CaptureOutcome::Interrupted {
+        enter_session_state(logger, &frame_stack, SessionState::Failed);
+    }
+
+    // The capture runs on its own thread, outside of Rocket's runtime, so the db calls are driven to completion on one of its own
+    let runtime = match tokio::runtime::Builder::new_current_thread().enable_all().build() {
+        Ok(runtime) => runtime,
+        Err(e) => {
+            if let Ok(mut handle) = logger.lock() {
+                handle.log(Severity::ERROR, &format!("Failed to start a runtime to store the capture with error '{}'", e));
+            }
+            return Err(sqlx::Error::Io(e));
+        }
+    };
+
+    let mut image_ids: Vec<json::Value> = vec![];
+    for (position, picture) in &data.pictures {
+        if let Some(image_id) = store_picture(logger, &runtime, &project, image_ids.is_empty(), picture) {
+            image_ids.push(json::json!({ "position": position, "image_id": image_id }));
+        }
+    }
+
+    // A failed capture is kept for a look at what went wrong, marked so it isn't taken for a measurement
+    if data.outcome != CaptureOutcome::Complete {
+        if let Ok(mut handle) = logger.lock() {
+            handle.log(Severity::WARNING, &format!("Capture for project {} {}, with {} device errors reported", project.project_id(), data.outcome.as_str(), data.errors.len()));
+        }
+    }
+
+    let contents = capture_contents(&data, image_ids);
+    if let Err(e) = runtime.block_on(db::update_project(project, contents)) {
+        if let Ok(mut handle) = logger.lock() {
+            handle.log(Severity::ERROR, &format!("Failed to store the capture with error '{}'", e));
+        }
+        return Err(e);
+    }
 
     Ok(())
 }
 
-fn acquire_port(logger : &Arc<Mutex<Logger>>, rx_thread: &ThreadReceiver, tx_thread: &ThreadSender, port_name: &str) -> TTYPort {
+/// What's stored as the project data of a capture, `image_ids` being where its pictures went
+pub fn capture_contents(data: &CaptureData, image_ids: Vec<json::Value>) -> json::Value {
+    // Positions and network ids aren't strings, so they can't key a JSON object. Each entry carries its own instead
+    let records: Vec<json::Value> = data.rssi_records.iter().map(|(position, records)| json::json!({ "position": position, "records": records })).collect();
+    let ssids  : Vec<json::Value> = data.ssids       .iter().map(|(id, ssid  )| json::json!({ "id": id, "ssid" : ssid  })).collect();
+    let bssids : Vec<json::Value> = data.bssids      .iter().map(|(id, bssid )| json::json!({ "id": id, "bssid": bssid })).collect();
+    let networks: Vec<json::Value> = data.networks   .iter().map(|(id, infos )| json::json!({ "id": id, "info" : infos })).collect();
+
+    json::json!({
+        "records" : records,
+        "ssids"   : ssids,
+        "bssids"  : bssids,
+        "networks": networks,
+        "status"  : data.outcome.as_str(),
+        "errors"  : data.errors,
+        "pictures": image_ids
+    })
+}
+
+/// Stores a picture, and makes it the one shown for the project if it's the first. Returns its image id
+fn store_picture(logger : &Arc<Mutex<Logger>>, runtime: &tokio::runtime::Runtime, project: &model::types::Project, is_first: bool, picture: &[u8]) -> Option<i64> {
+    let result: Result<i64, sqlx::Error> = runtime.block_on(async {
+        let image_id = db::insert_image(picture).await?;
+        if is_first {
+            db::set_project_image(project, image_id).await?;
+        }
+
+        Ok(image_id)
+    });
+
+    match result {
+        Ok(image_id) => Some(image_id),
+        Err(e) => {
+            if let Ok(mut handle) = logger.lock() {
+                handle.log(Severity::ERROR, &format!("Failed to store picture with error '{}'", e));
+            }
+            None
+        }
+    }
+}
+
+/// Opens the line to the ESP32, trying again until it can. None if the backend shuts down first
+fn acquire_port(logger : &Arc<Mutex<Logger>>, rx_thread: &ThreadReceiver, tx_thread: &ThreadSender, status: &Arc<Mutex<Esp32Status>>, config: &Config, framing: Framing, cancel: &CancellationToken) -> Option<Transport> {
+    let transport = config.esp32_transport();
     loop {
-        let port = create_port_conn(&port_name);
+        if cancel.is_cancelled() {
+            return None;
+        }
+
+        let port = match transport {
+            TransportConfig::Discover { settings } => discover_esp32(logger, status, settings, framing, config.esp32_claimed_ports()),
+            _ => open_transport(transport, framing)
+        };
 
         // if any status requests come, state the backend is not ready
         handle_thread_msg(&logger, &rx_thread, &tx_thread, false);
 
         // either unwrap the port, or log the error and try again in 2 secs
         match port {
-            Ok(port) => return port,
+            Ok(port) => return Some(port),
             Err(err) => {
                 if let Ok(mut handle) = logger.lock() {
-                    handle.log(Severity::ERROR, &format!("Failed to bind to port {} with error {}. Retrying in 2s", &err, &port_name));
+                    handle.log(Severity::ERROR, &format!("Failed to bind to port {} with error {}. Retrying in 2s", transport, &err));
                     thread::sleep(Duration::from_secs(2));
                 }
             }
@@ -128,72 +726,255 @@ fn acquire_port(logger : &Arc<Mutex<Logger>>, rx_thread: &ThreadReceiver, tx_thr
     };
 }
 
-fn await_capture_order(logger : &Arc<Mutex<Logger>>, rx_thread: &ThreadReceiver, tx_thread: &ThreadSender) -> model::types::Project {
+/// Looks for the ESP32 on every serial port there is but those `claimed` by other rigs, starting with
+/// the one it was last found on. What turned up is left in `status` for the web API
+fn discover_esp32(logger : &Arc<Mutex<Logger>>, status: &Arc<Mutex<Esp32Status>>, settings: &SerialConfig, framing: Framing, claimed: &[PathBuf]) -> std::io::Result<Transport> {
+    let remembered = status.lock().ok().and_then(|status| status.port().map(Path::to_path_buf));
+    let (found, port) = discover_transport(settings, framing, remembered.as_deref(), claimed);
+
+    if let Ok(mut handle) = logger.lock() {
+        for port in &found {
+            handle.log(Severity::DEBUG, &format!("Probed {} for the ESP32: {}", port.path.display(), port.result.as_str()));
+        }
+    }
+
+    if let Ok(mut status) = status.lock() {
+        status.set_ports(found);
+    }
+
+    let (path, port) = port?;
+    if let Ok(mut handle) = logger.lock() {
+        handle.log(Severity::INFO, &format!("Found the ESP32 on {}", path.display()));
+    }
+    if let Ok(mut status) = status.lock() {
+        status.set_port(Some(path));
+    }
+
+    Ok(port)
+}
+
+/// Waits for the web thread to order a capture, keeping the link alive with heartbeats in the meantime.
+/// Without `listen`, as for a replayed trace, nothing is read: whatever comes next belongs to the capture.
+/// None if the backend shuts down first
+fn await_capture_order<T: FrameTransport>(logger : &Arc<Mutex<Logger>>, rx_thread: &ThreadReceiver, tx_thread: &ThreadSender, frame_stack: &mut FrameStack, conn: &mut T, listen: bool, cancel: &CancellationToken) -> Option<model::types::Project> {
+    let read_timeout = conn.read_timeout();
+    if let Err(e) = conn.set_read_timeout(IDLE_POLL_TIMEOUT) {
+        if let Ok(mut handle) = logger.lock() {
+            handle.log(Severity::ERROR, &format!("Failed to set read timeout with error '{}'", e));
+        }
+    }
+
     loop {
-        thread::sleep(Duration::from_millis(300));
+        if cancel.is_cancelled() {
+            return None;
+        }
 
-        // Process status requests
-        let msg = handle_thread_msg(&logger, &rx_thread, &tx_thread, false);    
+        if listen {
+            if let Err(e) = proc_tx_heartbeat(conn, frame_stack) {
+                if let Ok(mut handle) = logger.lock() {
+                    handle.log(Severity::ERROR, &format!("Failed to send heartbeat with error '{:?}'", e));
+                }
+            }
 
-        if let Some(msg) = msg {
-            match msg {
-                Message::StartCapture(project) => return project,
-                _ => {}
+            // Listening is what keeps the link up, heartbeats are taken in on the way. Nothing else is expected yet
+            match rx_frame(frame_stack, conn) {
+                Ok(frame) => {
+                    if let Ok(mut handle) = logger.lock() {
+                        handle.log(Severity::DEBUG, &format!("Ignored {:?} received before the capture", frame.get_cmd()));
+                    }
+                },
+                Err(FrameError::TransmissionTimedOut) => {},
+                Err(_) => thread::sleep(IDLE_POLL_TIMEOUT)
             }
         }
+        else {
+            thread::sleep(IDLE_POLL_TIMEOUT);
+        }
+
+        // Process status requests. Waiting on an order is what being ready is
+        let msg = handle_thread_msg(&logger, &rx_thread, &tx_thread, true);    
+
+        if let Some(Message::StartCapture(project)) = msg {
+            if let Err(e) = conn.set_read_timeout(read_timeout) {
+                if let Ok(mut handle) = logger.lock() {
+                    handle.log(Severity::ERROR, &format!("Failed to set read timeout with error '{}'", e));
+                }
+            }
+            return Some(project);
+        }
     }
 }
 
-pub fn launch_esp32_backend(logger : Arc<Mutex<Logger>>, rx_thread: ThreadReceiver, tx_thread: ThreadSender)-> Result<(), sqlx::Error>{ 
-    let config = crate::internal::config::load_config().unwrap_or_default();
+/// Starts a backend task for each of the rigs in `configs`, and returns the registry the web thread
+/// reaches them through and shuts them down with. Has to be called from within the runtime
+pub fn launch_esp32_backends(logger : Arc<Mutex<Logger>>, configs: Vec<Config>) -> RigRegistry {
+    let mut rigs = RigRegistry::new();
+
+    for config in configs {
+        let status = Arc::new(Mutex::new(Esp32Status::default()));
+        let (tx_web, rx_web) = mpsc::channel::<Message>();
+        let (tx_esp, rx_esp) = mpsc::channel::<Message>();
+
+        if !rigs.register(Rig::new(config.esp32_id(), tx_web, rx_esp, status.clone())) {
+            if let Ok(mut handle) = logger.lock() {
+                handle.log(Severity::ERROR, &format!("Rig {} is configured twice, only the first one is driven", config.esp32_id()));
+            }
+            continue;
+        }
+
+        if let Ok(mut handle) = logger.lock() {
+            handle.log(Severity::INFO, &format!("Starting backend for rig {} on {}", config.esp32_id(), config.esp32_transport()));
+        }
 
-    let port_name = config.esp32_port();
+        // The rig stays reachable after a capture, its backend starts over on a new session. Sessions block on
+        // the line between checks on the cancellation, so they get a thread of their own from the runtime
+        let logger = logger.clone();
+        let cancel = rigs.cancellation().clone();
+        rigs.add_session(tokio::task::spawn_blocking(move || while !cancel.is_cancelled() {
+            let result = run_esp32_backend(logger.clone(), status.clone(), &config, &rx_web, &tx_esp, &cancel);
+
+            // A replayed trace only plays out once
+            if matches!(config.esp32_transport(), TransportConfig::Replay { .. }) {
+                break;
+            }
+
+            if let Err(e) = result {
+                if let Ok(mut handle) = logger.lock() {
+                    handle.log(Severity::WARNING, &format!("Session with rig {} ended with error '{:?}'. Starting over in {}s", config.esp32_id(), e, SESSION_RESTART_DELAY.as_secs()));
+                }
+                thread::sleep(SESSION_RESTART_DELAY);
+            }
+        }));
+    }
+
+    rigs
+}
+
+/// Runs one capture with the ESP32 set up in `config`, from acquiring its port to telling Rocket it's done.
+/// Fails with what ended the session, when the ESP32 couldn't be brought to capture. Once `cancel` is
+/// cancelled, a session that hasn't started capturing yet ends where it is
+pub fn run_esp32_backend(logger : Arc<Mutex<Logger>>, status: Arc<Mutex<Esp32Status>>, config: &Config, rx_thread: &ThreadReceiver, tx_thread: &ThreadSender, cancel: &CancellationToken)-> Result<(), FrameError>{ 
+    let transport = config.esp32_transport();
+    let replay = matches!(transport, TransportConfig::Replay { .. });
+
+    // Traces hold frames as they were before going on the line, so a replay is read raw
+    let framing = if replay { Framing::Raw } else { config.esp32_framing() };
 
     // Try to acquire handle for the port
-    let mut conn = acquire_port(&logger, &rx_thread, &tx_thread, port_name);
+    let mut conn = match acquire_port(&logger, rx_thread, tx_thread, &status, config, framing, cancel) {
+        Some(conn) => conn,
+        None => return Ok(())
+    };
 
     // we've acquired the handle to the port, log it.
     if let Ok(mut handle) = logger.lock() {
-        handle.log(Severity::INFO, &format!("Acquired connection to port {}", &port_name));
+        handle.log(Severity::INFO, &format!("Acquired connection to port {}", transport));
     }
     
     // TODO: Remove assert in favor of error handling
     // Perform handshake with ESP32, we're ready to start the transmission
-    let mut frame_stack = FrameStack::new();
-    let mut result = proc_tx_handshake(&mut conn, &mut frame_stack, logger.clone());
-    while let Err(e) = result {
-        if let Ok(mut handle) = logger.lock() {
-            handle.log(Severity::INFO, &format!("Error on handshake = {:?}", e));
+    let mut frame_stack = config.esp32_arq().frame_stack();
+    frame_stack.set_link_monitor(config.esp32_link().monitor());
+    frame_stack.set_framing(framing);
+    frame_stack.set_session(Some(Arc::new(Mutex::new(SessionState::Disconnected))));
+    if let Ok(mut status) = status.lock() {
+        status.set_link(Some(frame_stack.link_monitor()));
+        status.set_session(frame_stack.session());
+    }
+
+    if let Some(path) = config.esp32_trace() {
+        match TraceRecorder::create(path) {
+            Ok(trace) => frame_stack.set_trace_recorder(Some(trace)),
+            Err(e) => {
+                if let Ok(mut handle) = logger.lock() {
+                    handle.log(Severity::ERROR, &format!("Failed to create trace {} with error '{}'. Not recording", path.display(), e));
+                }
+            }
         }
-        result = proc_tx_handshake(&mut conn, &mut frame_stack, logger.clone());
     }
 
+    enter_session_state(&logger, &frame_stack, SessionState::Handshaking);
+    let baud_rates = transport.baud_rates();
+    let mut attempts = 0;
+    let protocol = loop {
+        if cancel.is_cancelled() {
+            terminate_esp32_backend(logger, &status, tx_thread);
+            return Ok(());
+        }
+
+        attempts += 1;
+        let e = match proc_tx_handshake_fallback(&mut conn, &mut frame_stack, logger.clone(), &baud_rates) {
+            Ok(protocol) => break protocol,
+            Err(e) => e
+        };
+
+        if !is_retryable(&e) || attempts >= MAX_HANDSHAKE_ATTEMPTS {
+            return abandon_session(logger, &frame_stack, &status, tx_thread, "handshake", e);
+        }
+
+        if let Ok(mut handle) = logger.lock() {
+            match e {
+                // Retrying right away won't change the firmware, so give it time to be flashed
+                FrameError::UnsupportedProtocolVersion => {
+                    handle.log(Severity::ERROR, "ESP32 firmware speaks a protocol version this backend doesn't support. Retrying in 5s");
+                    thread::sleep(Duration::from_secs(5));
+                },
+                _ => handle.log(Severity::INFO, &format!("Error on handshake = {:?}", e))
+            }
+        }
+    };
+
     // log it lul
     if let Ok(mut handle) = logger.lock() {
-        handle.log(Severity::INFO, "Sucessful handshake with ESP32");
+        handle.log(Severity::INFO, &format!("Sucessful handshake with ESP32, protocol v{} with capabilities {:?}", protocol.version(), protocol.capability_names()));
     }
+
+    if let Ok(mut status) = status.lock() {
+        status.set_protocol(Some(protocol));
+    }
+    enter_session_state(&logger, &frame_stack, SessionState::Configuring);
     
 
     // wait for the order to start the capture. Comes asyncronously from the web thread
-    let user = await_capture_order(&logger, &rx_thread, &tx_thread);
+    // A replayed trace only plays out once, so it's not read from until then
+    let user = match await_capture_order(&logger, rx_thread, tx_thread, &mut frame_stack, &mut conn, !replay, cancel) {
+        Some(user) => user,
+        None => {
+            terminate_esp32_backend(logger, &status, tx_thread);
+            return Ok(());
+        }
+    };
 
     // Perform the reset of the connection. After its completion, the ESP32 will begin capture
     let mut result = proc_tx_reset    (&mut conn, &mut frame_stack);
+    let mut attempts = 1;
     while let Err(e) = result {
         if let Ok(mut handle) = logger.lock() {
             handle.log(Severity::INFO, &format!("Error on reset = {:?}", e));
         }
+
+        if !is_retryable(&e) || attempts >= MAX_RESET_ATTEMPTS {
+            return abandon_session(logger, &frame_stack, &status, tx_thread, "reset", e);
+        }
+        attempts += 1;
         result = proc_tx_reset    (&mut conn, &mut frame_stack);
     }
+    enter_session_state(&logger, &frame_stack, SessionState::Capturing);
 
 
     if let Ok(mut handle) = logger.lock() {
         handle.log(Severity::INFO, &format!("Starting capture proc"));
     }
-    let _ = capture_project_data(&logger, user, frame_stack, conn);
+    // Don't sit on a read for longer than a heartbeat interval, so a quiet ESP32 is noticed in time
+    if let Err(e) = conn.set_read_timeout(config.esp32_link().heartbeat_interval) {
+        if let Ok(mut handle) = logger.lock() {
+            handle.log(Severity::ERROR, &format!("Failed to set read timeout with error '{}'", e));
+        }
+    }
+    let _ = capture_project_data(&logger, user, config, &status, frame_stack, conn, (rx_thread, tx_thread, cancel));
     
 
-    terminate_esp32_backend(logger, tx_thread);
+    terminate_esp32_backend(logger, &status, tx_thread);
 
     Ok(())
 }
\ No newline at end of file
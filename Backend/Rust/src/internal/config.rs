@@ -1,50 +1,452 @@
-use std::{fs::File, io::Read, net::{IpAddr, Ipv4Addr}, str::FromStr};
+use std::{fmt, fs::File, io::Read, net::{IpAddr, Ipv4Addr, SocketAddr}, path::{Path, PathBuf}, str::FromStr, time::Duration};
 
 use rocket::serde::json;
 
+use crate::internal::frame_type::{FrameStack, DEFAULT_MAX_RETRIES, DEFAULT_RETRANSMIT_TIMEOUT, DEFAULT_WINDOW_SIZE};
+use crate::internal::link::{LinkMonitor, DEFAULT_HEARTBEAT_INTERVAL, DEFAULT_LIVENESS_TIMEOUT};
+use crate::internal::framing::Framing;
+use crate::internal::transport::READ_TIMEOUT;
+
+// what a lone ESP32 that isn't given an id goes by
+pub const DEFAULT_RIG_ID: &str = "esp32";
+
+// rates a USB serial bridge to the ESP32 can be set to
+const STANDARD_BAUD_RATES: [u32; 11] = [1200, 2400, 4800, 9600, 19200, 38400, 57600, 115200, 230400, 460800, 921600];
+
+
+/// Why the config couldn't be loaded, naming the key at fault
+#[derive(PartialEq, Debug, Clone)]
+pub enum ConfigError {
+    /// The file is there, but can't be read or isn't JSON
+    Unreadable,
+    MissingField(&'static str),
+    /// The key is set to something it can't be
+    InvalidField(&'static str),
+    /// Two rigs go by this id
+    DuplicateId(String),
+    /// Two rigs are configured on this serial port
+    DuplicatePort(String),
+    /// More than one rig looks for its ESP32 on every serial port, they'd race each other for the same one
+    MultipleDiscover
+}
+
+#[derive(PartialEq, Debug, Clone)]
+pub enum TransportConfig {
+    Serial { port   : String, settings: SerialConfig },
+    // the first serial port the ESP32 answers on
+    Discover { settings: SerialConfig },
+    Tcp    { address: SocketAddr },
+    // plays a recorded trace back instead of talking to an ESP32
+    Replay { trace  : PathBuf    },
+    // talks to a simulated ESP32, for running the backend without hardware. Only there for the tests
+    #[cfg(test)]
+    Simulated
+}
+
+impl fmt::Display for TransportConfig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TransportConfig::Serial { port, ..} => write!(f, "{}", port),
+            TransportConfig::Discover { .. }    => write!(f, "auto"),
+            TransportConfig::Tcp    { address } => write!(f, "tcp://{}", address),
+            TransportConfig::Replay { trace   } => write!(f, "replay://{}", trace.display()),
+            #[cfg(test)]
+            TransportConfig::Simulated            => write!(f, "simulated"),
+        }
+    }
+}
+
+impl TransportConfig {
+    /// How long a read waits for the ESP32 before it's given up on
+    pub fn read_timeout(&self) -> Duration {
+        match self {
+            TransportConfig::Serial { settings, .. } | TransportConfig::Discover { settings } => settings.read_timeout,
+            _ => READ_TIMEOUT
+        }
+    }
+
+    /// Rates to try the handshake at, in order. None for a line that has no speed to it
+    pub fn baud_rates(&self) -> Vec<u32> {
+        match self {
+            TransportConfig::Serial { settings, .. } | TransportConfig::Discover { settings } => settings.baud_rates(),
+            _ => Vec::new()
+        }
+    }
+}
+
+/// Line settings of the serial port to the ESP32
+#[derive(PartialEq, Debug, Clone)]
+pub struct SerialConfig {
+    pub baud_rate          : u32,
+
+    /// Tried in order when the ESP32 doesn't answer the handshake at `baud_rate`
+    pub fallback_baud_rates: Vec<u32>,
+    pub parity             : serial::Parity,
+    pub stop_bits          : serial::StopBits,
+    pub flow_control       : serial::FlowControl,
+    pub read_timeout       : Duration
+}
+
+impl SerialConfig {
+    /// Every rate the handshake is tried at, the configured one first
+    pub fn baud_rates(&self) -> Vec<u32> {
+        std::iter::once(self.baud_rate).chain(self.fallback_baud_rates.iter().copied()).collect()
+    }
+}
+
+impl Default for SerialConfig {
+    fn default() -> Self {
+        Self {
+            baud_rate          : 115200,
+            fallback_baud_rates: Vec::new(),
+            parity             : serial::ParityNone,
+            stop_bits          : serial::Stop1,
+            flow_control       : serial::FlowNone,
+            read_timeout       : READ_TIMEOUT
+        }
+    }
+}
+
+/// Tuning of the reliable delivery on top of the frame protocol
+#[derive(PartialEq, Debug, Clone)]
+pub struct ArqConfig {
+    pub window_size       : usize,
+    pub retransmit_timeout: Duration,
+    pub max_retries       : u32
+}
+
+impl ArqConfig {
+    pub fn frame_stack(&self) -> FrameStack {
+        FrameStack::with_window(self.window_size, self.retransmit_timeout, self.max_retries)
+    }
+}
+
+impl Default for ArqConfig {
+    fn default() -> Self {
+        Self {
+            window_size       : DEFAULT_WINDOW_SIZE,
+            retransmit_timeout: DEFAULT_RETRANSMIT_TIMEOUT,
+            max_retries       : DEFAULT_MAX_RETRIES
+        }
+    }
+}
+
+/// How often to send heartbeats, and how long the ESP32 can stay quiet before it's considered gone
+#[derive(PartialEq, Debug, Clone)]
+pub struct LinkConfig {
+    pub heartbeat_interval: Duration,
+    pub liveness_timeout  : Duration
+}
+
+impl LinkConfig {
+    pub fn monitor(&self) -> LinkMonitor {
+        LinkMonitor::new(self.heartbeat_interval, self.liveness_timeout)
+    }
+}
+
+impl Default for LinkConfig {
+    fn default() -> Self {
+        Self {
+            heartbeat_interval: DEFAULT_HEARTBEAT_INTERVAL,
+            liveness_timeout  : DEFAULT_LIVENESS_TIMEOUT
+        }
+    }
+}
 
 pub struct Config {
-    esp32_port  : String,
-    esp32_cam_ip: IpAddr
+    esp32_id       : String,
+    esp32_transport: TransportConfig,
+    esp32_arq      : ArqConfig,
+    esp32_link     : LinkConfig,
+    esp32_framing  : Framing,
+    esp32_trace    : Option<PathBuf>,
+    esp32_cam_ip   : IpAddr,
+
+    /// Serial ports the other rigs are configured on, left to them when looking for this one's ESP32
+    esp32_claimed_ports: Vec<PathBuf>
 }
 
 impl Config {
     pub fn esp32_cam_ip(&self) -> IpAddr {
         self.esp32_cam_ip
     }
-    
-    pub fn esp32_port(&self) -> &str {
-        &self.esp32_port
+
+    /// Which rig this ESP32 drives. No two configured rigs share one
+    pub fn esp32_id(&self) -> &str {
+        &self.esp32_id
+    }
+
+    pub fn esp32_transport(&self) -> &TransportConfig {
+        &self.esp32_transport
+    }
+
+    pub fn esp32_arq(&self) -> &ArqConfig {
+        &self.esp32_arq
+    }
+
+    pub fn esp32_link(&self) -> &LinkConfig {
+        &self.esp32_link
+    }
+
+    /// How frames are delimited on the line to the ESP32
+    pub fn esp32_framing(&self) -> Framing {
+        self.esp32_framing
+    }
+
+    /// Where to record the frames exchanged with the ESP32, if anywhere
+    pub fn esp32_trace(&self) -> Option<&Path> {
+        self.esp32_trace.as_deref()
+    }
+
+    pub fn esp32_claimed_ports(&self) -> &[PathBuf] {
+        &self.esp32_claimed_ports
     }
 }
 
 impl Default for Config {
     fn default() -> Self {
-        Self { esp32_cam_ip: IpAddr::V4(Ipv4Addr::new(0, 0, 0, 1)), esp32_port: String::from("/dev/ttyUSB0") }
+        Self {
+            esp32_cam_ip   : IpAddr::V4(Ipv4Addr::new(0, 0, 0, 1)),
+            esp32_id       : String::from(DEFAULT_RIG_ID),
+            esp32_transport: TransportConfig::Discover { settings: SerialConfig::default() },
+            esp32_arq      : ArqConfig::default(),
+            esp32_link     : LinkConfig::default(),
+            esp32_framing  : Framing::Raw,
+            esp32_trace    : None,
+            esp32_claimed_ports: Vec::new()
+        }
     }
 }
 
-pub fn load_config() -> Option<Config> {
-    let config = if let Ok(mut file) = File::open("res/config.json") {
-        let mut contents = String::new();
-        file.read_to_string(&mut contents).ok()?;
-        contents
-    } else {
-        String::from("{}")
+pub fn load_config() -> Result<Config, ConfigError> {
+    load_config_from(Path::new("res/config.json"))
+}
+
+/// The first rig's config, see `load_rig_configs_from`
+pub fn load_config_from(path: &Path) -> Result<Config, ConfigError> {
+    load_rig_configs_from(path)?.into_iter().next().ok_or(ConfigError::InvalidField("rigs"))
+}
+
+pub fn load_rig_configs() -> Result<Vec<Config>, ConfigError> {
+    load_rig_configs_from(Path::new("res/config.json"))
+}
+
+/// One config per rig listed under `esp32.rigs`, each taking what it doesn't set itself from `esp32`.
+/// Without the list, `esp32` is the only rig. Without the file, there's a single rig with the defaults
+pub fn load_rig_configs_from(path: &Path) -> Result<Vec<Config>, ConfigError> {
+    let config = match File::open(path) {
+        Ok(mut file) => {
+            let mut contents = String::new();
+            file.read_to_string(&mut contents).map_err(|_| ConfigError::Unreadable)?;
+            contents
+        },
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(vec![Config::default()]),
+        Err(_) => return Err(ConfigError::Unreadable)
     };
 
-    let config = json::from_str(&config).unwrap_or(json::json!({}));
-    let config = config.as_object()?;
-    let config = config.get("config")?.as_object()?;
+    let config: json::Value = json::from_str(&config).map_err(|_| ConfigError::Unreadable)?;
+    let config = config.as_object().ok_or(ConfigError::Unreadable)?;
+    let config = object(config, "config")?;
     
-    let esp32_cam = config.get("esp32_cam")?.as_object()?;
-    let esp32_cam_ip: &str = esp32_cam.get("ip")?.as_str()?;
+    let esp32_cam = object(config, "esp32_cam")?;
+    let esp32_cam_ip = esp32_cam.get("ip").ok_or(ConfigError::MissingField("ip"))?;
+    let esp32_cam_ip = esp32_cam_ip.as_str().and_then(|ip| Ipv4Addr::from_str(ip).ok()).ok_or(ConfigError::InvalidField("ip"))?;
+    let esp32_cam_ip = IpAddr::V4(esp32_cam_ip);
+
+    let esp32 = object(config, "esp32")?;
+    let rigs = match esp32.get("rigs") {
+        Some(rigs) => rigs.as_array().ok_or(ConfigError::InvalidField("rigs"))?.iter().map(|rig| {
+            let mut merged = esp32.clone();
+            merged.remove("rigs");
+            merged.extend(rig.as_object().ok_or(ConfigError::InvalidField("rigs"))?.clone());
+            Ok(merged)
+        }).collect::<Result<Vec<_>, ConfigError>>()?,
+        None => vec![esp32.clone()]
+    };
+
+    let mut configs = rigs.iter().map(|esp32| parse_esp32(esp32, esp32_cam_ip)).collect::<Result<Vec<Config>, ConfigError>>()?;
+
+    // every rig needs its own id to be picked by, and its own port to be driven over
+    let serial_port = |config: &Config| match config.esp32_transport() {
+        TransportConfig::Serial { port, .. } => Some(port.clone()),
+        _ => None
+    };
+    for (i, config) in configs.iter().enumerate() {
+        if configs[..i].iter().any(|other| other.esp32_id == config.esp32_id) {
+            return Err(ConfigError::DuplicateId(config.esp32_id.clone()));
+        }
+
+        if let Some(port) = serial_port(config).filter(|port| configs[..i].iter().any(|other| serial_port(other).as_ref() == Some(port))) {
+            return Err(ConfigError::DuplicatePort(port));
+        }
+    }
+
+    if configs.iter().filter(|config| matches!(config.esp32_transport(), TransportConfig::Discover { .. })).count() > 1 {
+        return Err(ConfigError::MultipleDiscover);
+    }
+
+    if configs.is_empty() {
+        return Err(ConfigError::InvalidField("rigs"));
+    }
+
+    // a rig looking for its ESP32 mustn't take the port of one that was told where its ESP32 is
+    let ports: Vec<PathBuf> = configs.iter().filter_map(serial_port).map(PathBuf::from).collect();
+    for config in configs.iter_mut().filter(|config| matches!(config.esp32_transport(), TransportConfig::Discover { .. })) {
+        config.esp32_claimed_ports = ports.clone();
+    }
 
-    let esp32 = config.get("esp32")?.as_object()?;
-    let esp32_port = esp32.get("port")?.as_str()?;
+    Ok(configs)
+}
 
-    Some(Config {
-        esp32_cam_ip: IpAddr::V4(Ipv4Addr::from_str(esp32_cam_ip).ok()?),
-        esp32_port  : esp32_port.to_string(),
+/// The section under `key`, which every config needs
+fn object<'a>(parent: &'a serde_json::Map<String, json::Value>, key: &'static str) -> Result<&'a serde_json::Map<String, json::Value>, ConfigError> {
+    parent.get(key).ok_or(ConfigError::MissingField(key))?.as_object().ok_or(ConfigError::InvalidField(key))
+}
+
+fn parse_esp32(esp32: &serde_json::Map<String, json::Value>, esp32_cam_ip: IpAddr) -> Result<Config, ConfigError> {
+    let esp32_id        = match esp32.get("id") {
+        Some(id) => id.as_str().filter(|id| !id.is_empty()).ok_or(ConfigError::InvalidField("id"))?.to_string(),
+        None => String::from(DEFAULT_RIG_ID)
+    };
+    let esp32_transport = parse_transport(esp32)?;
+    let esp32_arq       = parse_arq(esp32)?;
+    let esp32_link      = parse_link(esp32)?;
+    let esp32_framing   = match esp32.get("framing") {
+        Some(framing) => framing.as_str().and_then(Framing::from_name).ok_or(ConfigError::InvalidField("framing"))?,
+        None => Framing::Raw
+    };
+    let esp32_trace     = match esp32.get("record_trace") {
+        Some(path) => Some(PathBuf::from(path.as_str().ok_or(ConfigError::InvalidField("record_trace"))?)),
+        None => None
+    };
+
+    Ok(Config {
+        esp32_cam_ip,
+        esp32_id,
+        esp32_transport,
+        esp32_arq,
+        esp32_link,
+        esp32_framing,
+        esp32_trace,
+        esp32_claimed_ports: Vec::new(),
     })
-}
\ No newline at end of file
+}
+
+fn parse_arq(esp32: &serde_json::Map<String, json::Value>) -> Result<ArqConfig, ConfigError> {
+    let mut arq = ArqConfig::default();
+
+    // every key is optional, but one that's there has to make sense
+    if let Some(window_size) = esp32.get("window_size") {
+        arq.window_size = window_size.as_u64().filter(|size| *size > 0).ok_or(ConfigError::InvalidField("window_size"))? as usize;
+    }
+
+    if let Some(timeout) = esp32.get("retransmit_timeout_ms") {
+        arq.retransmit_timeout = Duration::from_millis(timeout.as_u64().ok_or(ConfigError::InvalidField("retransmit_timeout_ms"))?);
+    }
+
+    if let Some(max_retries) = esp32.get("max_retries") {
+        arq.max_retries = max_retries.as_u64().and_then(|retries| u32::try_from(retries).ok()).ok_or(ConfigError::InvalidField("max_retries"))?;
+    }
+
+    Ok(arq)
+}
+
+fn parse_link(esp32: &serde_json::Map<String, json::Value>) -> Result<LinkConfig, ConfigError> {
+    let mut link = LinkConfig::default();
+
+    if let Some(interval) = esp32.get("heartbeat_interval_ms") {
+        link.heartbeat_interval = Duration::from_millis(interval.as_u64().filter(|interval| *interval > 0).ok_or(ConfigError::InvalidField("heartbeat_interval_ms"))?);
+    }
+
+    if let Some(timeout) = esp32.get("liveness_timeout_ms") {
+        link.liveness_timeout = Duration::from_millis(timeout.as_u64().ok_or(ConfigError::InvalidField("liveness_timeout_ms"))?);
+    }
+
+    // a timeout within one interval would call the link down between two heartbeats
+    if link.liveness_timeout <= link.heartbeat_interval {
+        return Err(ConfigError::InvalidField("liveness_timeout_ms"));
+    }
+
+    Ok(link)
+}
+
+fn parse_serial(esp32: &serde_json::Map<String, json::Value>) -> Result<SerialConfig, ConfigError> {
+    let mut serial = SerialConfig::default();
+    let baud_rate = |rate: &json::Value| rate.as_u64().and_then(|rate| u32::try_from(rate).ok()).filter(|rate| STANDARD_BAUD_RATES.contains(rate));
+
+    if let Some(rate) = esp32.get("baud_rate") {
+        serial.baud_rate = baud_rate(rate).ok_or(ConfigError::InvalidField("baud_rate"))?;
+    }
+
+    if let Some(rates) = esp32.get("fallback_baud_rates") {
+        serial.fallback_baud_rates = rates.as_array()
+            .and_then(|rates| rates.iter().map(baud_rate).collect::<Option<Vec<u32>>>())
+            .ok_or(ConfigError::InvalidField("fallback_baud_rates"))?;
+    }
+
+    // the same rate twice would only make the handshake wait on it twice
+    let rates = serial.baud_rates();
+    if rates.iter().enumerate().any(|(i, rate)| rates[..i].contains(rate)) {
+        return Err(ConfigError::InvalidField("fallback_baud_rates"));
+    }
+
+    if let Some(parity) = esp32.get("parity") {
+        serial.parity = match parity.as_str() {
+            Some("none") => serial::ParityNone,
+            Some("odd" ) => serial::ParityOdd,
+            Some("even") => serial::ParityEven,
+            _ => return Err(ConfigError::InvalidField("parity"))
+        };
+    }
+
+    if let Some(stop_bits) = esp32.get("stop_bits") {
+        serial.stop_bits = match stop_bits.as_u64() {
+            Some(1) => serial::Stop1,
+            Some(2) => serial::Stop2,
+            _ => return Err(ConfigError::InvalidField("stop_bits"))
+        };
+    }
+
+    if let Some(flow_control) = esp32.get("flow_control") {
+        serial.flow_control = match flow_control.as_str() {
+            Some("none"    ) => serial::FlowNone,
+            Some("software") => serial::FlowSoftware,
+            Some("hardware") => serial::FlowHardware,
+            _ => return Err(ConfigError::InvalidField("flow_control"))
+        };
+    }
+
+    if let Some(timeout) = esp32.get("read_timeout_ms") {
+        serial.read_timeout = Duration::from_millis(timeout.as_u64().filter(|timeout| *timeout > 0).ok_or(ConfigError::InvalidField("read_timeout_ms"))?);
+    }
+
+    Ok(serial)
+}
+
+fn parse_transport(esp32: &serde_json::Map<String, json::Value>) -> Result<TransportConfig, ConfigError> {
+    // serial is the default, so configs written before the tcp transport existed keep working
+    let transport = esp32.get("transport").and_then(|transport| transport.as_str()).unwrap_or("serial");
+
+    match transport {
+        "serial" => {
+            // without a port, or with "auto", the ESP32 is looked for on every serial port there is
+            let settings = parse_serial(esp32)?;
+            match esp32.get("port").map(|port| port.as_str().ok_or(ConfigError::InvalidField("port"))).transpose()? {
+                Some(port) if port != "auto" => Ok(TransportConfig::Serial { port: port.to_string(), settings }),
+                _ => Ok(TransportConfig::Discover { settings })
+            }
+        },
+        "tcp" => {
+            let address = esp32.get("address").ok_or(ConfigError::MissingField("address"))?;
+            let address = address.as_str().and_then(|address| SocketAddr::from_str(address).ok()).ok_or(ConfigError::InvalidField("address"))?;
+            Ok(TransportConfig::Tcp { address })
+        },
+        #[cfg(test)]
+        "simulated" => Ok(TransportConfig::Simulated),
+        "replay" => {
+            let trace = esp32.get("trace").ok_or(ConfigError::MissingField("trace"))?;
+            Ok(TransportConfig::Replay { trace: PathBuf::from(trace.as_str().ok_or(ConfigError::InvalidField("trace"))?) })
+        },
+        _ => Err(ConfigError::InvalidField("transport"))
+    }
+}
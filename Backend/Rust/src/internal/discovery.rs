@@ -0,0 +1,124 @@
+// std crates
+use std::collections::HashSet;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+// own crates
+use crate::internal::framing::Framing;
+use crate::internal::procs::proc_tx_probe;
+use crate::internal::transport::FrameTransport;
+
+// long enough for an ESP32 to answer a SoT, short enough to get through a handful of ports quickly
+pub const PROBE_TIMEOUT: Duration = Duration::from_millis(500);
+
+// USB serial bridges show up as ttyUSB, the ESP32's own USB as ttyACM
+const PORT_PREFIXES: [&str; 2] = ["ttyUSB", "ttyACM"];
+
+/// What probing a port for the ESP32 turned up
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub enum ProbeResult {
+    /// Answered the StartOfTransmission
+    Answered,
+
+    /// Opened, but nothing answered at any baud rate
+    Silent,
+
+    /// Couldn't be opened, it's busy or we lack the rights to it
+    Unavailable
+}
+
+impl ProbeResult {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ProbeResult::Answered    => "answered",
+            ProbeResult::Silent      => "silent",
+            ProbeResult::Unavailable => "unavailable",
+        }
+    }
+}
+
+#[derive(PartialEq, Debug, Clone)]
+pub struct DiscoveredPort {
+    pub path  : PathBuf,
+    pub result: ProbeResult
+}
+
+/// Serial ports the ESP32 could be on, see `candidate_ports_in`
+pub fn candidate_ports() -> Vec<PathBuf> {
+    candidate_ports_in(Path::new("/dev"))
+}
+
+/// Serial ports under `dev` the ESP32 could be on. Links in `serial/by-id` come first, their names
+/// survive a replug. A device already listed by one of them isn't listed again by its tty
+pub fn candidate_ports_in(dev: &Path) -> Vec<PathBuf> {
+    let ttys = sorted_entries(dev).into_iter().filter(|path| {
+        path.file_name().and_then(|name| name.to_str()).is_some_and(|name| PORT_PREFIXES.iter().any(|prefix| name.starts_with(prefix)))
+    });
+
+    let mut devices = HashSet::new();
+    sorted_entries(&dev.join("serial/by-id")).into_iter()
+        .chain(ttys)
+        .filter(|path| devices.insert(fs::canonicalize(path).unwrap_or_else(|_| path.clone())))
+        .collect()
+}
+
+/// `candidates` without the `claimed` ports, however either names the device
+pub fn unclaimed_ports(candidates: Vec<PathBuf>, claimed: &[PathBuf]) -> Vec<PathBuf> {
+    let device = |path: &PathBuf| fs::canonicalize(path).unwrap_or_else(|_| path.clone());
+    let claimed: HashSet<PathBuf> = claimed.iter().map(device).collect();
+
+    candidates.into_iter().filter(|path| !claimed.contains(&device(path))).collect()
+}
+
+fn sorted_entries(dir: &Path) -> Vec<PathBuf> {
+    let mut entries: Vec<PathBuf> = match fs::read_dir(dir) {
+        Ok(entries) => entries.filter_map(|entry| entry.ok().map(|entry| entry.path())).collect(),
+        Err(_) => vec![]
+    };
+    entries.sort();
+
+    entries
+}
+
+/// Opens each of `candidates` with `open` and probes it at each of `baud_rates`, until one answers.
+/// `remembered` goes first if it's still around, the ESP32 is most likely where it was last time.
+/// Returns what turned up on every port probed, and the one that answered, left open at the rate it
+/// answered at
+pub fn discover_port<T, F>(candidates: &[PathBuf], remembered: Option<&Path>, baud_rates: &[u32], framing: Framing, mut open: F) -> (Vec<DiscoveredPort>, Option<(PathBuf, T)>)
+where
+    T: FrameTransport,
+    F: FnMut(&Path) -> io::Result<T>
+{
+    let first  = candidates.iter().filter(|path| Some(path.as_path()) == remembered);
+    let others = candidates.iter().filter(|path| Some(path.as_path()) != remembered);
+
+    let mut found = vec![];
+    for path in first.chain(others) {
+        let mut port = match open(path) {
+            Ok(port) => port,
+            Err(_) => {
+                found.push(DiscoveredPort { path: path.clone(), result: ProbeResult::Unavailable });
+                continue;
+            }
+        };
+
+        if probe(&mut port, baud_rates, framing) {
+            found.push(DiscoveredPort { path: path.clone(), result: ProbeResult::Answered });
+            return (found, Some((path.clone(), port)));
+        }
+        found.push(DiscoveredPort { path: path.clone(), result: ProbeResult::Silent });
+    }
+
+    (found, None)
+}
+
+fn probe<T: FrameTransport>(port: &mut T, baud_rates: &[u32], framing: Framing) -> bool {
+    // Nothing to switch to, the line stays at the rate it was opened at
+    if baud_rates.is_empty() {
+        return proc_tx_probe(port, framing, PROBE_TIMEOUT).is_ok();
+    }
+
+    baud_rates.iter().any(|baud_rate| port.set_baud_rate(*baud_rate).is_ok() && proc_tx_probe(port, framing, PROBE_TIMEOUT).is_ok())
+}
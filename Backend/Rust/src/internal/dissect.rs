@@ -0,0 +1,249 @@
+// The backend never needs frames explained to it, only the dissector binary and the tests use this
+#![allow(dead_code)]
+
+// std crates
+use std::fmt;
+
+// own crates
+use crate::internal::frame_type::*;
+use crate::internal::utils::{bytes_to_hex, hex_to_bytes};
+
+/// CRC16 found at the end of a frame, against the one its bytes should have
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub struct CrcStatus {
+    pub received: u16,
+    pub expected: u16
+}
+
+impl CrcStatus {
+    pub fn is_valid(&self) -> bool {
+        self.received == self.expected
+    }
+}
+
+/// Everything that can be told about a frame, even one that doesn't parse
+#[derive(PartialEq, Debug, Clone)]
+pub struct Dissection {
+    pub offset    : usize,
+    pub size      : usize,
+    pub cmd_nibble: u8,
+    pub name      : &'static str,
+    pub length    : u16,
+    pub frame_id  : u32,
+    pub cmd       : Result<Cmd, FrameError>,
+
+    /// None when the frame is cut short, or its length can't be right
+    pub crc       : Option<CrcStatus>
+}
+
+impl Dissection {
+    pub fn is_valid(&self) -> bool {
+        self.cmd.is_ok() && self.crc.is_some_and(|crc| crc.is_valid())
+    }
+}
+
+#[derive(PartialEq, Debug, Clone)]
+pub enum Dissected {
+    Frame  (Dissection),
+
+    /// Bytes that aren't part of any frame
+    Garbage{ offset: usize, bytes: Vec<u8> }
+}
+
+/// Name of the command a frame carries, told apart by its nibble and, for extended ones, its sub-opcode
+pub fn command_name(cmd_nibble: u8, body: &[u8]) -> &'static str {
+    match cmd_nibble {
+        0x0 => "StartOfTransmission",
+        0x1 => "Reset",
+        0x2 => "Ready",
+        0x3 => "RequestPosition",
+        0x4 => "Ack",
+        0x5 => "RequestRetransmit",
+        0x6 => "RequestAck",
+        0x7 => "AddSSID",
+        0x8 => "AddBSSID",
+        0x9 => "RecordRSSI",
+        0xA => "SetPosition",
+        0xB => "SetParams",
+        0xC => "TransmitPicture",
+        0xD => "TransmitLogs",
+        EXTENDED_CMD_NIBBLE => match body.first() {
+            Some(&SUB_OPCODE_FRAGMENT     ) => "Fragment",
+            Some(&SUB_OPCODE_PICTURE_CHUNK) => "PictureChunk",
+            Some(&SUB_OPCODE_HEARTBEAT    ) => "Heartbeat",
+            Some(&SUB_OPCODE_RECORD_BATCH ) => "RecordBatch",
+            Some(&SUB_OPCODE_NETWORK_INFO ) => "NetworkInfo",
+            Some(&SUB_OPCODE_DEVICE_ERROR ) => "DeviceError",
+            Some(&SUB_OPCODE_PAUSE        ) => "Pause",
+            Some(&SUB_OPCODE_RESUME       ) => "Resume",
+            Some(&SUB_OPCODE_ABORT        ) => "Abort",
+            _ => "Extended"
+        },
+        _ => "EndOfTransmission"
+    }
+}
+
+/// Dissects the frame at the start of `bytes`. Only fails if there isn't even a header to read
+pub fn dissect_frame(bytes: &[u8], offset: usize) -> Result<Dissection, FrameError> {
+    let (cmd_nibble, length, frame_length, frame_id) = Frame::parse_header(bytes)?;
+    let frame_length = usize::from(frame_length);
+
+    let body_end = bytes.len().min(frame_length - CHECKSUM_SIZE);
+    let name = command_name(cmd_nibble, &bytes[FRAME_HEADER_SIZE..body_end]);
+
+    let mut dissection = Dissection {
+        offset,
+        size: bytes.len().min(frame_length),
+        cmd_nibble,
+        name,
+        length,
+        frame_id,
+        cmd: Err(FrameError::NotEnoughBytes),
+        crc: None
+    };
+
+    if let Err(e) = Cmd::check_length(cmd_nibble, usize::from(length)) {
+        dissection.cmd = Err(e);
+        return Ok(dissection);
+    }
+
+    if bytes.len() < frame_length {
+        return Ok(dissection);
+    }
+
+    let checked = &bytes[..frame_length - CHECKSUM_SIZE];
+    dissection.crc = Some(CrcStatus {
+        received: u16::from_be_bytes([bytes[frame_length - 2], bytes[frame_length - 1]]),
+        expected: u16::from_be_bytes(Checksum::from_bytes(checked).as_bytes())
+    });
+    dissection.cmd = Cmd::parse(cmd_nibble, length, frame_length as u16, bytes);
+
+    Ok(dissection)
+}
+
+/// Dissects frames laid back to back, as in a hex dump of what should be whole frames. Stops at the
+/// first one whose end can't be told, what's left after it can't be trusted to start a frame
+pub fn dissect_frames(bytes: &[u8]) -> Vec<Dissected> {
+    let mut dissected = vec![];
+    let mut offset = 0;
+
+    while offset < bytes.len() {
+        let dissection = match dissect_frame(&bytes[offset..], offset) {
+            Ok(dissection) => dissection,
+            Err(_) => {
+                dissected.push(Dissected::Garbage { offset, bytes: bytes[offset..].to_vec() });
+                break;
+            }
+        };
+
+        let complete = dissection.crc.is_some();
+        offset += dissection.size;
+        dissected.push(Dissected::Frame(dissection));
+
+        if !complete {
+            break;
+        }
+    }
+
+    dissected
+}
+
+/// Dissects a raw capture of the line, resynchronizing like the backend does. A frame that got corrupted
+/// is still shown, as long as it ends before the next valid frame
+pub fn dissect_capture(bytes: &[u8]) -> Vec<Dissected> {
+    let mut dissected = vec![];
+    let mut offset = 0;
+
+    while offset < bytes.len() {
+        match Frame::scan(&bytes[offset..]) {
+            FrameScan::Found { skipped, consumed, .. } => {
+                dissect_skipped(&bytes[offset..offset + skipped], offset, false, &mut dissected);
+                offset += skipped;
+
+                if let Ok(dissection) = dissect_frame(&bytes[offset..offset + consumed], offset) {
+                    dissected.push(Dissected::Frame(dissection));
+                }
+                offset += consumed;
+            },
+            FrameScan::Incomplete { .. } => {
+                dissect_skipped(&bytes[offset..], offset, true, &mut dissected);
+                break;
+            }
+        }
+    }
+
+    dissected
+}
+
+fn dissect_skipped(bytes: &[u8], offset: usize, at_end: bool, dissected: &mut Vec<Dissected>) {
+    let mut garbage_start = 0;
+    let mut start = 0;
+
+    while start < bytes.len() {
+        // A frame cut short only makes sense at the very end of the capture
+        let dissection = dissect_frame(&bytes[start..], offset + start).ok()
+            .filter(|dissection| dissection.crc.is_some() || (at_end && dissection.cmd == Err(FrameError::NotEnoughBytes)));
+
+        match dissection {
+            Some(dissection) => {
+                if garbage_start < start {
+                    dissected.push(Dissected::Garbage { offset: offset + garbage_start, bytes: bytes[garbage_start..start].to_vec() });
+                }
+
+                start += dissection.size;
+                garbage_start = start;
+                dissected.push(Dissected::Frame(dissection));
+            },
+            None => start += 1
+        }
+    }
+
+    if garbage_start < bytes.len() {
+        dissected.push(Dissected::Garbage { offset: offset + garbage_start, bytes: bytes[garbage_start..].to_vec() });
+    }
+}
+
+/// Reads bytes written by hand or pasted from the tests: `20 00 00 00 00 01 BB C6`, `200000000001BBC6`
+/// or `[0x20, 0x00, 0x00, 0x00, 0x00, 0x01, 0xBB, 0xC6]` all work
+pub fn parse_hex_input(input: &str) -> Option<Vec<u8>> {
+    let mut bytes = vec![];
+
+    for token in input.split(|c: char| c == ',' || c == '[' || c == ']' || c.is_ascii_whitespace()) {
+        if token.is_empty() {
+            continue;
+        }
+
+        match token.strip_prefix("0x").or_else(|| token.strip_prefix("0X")) {
+            Some(byte) if (1..=2).contains(&byte.len()) => bytes.push(u8::from_str_radix(byte, 16).ok()?),
+            Some(_) => return None,
+            None => bytes.extend(hex_to_bytes(token)?)
+        }
+    }
+
+    Some(bytes)
+}
+
+impl fmt::Display for Dissection {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let crc = match self.crc {
+            Some(crc) if crc.is_valid() => format!("crc=0x{:04X} ok", crc.received),
+            Some(crc) => format!("crc=0x{:04X} BAD, expected 0x{:04X}", crc.received, crc.expected),
+            None => String::from("crc=missing")
+        };
+
+        writeln!(f, "[{:>6}] {:<19} (0x{:X}) id={:<10} len={:<4} {}", self.offset, self.name, self.cmd_nibble, self.frame_id, self.length, crc)?;
+        match &self.cmd {
+            Ok(cmd) => write!(f, "         {:?}", cmd),
+            Err(e) => write!(f, "         error: {:?}", e)
+        }
+    }
+}
+
+impl fmt::Display for Dissected {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Dissected::Frame(dissection) => write!(f, "{}", dissection),
+            Dissected::Garbage { offset, bytes } => write!(f, "[{:>6}] {} bytes that aren't a frame: {}", offset, bytes.len(), bytes_to_hex(bytes))
+        }
+    }
+}
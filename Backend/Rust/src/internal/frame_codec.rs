@@ -0,0 +1,103 @@
+// External crates
+use bytes::BytesMut;
+use rocket::futures::{SinkExt, StreamExt};
+use rocket::tokio::io::{AsyncRead, AsyncWrite};
+use tokio_util::codec::{Decoder, Encoder, Framed};
+
+// own crates
+use crate::internal::frame_ops::{next_rx_frame, tx_bytes, tx_done};
+use crate::internal::frame_type::*;
+
+/// Async counterpart of `frame_ops`' blocking reads and writes, for a session on any `AsyncRead + AsyncWrite`.
+/// Everything goes through the same `FrameStack`, so framing, the window, the session checks, reassembly
+/// and the trace work the same as on a blocking port
+pub struct FrameCodec {
+    frame_stack: FrameStack
+}
+
+pub type FramedConn<T> = Framed<T, FrameCodec>;
+
+impl FrameCodec {
+    pub fn new(frame_stack: FrameStack) -> FrameCodec {
+        FrameCodec { frame_stack }
+    }
+
+    pub fn framed<T: AsyncRead + AsyncWrite>(conn: T, frame_stack: FrameStack) -> FramedConn<T> {
+        Framed::new(conn, FrameCodec::new(frame_stack))
+    }
+
+    pub fn frame_stack(&self) -> &FrameStack {
+        &self.frame_stack
+    }
+
+    pub fn frame_stack_mut(&mut self) -> &mut FrameStack {
+        &mut self.frame_stack
+    }
+
+    pub fn into_frame_stack(self) -> FrameStack {
+        self.frame_stack
+    }
+}
+
+impl Decoder for FrameCodec {
+    type Item  = Frame;
+    type Error = FrameError;
+
+    fn decode(&mut self, src: &mut BytesMut) -> Result<Option<Frame>, FrameError> {
+        // What came in is unwrapped into the stack's own receive buffer, noise and partial frames are dealt with there
+        if !src.is_empty() {
+            self.frame_stack.push_rx_bytes(src);
+            src.clear();
+        }
+
+        // The frame handed out before is a copy, its bytes can go
+        self.frame_stack.release_rx_frame();
+        match next_rx_frame(&mut self.frame_stack)? {
+            Some(found) => found.lend(&mut self.frame_stack).map(|frame| Some(frame.into_frame())),
+            None => Ok(None)
+        }
+    }
+}
+
+impl Encoder<Frame> for FrameCodec {
+    type Error = FrameError;
+
+    fn encode(&mut self, frame: Frame, dst: &mut BytesMut) -> Result<(), FrameError> {
+        let (bytes, packet) = tx_bytes(&frame, &self.frame_stack)?;
+        dst.extend_from_slice(&packet);
+
+        // It's on the line with the next flush, and a flush that fails ends the stream. Until then it waits on its Ack
+        tx_done(frame, &bytes, &mut self.frame_stack);
+
+        Ok(())
+    }
+}
+
+/// Receives the next frame the window and the session let through, like `rx_frame`. A stream that
+/// ended has the connection closed
+pub async fn rx_frame_async<T: AsyncRead + AsyncWrite + Unpin>(conn: &mut FramedConn<T>) -> Result<Frame, FrameError> {
+    match conn.next().await {
+        Some(frame) => frame,
+        None => Err(FrameError::ConnectionClosed)
+    }
+}
+
+/// Sends a command as a new frame, fragmented the same way `tx_new_frame` does it
+pub async fn tx_new_frame_async<T: AsyncRead + AsyncWrite + Unpin>(cmd: Cmd, conn: &mut FramedConn<T>) -> Result<(), FrameError> {
+    let cmds = cmd.fragment()?;
+    let frame_stack = conn.codec().frame_stack();
+    if cmds.len() > 1 && !frame_stack.protocol().supports(CAP_FRAGMENTATION) {
+        return Err(FrameError::LengthValueOutOfRange);
+    }
+
+    if cmds.len() > 1 && cmds.len() > frame_stack.tx_window_free() {
+        return Err(FrameError::WindowFull);
+    }
+
+    for cmd in cmds {
+        let frame = Frame::from_cmd(cmd, conn.codec().frame_stack().curr_id())?;
+        conn.send(frame).await?;
+    }
+
+    Ok(())
+}
@@ -5,81 +5,202 @@ use std::io;
 // External crates
 use serial::prelude::*;
 use serial::unix::TTYPort;
-use std::time::Duration;
 
 // own crates
 pub use crate::internal::frame_type::*;
+use crate::internal::config::SerialConfig;
+use crate::internal::transport::FrameTransport;
+use crate::internal::trace::Direction;
 
+const RX_CHUNK_SIZE: usize = 256;
 
-pub fn create_port_conn(port_name: &str) -> io::Result<TTYPort> {
+
+/// Opens the serial port to the ESP32 with the line settings from the config
+pub fn create_port_conn(port_name: &str, settings: &SerialConfig) -> io::Result<TTYPort> {
     let mut port = serial::open(port_name)?;
-    if let Err(e) = port.set_timeout(Duration::from_secs(25)) {
-        dbg!("[DEBUG]Failed to set timeout with error ", e);
-    }
+    port.set_timeout(settings.read_timeout)?;
 
-    port.reconfigure(&|settings| {
-        settings.set_baud_rate   (serial::Baud115200)?;
-        settings.set_char_size   (serial::Bits8);
-        settings.set_parity      (serial::ParityNone);
-        settings.set_stop_bits   (serial::Stop1);
-        settings.set_flow_control(serial::FlowNone);
+    port.reconfigure(&|port_settings| {
+        port_settings.set_baud_rate   (serial::BaudRate::from_speed(settings.baud_rate as usize))?;
+        port_settings.set_char_size   (serial::Bits8);
+        port_settings.set_parity      (settings.parity);
+        port_settings.set_stop_bits   (settings.stop_bits);
+        port_settings.set_flow_control(settings.flow_control);
         Ok(())
     })?;
 
     Ok(port)
 }
 
-pub fn rx_frame<T: SerialPort>(frame_stack : &mut FrameStack, port: &mut T) -> Result<Frame, FrameError> {
-    let mut header: [u8; FRAME_HEADER_SIZE] = [0, 0, 0, 0, 0, 0];
+/// Where `rx_frame_ref` left the frame it's about to lend out
+pub(crate) enum RxFrame {
+    /// At the start of the receive buffer, this many bytes long
+    Buffered(usize),
+
+    /// Put back together from fragments, see `FrameStack::lend_reassembled`
+    Reassembled
+}
 
-    match port.read_exact(&mut header) {
-        Ok (_) => {},
-        Err(_) => {return Err(FrameError::TransmissionTimedOut);}
+impl RxFrame {
+    pub(crate) fn lend(self, frame_stack: &mut FrameStack) -> Result<FrameRef<'_>, FrameError> {
+        match self {
+            RxFrame::Buffered(consumed) => frame_stack.lend_rx_frame(consumed),
+            RxFrame::Reassembled        => frame_stack.lend_reassembled()
+        }
     }
+}
 
-    // We parse the header to see how many bytes we need to read
-    let (_, length, frame_length, _) = Frame::parse_header(&header)?;
+/// Receives the next frame the window and the session let through, see `rx_frame_ref`, as a copy of
+/// its own for callers that hold on to it past the next receive
+pub fn rx_frame<T: FrameTransport>(frame_stack : &mut FrameStack, port: &mut T) -> Result<Frame, FrameError> {
+    rx_frame_ref(frame_stack, port).map(FrameRef::into_frame)
+}
 
-    // We read the rest, and to avoid unnecesary copies, we also read the Checksum. We're gonna need it anyways
-    let mut buff = vec![0; length as usize + CHECKSUM_SIZE];
+/// Same as `rx_frame`, but the frame is read in place and borrowed from the stack: records and
+/// picture data aren't copied anywhere. It stays valid until the next receive on this stack
+pub fn rx_frame_ref<'a, T: FrameTransport>(frame_stack : &'a mut FrameStack, port: &mut T) -> Result<FrameRef<'a>, FrameError> {
+    rx_frame_in_place(frame_stack, port)?.lend(frame_stack)
+}
 
-    
-    match port.read_exact(&mut buff) {
-        Ok(_) => {
-            let mut complete_buff = Vec::with_capacity(frame_length as usize);
-            complete_buff.append(&mut header.to_vec());
-            complete_buff.append(&mut buff);
+fn rx_frame_in_place<T: FrameTransport>(frame_stack : &mut FrameStack, port: &mut T) -> Result<RxFrame, FrameError> {
+    let mut timed_out = false;
+    frame_stack.release_rx_frame();
 
-            let frame = Frame::parse(&complete_buff)?.0;
-            frame_stack.append_rx_frame(frame.clone());
-            Ok(frame)
-        },
+    loop {
+        if let Some(found) = next_rx_frame(frame_stack)? {
+            return Ok(found);
+        }
+
+        rx_more(frame_stack, port, &mut timed_out)?;
+    }
+}
+
+/// Takes the next frame the window and the session let through out of what's already in the receive
+/// buffer. None once it's gone through all of it without finding one, the rest is up to the caller to read
+pub(crate) fn next_rx_frame(frame_stack : &mut FrameStack) -> Result<Option<RxFrame>, FrameError> {
+    let session = frame_stack.session_state();
+
+    loop {
+        // Only what the window needs leaves the buffer here, the frame itself is parsed again once it's lent out
+        let found = match FrameRef::scan(frame_stack.rx_buffer()) {
+            FrameScan::Found { frame, skipped, consumed } => {
+                let frame_id = frame.get_frame_id();
+                let control = match frame.get_cmd() {
+                    CmdRef::Owned(cmd) if cmd.is_control() => Some(cmd.clone()),
+                    _ => None
+                };
+                let accepted = session.is_none_or(|state| state.accepts(frame.get_cmd()));
+                let fragment = match frame.get_cmd() {
+                    CmdRef::Owned(Cmd::Fragment { .. }) => Some(frame.into_frame()),
+                    _ => None
+                };
+
+                Some((frame_id, control, fragment, accepted, skipped, consumed))
+            },
+            FrameScan::Incomplete { skipped } => {
+                frame_stack.drop_rx_bytes(skipped);
+                None
+            }
+        };
+
+        if let Some((frame_id, control, fragment, accepted, skipped, consumed)) = found {
+            frame_stack.drop_rx_bytes(skipped);
+            frame_stack.record_rx_trace(consumed);
+            frame_stack.mark_heard();
+
+            // Heartbeats only tell us the remote is still there, though their id counts like any other, and
+            // duplicates were already handed out once. Wait for the next frame instead
+            let accepted_id = frame_stack.accept_rx_frame_id(frame_id, control.as_ref());
+            if control == Some(Cmd::Heartbeat) || !accepted_id {
+                frame_stack.rx_buffer().drain(..consumed);
+                continue;
+            }
+
+            // Frames the session doesn't take at this point still count towards the next Ack, they just aren't acted on
+            match fragment {
+                // Fragments are held back until the command they carry is whole
+                Some(fragment) => {
+                    frame_stack.rx_buffer().drain(..consumed);
+                    if !frame_stack.reassemble_in_place(fragment)? {
+                        continue;
+                    }
+
+                    let accepted = session.is_none_or(|state| frame_stack.lend_reassembled().is_ok_and(|frame| state.accepts(frame.get_cmd())));
+                    if accepted {
+                        return Ok(Some(RxFrame::Reassembled));
+                    }
+                    frame_stack.reject_rx_frame();
+                    continue;
+                },
+                None if accepted => return Ok(Some(RxFrame::Buffered(consumed))),
+                None => {
+                    frame_stack.rx_buffer().drain(..consumed);
+                    frame_stack.reject_rx_frame();
+                    continue;
+                }
+            }
+        }
+
+        return Ok(None);
+    }
+}
+
+/// Reads the next chunk into the receive buffer
+fn rx_more<T: FrameTransport>(frame_stack : &mut FrameStack, port: &mut T, timed_out: &mut bool) -> Result<(), FrameError> {
+    let mut chunk = [0u8; RX_CHUNK_SIZE];
+
+    if *timed_out {
+        return Err(FrameError::TransmissionTimedOut);
+    }
+
+    match port.read(&mut chunk) {
+        Ok(0) => return Err(FrameError::ConnectionClosed),
+        Ok(read) => frame_stack.push_rx_bytes(&chunk[..read]),
+        Err(e) if e.kind() == io::ErrorKind::Interrupted => {},
         Err(e) => {
-            print!("[DEBUG]Failed to read frame body with error {}", e);
-            panic!("[DEBUG]Failed to read frame body with error {}", e);
-        },
+            let e = FrameError::from(e);
+            if e != FrameError::TransmissionTimedOut || frame_stack.rx_buffer().is_empty() {
+                return Err(e);
+            }
+
+            // The line went quiet with bytes still pending. The first time, the rest of the frame may
+            // just be slow to come, so they're kept for the next receive
+            if !frame_stack.stall_rx() {
+                return Err(e);
+            }
+
+            // Nothing came since, they're either a stale partial frame or a header that only looked
+            // valid. Drop the first byte and give the rest one more scan
+            frame_stack.drop_rx_bytes(1);
+            *timed_out = true;
+        }
     }
 
+    Ok(())
 }
 
-pub fn rx_frame_blocking<T: SerialPort>(frame_stack : &mut FrameStack, port: &mut T) -> Result<Frame, FrameError> {
+pub fn rx_frame_blocking<T: FrameTransport>(frame_stack : &mut FrameStack, port: &mut T) -> Result<Frame, FrameError> {
 
-    
     match rx_frame(frame_stack, port) {
-        Ok (frame)  => return Ok(frame),
+        Ok (frame) => Ok(frame),
         Err(e) => {
             println!("[INFO ]Failed to read with error '{e:?}'");
-            return Err(e)
+            Err(e)
             //thread::sleep(Duration::from_millis(500));
         }
     }
 
 }
 
-pub fn rx_frame_blocking_expect<T: SerialPort>(frame_stack : &mut FrameStack, port: &mut T, expected_cmd_code : u8) -> Result<Frame, FrameError> {
+pub fn rx_frame_blocking_expect<T: FrameTransport>(frame_stack : &mut FrameStack, port: &mut T, expected_cmd_code : u8) -> Result<Frame, FrameError> {
     
     let frame = rx_frame_blocking(frame_stack, port)?;
 
+    // A remote that ran into trouble says so instead of answering
+    if let Cmd::DeviceError { error } = frame.get_cmd() {
+        return Err(FrameError::from(error));
+    }
+
     if frame.get_cmd().as_int()? != expected_cmd_code {
         return Err(FrameError::InvalidCommandSequence)
     }
@@ -88,28 +209,75 @@ pub fn rx_frame_blocking_expect<T: SerialPort>(frame_stack : &mut FrameStack, po
     
 }
 
-pub fn tx_new_frame<T: SerialPort>(cmd: Cmd, frame_stack : &mut FrameStack, port: &mut T) -> Result<(), FrameError> {
-    let frame = Frame::from_cmd(cmd, frame_stack.curr_id())?;
-    tx_frame_blocking(frame, frame_stack, port)
+/// Sends a command as a new frame. One too large for a frame goes out as consecutive fragments, if
+/// the remote can put them back together and the window has room for all of them, so it never goes
+/// out half sent
+pub fn tx_new_frame<T: FrameTransport>(cmd: Cmd, frame_stack : &mut FrameStack, port: &mut T) -> Result<(), FrameError> {
+    let cmds = cmd.fragment()?;
+    if cmds.len() > 1 && !frame_stack.protocol().supports(CAP_FRAGMENTATION) {
+        return Err(FrameError::LengthValueOutOfRange);
+    }
+
+    if cmds.len() > 1 && cmds.len() > frame_stack.tx_window_free() {
+        return Err(FrameError::WindowFull);
+    }
+
+    for cmd in cmds {
+        let frame = Frame::from_cmd(cmd, frame_stack.curr_id())?;
+        tx_frame_blocking(frame, frame_stack, port)?;
+    }
+
+    Ok(())
 }
 
-pub fn tx_frame_blocking<T: SerialPort>(frame: Frame, frame_stack : &mut FrameStack, port: &mut T) -> Result<(), FrameError> {
-    let bytes = frame.as_bytes()?;
-    match port.write_all(&bytes) {
+pub fn tx_frame_blocking<T: FrameTransport>(frame: Frame, frame_stack : &mut FrameStack, port: &mut T) -> Result<(), FrameError> {
+    let (bytes, packet) = tx_bytes(&frame, frame_stack)?;
+    match port.write_all(&packet) {
         Ok(_) => {
-            frame_stack.append_tx_frame(frame);
+            tx_done(frame, &bytes, frame_stack);
             Ok(())
         },
         Err(_) => Err(FrameError::FailedToTransmitFrame),
     }
 }
 
-pub fn retx_frame_blocking<T: SerialPort>(frame: Frame, port: &mut T) -> Result<(), FrameError> {
+/// The bytes of a new frame, and the packet they go on the line as. Only control frames go out on a full window
+pub(crate) fn tx_bytes(frame: &Frame, frame_stack : &FrameStack) -> Result<(Vec<u8>, Vec<u8>), FrameError> {
+    if !frame.get_cmd().is_control() && frame_stack.tx_window_full() {
+        return Err(FrameError::WindowFull);
+    }
+
+    let bytes = frame.as_bytes()?;
+    let packet = frame_stack.framing().encode(&bytes);
+    Ok((bytes, packet))
+}
+
+/// Takes note of a new frame having gone out, so it's traced and waits on its Ack
+pub(crate) fn tx_done(frame: Frame, bytes: &[u8], frame_stack : &mut FrameStack) {
+    frame_stack.record_trace(Direction::Tx, bytes);
+    frame_stack.mark_sent();
+    frame_stack.append_tx_frame(frame);
+}
+
+pub fn retx_frame_blocking<T: FrameTransport>(frame: Frame, frame_stack : &FrameStack, port: &mut T) -> Result<(), FrameError> {
     let bytes = frame.as_bytes()?;
-    match port.write_all(&bytes) {
+    match port.write_all(&frame_stack.framing().encode(&bytes)) {
         Ok(_) => {
+            frame_stack.record_trace(Direction::Tx, &bytes);
+            frame_stack.mark_sent();
             Ok(())
         },
         Err(_) => Err(FrameError::FailedToTransmitFrame),
     }
-}
\ No newline at end of file
+}
+
+/// Re-sends every frame whose Ack is overdue. Returns how many went out again
+pub fn retx_expired_frames<T: FrameTransport>(frame_stack : &mut FrameStack, port: &mut T) -> Result<usize, FrameError> {
+    let expired = frame_stack.expired_tx_frames()?;
+
+    for frame in &expired {
+        retx_frame_blocking(frame.clone(), frame_stack, port)?;
+    }
+
+    Ok(expired.len())
+}
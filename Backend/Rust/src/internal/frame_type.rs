@@ -1,15 +1,91 @@
 use std::str::{from_utf8, FromStr};
 use std::result::Result;
-use std::collections::VecDeque;
+use std::collections::{BTreeMap, VecDeque};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
 use rocket::serde::json;
 use serde::Serialize;
 use crate::internal::utils::*;
+use crate::internal::trace::{Direction, TraceRecorder};
+use crate::internal::link::{LinkMonitor, LinkState};
+use crate::internal::framing::Framing;
+use crate::internal::session::SessionState;
 
 extern crate rocket;
 
 static CRC_16 : crc::Crc<u16> = crc::Crc::<u16>::new(&crc::CRC_16_MODBUS);
 pub const FRAME_HEADER_SIZE: usize = 6;
 pub const CHECKSUM_SIZE: usize = 2;
+pub const MAX_BODY_SIZE: usize = 0x0FFF;
+
+/// Nibble shared by every extended command. The first byte of their body tells them apart
+pub const EXTENDED_CMD_NIBBLE: u8 = 0xE;
+pub const SUB_OPCODE_FRAGMENT: u8 = 0x01;
+pub const SUB_OPCODE_PICTURE_CHUNK: u8 = 0x04;
+pub const SUB_OPCODE_HEARTBEAT    : u8 = 0x05;
+pub const SUB_OPCODE_RECORD_BATCH : u8 = 0x06;
+pub const SUB_OPCODE_NETWORK_INFO : u8 = 0x07;
+pub const SUB_OPCODE_DEVICE_ERROR : u8 = 0x08;
+pub const SUB_OPCODE_PAUSE        : u8 = 0x09;
+pub const SUB_OPCODE_RESUME       : u8 = 0x0A;
+pub const SUB_OPCODE_ABORT        : u8 = 0x0B;
+
+/// Version of the protocol spoken by this backend, and the oldest one it still gets along with.
+/// Version 1 is the firmware that answers a StartOfTransmission with a bare Ack, and only knows the base commands
+pub const PROTOCOL_VERSION       : u8 = 2;
+pub const MIN_PROTOCOL_VERSION   : u8 = 1;
+pub const LEGACY_PROTOCOL_VERSION: u8 = 1;
+
+/// Capability bits exchanged in the StartOfTransmission and its Ack. Anything not set by both ends is left unused
+pub const CAP_FRAGMENTATION  : u32 = 1 << 0;
+pub const CAP_BINARY_PICTURES: u32 = 1 << 1;
+pub const CAP_HEARTBEAT      : u32 = 1 << 2;
+pub const CAP_RECORD_BATCHES: u32 = 1 << 3;
+pub const CAP_NETWORK_INFO  : u32 = 1 << 4;
+pub const CAP_DEVICE_ERRORS : u32 = 1 << 5;
+pub const CAP_CAPTURE_CONTROL: u32 = 1 << 6;
+
+pub const HOST_CAPABILITIES: u32 = CAP_FRAGMENTATION | CAP_BINARY_PICTURES | CAP_HEARTBEAT | CAP_RECORD_BATCHES | CAP_NETWORK_INFO |
+                                   CAP_DEVICE_ERRORS | CAP_CAPTURE_CONTROL;
+const CAPABILITY_NAMES: [(u32, &str); 7] = [
+    (CAP_FRAGMENTATION  , "fragmentation"  ),
+    (CAP_BINARY_PICTURES, "binary_pictures"),
+    (CAP_HEARTBEAT      , "heartbeat"      ),
+    (CAP_RECORD_BATCHES , "record_batches" ),
+    (CAP_NETWORK_INFO   , "network_info"   ),
+    (CAP_DEVICE_ERRORS  , "device_errors"  ),
+    (CAP_CAPTURE_CONTROL, "capture_control"),
+];
+
+// version, min version and capabilities. Carried by a StartOfTransmission, and after the frame id by its Ack
+const PROTOCOL_INFO_SIZE: usize = 6;
+
+// sub-opcode, network id, BSSID, channel, secondary channel, auth mode and flags
+const NETWORK_INFO_SIZE: usize = 15;
+const NETWORK_INFO_HIDDEN_FLAG: u8 = 0x01;
+
+// sub-opcode, error code and position, followed by the context
+const DEVICE_ERROR_HEADER_SIZE: usize = 10;
+
+// sub-opcode, position, offset and total size
+const PICTURE_CHUNK_HEADER_SIZE: usize = 17;
+pub const MAX_PICTURE_CHUNK_SIZE: usize = MAX_BODY_SIZE - PICTURE_CHUNK_HEADER_SIZE;
+
+// sub-opcode, cmd nibble and sequence
+const FRAGMENT_HEADER_SIZE: usize = 4;
+const FRAGMENT_LAST_FLAG  : u16   = 0x8000;
+pub const MAX_FRAGMENT_PAYLOAD: usize = MAX_BODY_SIZE - FRAGMENT_HEADER_SIZE;
+
+// A sender that gave up halfway through a command leaves its fragments behind. Keep only a few around
+const MAX_PARTIAL_CMDS: usize = 4;
+
+// Fragments one command may span, so a partial command never holds more than this many full frames.
+// Far more than the largest logs the firmware sends
+pub const MAX_FRAGMENTS: usize = 64;
+
+pub const DEFAULT_WINDOW_SIZE       : usize    = 32;
+pub const DEFAULT_RETRANSMIT_TIMEOUT: Duration = Duration::from_secs(2);
+pub const DEFAULT_MAX_RETRIES       : u32      = 5;
 
 #[derive(PartialEq, Debug, Clone, Serialize)]
 pub struct BSSID {
@@ -23,6 +99,88 @@ pub struct SSID {
 }
 
 
+/// Channel next to the primary one that a 40 MHz network also takes, as the ESP32 reports it
+#[derive(PartialEq, Debug, Clone, Copy, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SecondaryChannel {
+    None  = 0,
+    Above = 1,
+    Below = 2
+}
+
+/// Told apart by channel number: 1 to 14 are 2.4 GHz, 32 to 177 are 5 GHz
+#[derive(PartialEq, Debug, Clone, Copy, Serialize)]
+pub enum Band {
+    #[serde(rename = "2.4GHz")]
+    Band2G4,
+    #[serde(rename = "5GHz")]
+    Band5G
+}
+
+/// How a network authenticates, numbered like the ESP32's `wifi_auth_mode_t`. Modes newer firmware
+/// knows and we don't are kept as their number
+#[derive(PartialEq, Debug, Clone, Copy, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AuthMode {
+    Open,
+    Wep,
+    WpaPsk,
+    Wpa2Psk,
+    WpaWpa2Psk,
+    Wpa2Enterprise,
+    Wpa3Psk,
+    Wpa2Wpa3Psk,
+    WapiPsk,
+    Owe,
+    Other(u8)
+}
+
+/// What a scan tells about one access point besides its name and signal
+#[derive(PartialEq, Debug, Clone, Serialize)]
+pub struct NetworkInfo {
+    bssid            : BSSID,
+    channel          : u8,
+    band             : Band,
+    secondary_channel: SecondaryChannel,
+    auth_mode        : AuthMode,
+    hidden           : bool
+}
+
+/// Faults the ESP32 reports, numbered the way the firmware does. Codes newer firmware knows and we
+/// don't are kept as their number
+#[derive(PartialEq, Debug, Clone, Copy, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DeviceErrorCode {
+    /// A stepper didn't reach the position it was sent to
+    StepperStall,
+    /// The accelerometer or the WiFi radio didn't come up
+    SensorInitFailed,
+    /// A WiFi scan failed or came back empty handed
+    ScanFailed,
+    /// Supply voltage dropped too low to keep the steppers going
+    LowPower,
+    Other(u8)
+}
+
+/// What the backend does about a `DeviceError`
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub enum ErrorAction {
+    /// Sends the ESP32 back to the position to measure it again
+    RetryPosition,
+    /// Stops the capture, keeping everything that came in so far
+    Abort,
+    /// Stops the capture, nothing that came in can be trusted
+    FailProject
+}
+
+/// A fault the ESP32 ran into, where it was at the time and whatever it had to say about it
+#[derive(PartialEq, Debug, Clone, Serialize)]
+pub struct DeviceError {
+    code    : DeviceErrorCode,
+    position: Position,
+    context : String
+}
+
 #[derive(PartialEq, Debug, Clone)]
 pub struct Checksum {
     checksum: u16
@@ -50,21 +208,49 @@ pub struct Record {
     rssi       : RSSI
 }
 
+#[derive(PartialEq, Debug, Clone, Serialize)]
+pub struct ProtocolInfo {
+    version     : u8,
+    min_version : u8,
+    capabilities: u32
+}
+
 #[derive(PartialEq, Debug, Clone)]
 pub struct StepSize {
     pitch_step: u32,
     yaw_step  : u32
 }
 
+/// The positions a capture can visit, set by `SetParams`: its starting position and every step away from it
+#[derive(PartialEq, Debug, Clone)]
+pub struct Grid {
+    origin   : Position,
+    step_size: StepSize
+}
+
+/// A position on the `Grid`, counted in steps from its origin
+#[derive(PartialEq, Debug, Clone, Copy, Default)]
+pub struct GridStep {
+    pitch: i64,
+    yaw  : i64
+}
+
+/// Records taken at one position of a `RecordBatch`
+#[derive(PartialEq, Debug, Clone)]
+pub struct GridRecords {
+    step   : GridStep,
+    records: Vec<Record>
+}
+
 
 #[derive(PartialEq, Debug, Clone)]
 #[repr(u8)]
 pub enum Cmd {
-    StartOfTransmission = 0,
+    StartOfTransmission{info: Option<ProtocolInfo>                                        } = 0,
     Reset            = 1,
     Ready            = 2,
     RequestPosition  = 3,
-    Ack              {frame_id: u32                                                       } = 4,
+    Ack              {frame_id: u32, info: Option<ProtocolInfo>                           } = 4,
     RequestRetransmit{frame_id_start: u32, frame_id_end: u32                              } = 5,
     RequestAck       {frame_id: u32                                                       } = 6,
     AddSSID          {id: NetworkId, ssid: SSID                                           } = 7,
@@ -75,14 +261,56 @@ pub enum Cmd {
     TransmitPicture  {position: Position , body      : json::Value                        } = 12,
     TransmitLogs     {logs    : json::Value                                               } = 13,
 
-    EndOfTransmission = 15
+    EndOfTransmission = 15,
+
+    // Extended commands, sent as nibble 0xE with a sub-opcode
+    Fragment         {cmd_nibble: u8, sequence: u16, last: bool, payload: Vec<u8>         },
+    PictureChunk     {position: Position, offset: u32, total_size: u32, data: Vec<u8>      },
+    Heartbeat,
+
+    /// Same as `RecordRSSI` for several positions at once, in a fraction of the bytes. Positions are
+    /// sent as steps on the `Grid` from the one before, network ids as varints
+    RecordBatch      {entries: Vec<GridRecords>                                           },
+    NetworkInfo      {id: NetworkId, info: NetworkInfo                                    },
+    DeviceError      {error: DeviceError                                                  },
+
+    /// Sent to the ESP32 while it captures. It stays where it is while paused, and an abort has it
+    /// send what it has left before its EndOfTransmission
+    Pause,
+    Resume,
+    Abort
 }
 
 #[derive(PartialEq, Debug, Clone)]
 pub struct Frame {
     cmd: Cmd,
     frame_id: u32,
-    checksum: Checksum
+    /// `None` once put back together from fragments, it never went over the line as one frame
+    checksum: Option<Checksum>
+}
+
+/// Records of a `RecordRSSI` read straight from the frame's body, one at a time
+#[derive(PartialEq, Debug, Clone)]
+pub struct RecordIter<'a> {
+    bytes: &'a [u8]
+}
+
+/// A command read in place. The ones sent at a high rate borrow their payload from the receive buffer,
+/// everything else is small enough to just be owned
+#[derive(PartialEq, Debug, Clone)]
+pub enum CmdRef<'a> {
+    RecordRSSI  {position: Position, record_count: u32, records: RecordIter<'a>         },
+    PictureChunk{position: Position, offset: u32, total_size: u32, data: &'a [u8]       },
+    Owned       (Cmd)
+}
+
+/// A frame read in place, see `CmdRef`
+#[derive(PartialEq, Debug, Clone)]
+pub struct FrameRef<'a> {
+    cmd: CmdRef<'a>,
+    frame_id: u32,
+    /// Same as `Frame::checksum`
+    checksum: Option<Checksum>
 }
 
 
@@ -100,36 +328,252 @@ pub enum FrameError {
     TransmissionTimedOut,
     ValueOutOfRange,
     InvalidUTF8,
-    InvalidJson
+    InvalidJson,
+    UnsupportedProtocolVersion,
+    ConnectionClosed,
+    WindowFull,
+    RetransmitLimitReached,
+    FrameEvicted,
+
+    /// The remote reported a fault of its own instead of answering
+    DeviceFault(DeviceErrorCode)
+}
+
+impl From<std::io::Error> for FrameError {
+    fn from(error: std::io::Error) -> Self {
+        match error.kind() {
+            std::io::ErrorKind::TimedOut      |
+            std::io::ErrorKind::WouldBlock    => FrameError::TransmissionTimedOut,
+            std::io::ErrorKind::UnexpectedEof |
+            std::io::ErrorKind::BrokenPipe    |
+            std::io::ErrorKind::ConnectionReset   |
+            std::io::ErrorKind::ConnectionAborted => FrameError::ConnectionClosed,
+            _ => FrameError::FailedToTransmitFrame
+        }
+    }
+}
+
+/// Result of looking for the next frame in a byte stream that may hold garbage
+#[derive(PartialEq, Debug, Clone)]
+pub enum FrameScan<F = Frame> {
+    /// A valid frame starts `skipped` bytes in, and is `consumed` bytes long
+    Found     { frame: F, skipped: usize, consumed: usize },
+
+    /// No complete frame yet. The first `skipped` bytes can't be the start of one and can be thrown away
+    Incomplete{ skipped: usize }
+}
+
+/// Fragments of a command too large for one frame, waiting on the rest of them
+#[derive(PartialEq, Debug, Clone)]
+struct PartialCmd {
+    cmd_nibble: u8,
+    fragments : BTreeMap<u16, Vec<u8>>,
+    last      : Option<u16>,
+
+    /// When its first fragment came in
+    started_at: Instant
+}
+
+/// Frame sent to the remote and still waiting on its Ack
+#[derive(PartialEq, Debug, Clone)]
+struct PendingFrame {
+    frame  : Frame,
+    sent_at: Instant,
+    retries: u32
 }
 
 pub struct FrameStack {
     local_frame_id: u32,
-    tx_frame_queue: VecDeque<(u32, Frame)>,
-    rx_frame_queue: VecDeque<(u32, Frame)>,
+    tx_frame_queue: VecDeque<PendingFrame>,
+
+    /// Ids of the frames received since the last Ack, and whether each was a control frame
+    rx_frame_queue: VecDeque<(u32, bool)>,
+    rx_fragments  : BTreeMap<u32, PartialCmd>,
+
+    /// Last command put back together from fragments, until it's lent out: its nibble, frame id and body
+    rx_reassembled: Option<(u8, u32, Vec<u8>)>,
 
     remote_ackd_frame_id: u32,
+    protocol            : ProtocolInfo,
+
+    /// Grid of the capture under way, needed to tell where the records of a `RecordBatch` were taken
+    grid                : Option<Grid>,
+
+    window_size       : usize,
+    retransmit_timeout: Duration,
+    max_retries       : u32,
+    duplicate_frames  : usize,
+
+    /// Frames thrown away for coming at a point of the session where the remote can't send them
+    rejected_frames   : usize,
+
+    rx_buffer    : Vec<u8>,
+    dropped_bytes: usize,
+
+    /// Whether a read timed out with bytes pending and nothing has come in since
+    rx_stalled   : bool,
+
+    /// Bytes read off a delimited line whose packet hasn't ended yet. Decoded frames move on to `rx_buffer`
+    rx_packet    : Vec<u8>,
+    framing      : Framing,
+
+    /// Bytes at the start of `rx_buffer` that belong to the frame last lent out
+    rx_lent      : usize,
+
+    /// Shared with whoever reports on the link, so it can tell how the remote is doing at any time
+    link : Arc<Mutex<LinkMonitor>>,
+
+    /// Shared with the web API the same way. Without one, any command is taken at any time
+    session: Option<Arc<Mutex<SessionState>>>,
+    trace: Option<TraceRecorder>,
 }
 
 impl FrameStack {
     pub fn new() -> FrameStack {
+        FrameStack::with_window(DEFAULT_WINDOW_SIZE, DEFAULT_RETRANSMIT_TIMEOUT, DEFAULT_MAX_RETRIES)
+    }
+
+    pub fn with_window(window_size: usize, retransmit_timeout: Duration, max_retries: u32) -> FrameStack {
         FrameStack { 
             local_frame_id: 0,
-            tx_frame_queue: VecDeque::new(),
-            rx_frame_queue: VecDeque::new(),
-            remote_ackd_frame_id: 0
+            tx_frame_queue: VecDeque::with_capacity(window_size),
+            rx_frame_queue: VecDeque::with_capacity(window_size),
+            rx_fragments: BTreeMap::new(),
+            rx_reassembled: None,
+            remote_ackd_frame_id: 0,
+            protocol: ProtocolInfo::host(),
+            grid: None,
+            window_size,
+            retransmit_timeout,
+            max_retries,
+            duplicate_frames: 0,
+            rejected_frames: 0,
+            rx_buffer: Vec::new(),
+            dropped_bytes: 0,
+            rx_stalled: false,
+            rx_packet: Vec::new(),
+            framing: Framing::Raw,
+            rx_lent: 0,
+            link: Arc::new(Mutex::new(LinkMonitor::default())),
+            session: None,
+            trace: None
         }
     }
 
+    /// Holds on to a sent frame until the remote acks it. Every frame takes the next frame id, control
+    /// frames included, the way the firmware numbers them. Control frames are held too, so the remote
+    /// can ask for one it lost, but they don't take up room in the window and at most a window's worth
+    /// is kept
     pub fn append_tx_frame(&mut self, frame: Frame) {
-        self.tx_frame_queue.push_back((frame.frame_id, frame));
+        if frame.frame_id >= self.local_frame_id {
+            self.local_frame_id = frame.frame_id.wrapping_add(1);
+        }
+
+        if frame.cmd.is_control() && self.tx_frame_queue.iter().filter(|pending| pending.frame.cmd.is_control()).count() >= self.window_size {
+            if let Some(oldest) = self.tx_frame_queue.iter().position(|pending| pending.frame.cmd.is_control()) {
+                self.tx_frame_queue.remove(oldest);
+            }
+        }
+
+        self.tx_frame_queue.push_back(PendingFrame { frame, sent_at: Instant::now(), retries: 0 });
+    }
+
+    pub fn tx_window_full(&self) -> bool {
+        self.tx_in_flight() >= self.window_size
     }
 
-    pub fn append_rx_frame(&mut self, frame: Frame) {
-        self.rx_frame_queue.push_back((frame.frame_id, frame))
+    /// Data frames waiting on an Ack
+    pub fn tx_in_flight(&self) -> usize {
+        self.tx_frame_queue.iter().filter(|pending| !pending.frame.cmd.is_control()).count()
     }
 
-    pub fn get_rx_frame_queue(&self) -> &VecDeque<(u32, Frame)> {
+    pub fn tx_window_free(&self) -> usize {
+        self.window_size.saturating_sub(self.tx_in_flight())
+    }
+
+    /// Acks are cumulative, `next_expected` is the first frame id the remote hasn't received yet
+    pub fn ack_tx_frames(&mut self, next_expected: u32) {
+        self.tx_frame_queue.retain(|pending| pending.frame.frame_id >= next_expected);
+    }
+
+    /// Data frames whose Ack is overdue, marked as re-sent. Errs once a frame has used up its retries,
+    /// as by then the link is gone rather than lossy. Control frames are re-sent by the procedures that
+    /// send them, or when the remote asks for them
+    pub fn expired_tx_frames(&mut self) -> Result<Vec<Frame>, FrameError> {
+        let now = Instant::now();
+        let mut expired = vec![];
+
+        for pending in self.tx_frame_queue.iter_mut().filter(|pending| !pending.frame.cmd.is_control()) {
+            if now.duration_since(pending.sent_at) < self.retransmit_timeout {
+                continue;
+            }
+
+            if pending.retries >= self.max_retries {
+                return Err(FrameError::RetransmitLimitReached);
+            }
+
+            pending.retries += 1;
+            pending.sent_at  = now;
+            expired.push(pending.frame.clone());
+        }
+
+        Ok(expired)
+    }
+
+    /// Frames in [start, end) the remote asked for again, marked as re-sent. Ids that were never sent
+    /// are left out, but one that was sent and is no longer held, because it's been acked, fails the lot
+    pub fn requested_tx_frames(&mut self, start: u32, end: u32) -> Result<Vec<Frame>, FrameError> {
+        let now = Instant::now();
+        let end = end.min(self.local_frame_id);
+        let mut requested = Vec::with_capacity(end.saturating_sub(start) as usize);
+
+        for frame_id in start..end {
+            let pending = self.tx_frame_queue.iter_mut()
+                .find(|pending| pending.frame.frame_id == frame_id)
+                .ok_or(FrameError::FrameEvicted)?;
+
+            pending.sent_at = now;
+            requested.push(pending.frame.clone());
+        }
+
+        Ok(requested)
+    }
+
+    /// Records a received frame, from only what the window needs: the frame id, and the command if it's
+    /// a control frame. Acts on the remote's Acks. Returns false for frames that must not reach the caller:
+    /// data frames already received, and those too far ahead to fit in the window
+    pub fn accept_rx_frame_id(&mut self, frame_id: u32, control: Option<&Cmd>) -> bool {
+        if let Some(Cmd::Ack { frame_id, .. }) = control {
+            self.ack_tx_frames(*frame_id);
+        }
+
+        let in_window = frame_id >= self.remote_ackd_frame_id
+            && ((frame_id - self.remote_ackd_frame_id) as usize) < self.window_size;
+        let received  = self.rx_frame_queue.iter().any(|(id, _)| *id == frame_id);
+
+        // The remote numbers every frame, so each id counts once towards the next Ack, whatever it carries
+        if in_window && !received {
+            self.append_rx_frame(frame_id, control.is_some());
+        }
+
+        // A control frame sent again still wants an answer, the remote re-sends a RequestAck until it gets its Ack
+        if control.is_some() {
+            return true;
+        }
+
+        if !in_window || received {
+            self.duplicate_frames += 1;
+            return false;
+        }
+
+        true
+    }
+
+    pub fn append_rx_frame(&mut self, frame_id: u32, control: bool) {
+        self.rx_frame_queue.push_back((frame_id, control))
+    }
+
+    pub fn get_rx_frame_queue(&self) -> &VecDeque<(u32, bool)> {
         &self.rx_frame_queue
     } 
 
@@ -137,13 +581,280 @@ impl FrameStack {
         self.remote_ackd_frame_id
     }
 
+    /// Feeds a fragment received in place through reassembly. Fragments are held until the whole command
+    /// is in, which is then kept to be lent out with `lend_reassembled`, carrying the frame id of its first
+    /// fragment and no checksum since no CRC ever covered it whole. Returns whether it did
+    pub fn reassemble_in_place(&mut self, frame: Frame) -> Result<bool, FrameError> {
+        self.rx_reassembled = self.add_fragment(frame)?;
+        Ok(self.rx_reassembled.is_some())
+    }
+
+    /// Holds on to a fragment. Once all of its command's are in, returns the command's nibble, the
+    /// frame id of its first fragment and its body
+    fn add_fragment(&mut self, frame: Frame) -> Result<Option<(u8, u32, Vec<u8>)>, FrameError> {
+        let (cmd_nibble, sequence, last, payload) = match frame.cmd {
+            Cmd::Fragment { cmd_nibble, sequence, last, payload } => (cmd_nibble, sequence, last, payload),
+            _ => return Err(FrameError::InvalidCommandCode)
+        };
+
+        // Fragments of a command take consecutive frame ids, so they all point back to the same first one
+        let first_id = frame.frame_id.wrapping_sub(sequence as u32);
+
+        let partial = self.rx_fragments.entry(first_id)
+            .or_insert_with(|| PartialCmd { cmd_nibble, fragments: BTreeMap::new(), last: None, started_at: Instant::now() });
+
+        if partial.cmd_nibble != cmd_nibble {
+            self.rx_fragments.remove(&first_id);
+            return Err(FrameError::InvalidCommandSequence);
+        }
+
+        // A peer that keeps sending fragments doesn't get to have us hold on to all of them
+        if sequence as usize >= MAX_FRAGMENTS {
+            self.rx_fragments.remove(&first_id);
+            return Err(FrameError::LengthValueOutOfRange);
+        }
+
+        partial.fragments.insert(sequence, payload);
+        if last {
+            partial.last = Some(sequence);
+        }
+
+        let complete = partial.last
+            .is_some_and(|last| (0..=last).all(|sequence| partial.fragments.contains_key(&sequence)));
+
+        if !complete {
+            // Frame ids wrap, so the one given up on is the one that's waited the longest
+            while self.rx_fragments.len() > MAX_PARTIAL_CMDS {
+                let oldest = self.rx_fragments.iter().min_by_key(|(_, partial)| partial.started_at).map(|(first_id, _)| *first_id);
+                if let Some(oldest) = oldest {
+                    self.rx_fragments.remove(&oldest);
+                }
+            }
+            return Ok(None);
+        }
+
+        let partial = match self.rx_fragments.remove(&first_id) {
+            Some(partial) => partial,
+            None => return Ok(None)
+        };
+
+        let fragment_count = partial.last.map_or(0, |last| last as usize + 1);
+        let body: Vec<u8> = partial.fragments.into_values().take(fragment_count).flatten().collect();
+
+        Ok(Some((partial.cmd_nibble, first_id, body)))
+    }
+
+    /// Command last completed by `reassemble_in_place`, read in place
+    pub fn lend_reassembled(&self) -> Result<FrameRef<'_>, FrameError> {
+        let (cmd_nibble, frame_id, body) = self.rx_reassembled.as_ref().ok_or(FrameError::NotEnoughBytes)?;
+
+        Ok(FrameRef {
+            cmd     : CmdRef::parse(*cmd_nibble, body.len(), body)?,
+            frame_id: *frame_id,
+            checksum: None
+        })
+    }
+
+    /// Everything below `new_id` is acked, so it's dropped from the rx queue
     pub fn set_remote_ackd_frame_id (&mut self, new_id: u32) {
         self.remote_ackd_frame_id = new_id;
+        self.rx_frame_queue.retain(|(id, _)| *id >= new_id);
     }
 
     pub fn curr_id(&self) -> u32 {
         self.local_frame_id
     }
+
+    /// Protocol agreed on with the remote. Until a handshake says otherwise, it's the one spoken here
+    pub fn protocol(&self) -> &ProtocolInfo {
+        &self.protocol
+    }
+
+    pub fn set_protocol(&mut self, protocol: ProtocolInfo) {
+        if let Ok(mut link) = self.link.lock() {
+            link.set_heartbeats(protocol.supports(CAP_HEARTBEAT));
+        }
+        self.protocol = protocol;
+    }
+
+    pub fn grid(&self) -> Option<&Grid> {
+        self.grid.as_ref()
+    }
+
+    pub fn set_grid(&mut self, grid: Option<Grid>) {
+        self.grid = grid;
+    }
+
+    pub fn link_monitor(&self) -> Arc<Mutex<LinkMonitor>> {
+        self.link.clone()
+    }
+
+    pub fn set_link_monitor(&mut self, mut link: LinkMonitor) {
+        link.set_heartbeats(self.protocol.supports(CAP_HEARTBEAT));
+        self.link = Arc::new(Mutex::new(link));
+    }
+
+    pub fn link_state(&self) -> LinkState {
+        self.link.lock().map_or(LinkState::Down, |link| link.state())
+    }
+
+    pub fn heartbeat_due(&self) -> bool {
+        self.link.lock().is_ok_and(|link| link.heartbeat_due())
+    }
+
+    /// Notes the remote was just heard from
+    pub fn mark_heard(&self) {
+        if let Ok(mut link) = self.link.lock() {
+            link.heard();
+        }
+    }
+
+    /// Notes something was just sent to the remote
+    pub fn mark_sent(&self) {
+        if let Ok(mut link) = self.link.lock() {
+            link.sent();
+        }
+    }
+
+    pub fn session(&self) -> Option<Arc<Mutex<SessionState>>> {
+        self.session.clone()
+    }
+
+    pub fn set_session(&mut self, session: Option<Arc<Mutex<SessionState>>>) {
+        self.session = session;
+    }
+
+    pub fn session_state(&self) -> Option<SessionState> {
+        self.session.as_ref().and_then(|session| session.lock().ok().map(|state| *state))
+    }
+
+    /// Moves the session on to `next`, if it can go there from where it is
+    pub fn enter_session_state(&self, next: SessionState) -> Result<(), FrameError> {
+        let session = match &self.session {
+            Some(session) => session,
+            None => return Ok(())
+        };
+
+        let mut state = session.lock().map_err(|_| FrameError::InvalidCommandSequence)?;
+        if !state.can_enter(next) {
+            return Err(FrameError::InvalidCommandSequence);
+        }
+
+        *state = next;
+        Ok(())
+    }
+
+    /// Counts a frame thrown away by `rx_frame_blocking` or `rx_frame_ref` for coming at the wrong
+    /// point of the session
+    pub fn reject_rx_frame(&mut self) {
+        self.rejected_frames += 1;
+    }
+
+    pub fn rejected_frames(&self) -> usize {
+        self.rejected_frames
+    }
+
+    /// Bytes received but not yet part of a frame. Kept between reads, so a frame split across
+    /// them, or garbage in front of one, doesn't throw off the next read
+    pub fn rx_buffer(&mut self) -> &mut Vec<u8> {
+        &mut self.rx_buffer
+    }
+
+    pub fn framing(&self) -> Framing {
+        self.framing
+    }
+
+    pub fn set_framing(&mut self, framing: Framing) {
+        self.framing = framing;
+    }
+
+    /// Takes in bytes read off the line. On a delimited line only the frames of packets that decode
+    /// make it to the receive buffer, the rest are dropped whole
+    pub fn push_rx_bytes(&mut self, bytes: &[u8]) {
+        self.rx_stalled = false;
+        if self.framing == Framing::Raw {
+            self.rx_buffer.extend_from_slice(bytes);
+            return;
+        }
+
+        self.rx_packet.extend_from_slice(bytes);
+        let mut start = 0;
+        while let Some((packet, consumed)) = self.framing.next_packet(&self.rx_packet[start..]) {
+            start += consumed;
+
+            // Empty packets are just back to back delimiters
+            let frame = packet.filter(|packet| {
+                packet.is_empty() || FrameRef::parse(packet).is_ok_and(|(_, length)| usize::from(length) == packet.len())
+            });
+
+            match frame {
+                Some(frame) => self.rx_buffer.extend_from_slice(&frame),
+                None => self.dropped_bytes += consumed
+            }
+        }
+        self.rx_packet.drain(..start);
+
+        // No frame is that long, whatever the packet was it's lost
+        if self.rx_packet.len() > self.framing.max_packet_size() {
+            self.dropped_bytes += self.rx_packet.len();
+            self.rx_packet.clear();
+        }
+    }
+
+    pub fn drop_rx_bytes(&mut self, count: usize) {
+        let count = count.min(self.rx_buffer.len());
+        self.rx_buffer.drain(..count);
+        self.dropped_bytes += count;
+    }
+
+    /// Frame at the start of the receive buffer, `consumed` bytes long, read in place. Its bytes are
+    /// only let go of on the next receive, see `release_rx_frame`
+    pub fn lend_rx_frame(&mut self, consumed: usize) -> Result<FrameRef<'_>, FrameError> {
+        self.rx_lent = consumed.min(self.rx_buffer.len());
+        FrameRef::parse(&self.rx_buffer[..self.rx_lent]).map(|(frame, _)| frame)
+    }
+
+    /// Drops the bytes of the frame last lent out, it can't be borrowed anymore
+    pub fn release_rx_frame(&mut self) {
+        self.rx_buffer.drain(..self.rx_lent);
+        self.rx_lent = 0;
+    }
+
+    /// Records the first `consumed` bytes of the receive buffer as a received frame
+    pub fn record_rx_trace(&self, consumed: usize) {
+        self.record_trace(Direction::Rx, &self.rx_buffer[..consumed.min(self.rx_buffer.len())]);
+    }
+
+    /// Notes a read timed out with bytes pending. Returns whether the one before did too, with nothing
+    /// coming in between, in which case the pending bytes aren't waiting on the rest of a frame anymore
+    pub fn stall_rx(&mut self) -> bool {
+        std::mem::replace(&mut self.rx_stalled, true)
+    }
+
+    /// Total of bytes thrown away while looking for a valid frame boundary
+    pub fn dropped_bytes(&self) -> usize {
+        self.dropped_bytes
+    }
+
+    /// Total of frames received more than once, or outside of the window, and thrown away
+    pub fn duplicate_frames(&self) -> usize {
+        self.duplicate_frames
+    }
+
+    pub fn trace_recorder(&self) -> Option<&TraceRecorder> {
+        self.trace.as_ref()
+    }
+
+    /// Records every frame going over the wire from now on, or stops recording with None
+    pub fn set_trace_recorder(&mut self, trace: Option<TraceRecorder>) {
+        self.trace = trace;
+    }
+
+    pub fn record_trace(&self, direction: Direction, bytes: &[u8]) {
+        if let Some(trace) = &self.trace {
+            trace.record(direction, bytes);
+        }
+    }
 }
 
 impl Default for FrameStack {
@@ -175,6 +886,186 @@ impl BSSID {
     }
 }
 
+impl SecondaryChannel {
+    fn from_int(value: u8) -> Result<SecondaryChannel, FrameError> {
+        match value {
+            0 => Ok(SecondaryChannel::None ),
+            1 => Ok(SecondaryChannel::Above),
+            2 => Ok(SecondaryChannel::Below),
+            _ => Err(FrameError::ValueOutOfRange)
+        }
+    }
+}
+
+impl Band {
+    pub fn from_channel(channel: u8) -> Option<Band> {
+        match channel {
+            1..=14   => Some(Band::Band2G4),
+            32..=177 => Some(Band::Band5G ),
+            _ => None
+        }
+    }
+}
+
+impl AuthMode {
+    pub fn from_int(value: u8) -> AuthMode {
+        match value {
+            0 => AuthMode::Open,
+            1 => AuthMode::Wep,
+            2 => AuthMode::WpaPsk,
+            3 => AuthMode::Wpa2Psk,
+            4 => AuthMode::WpaWpa2Psk,
+            5 => AuthMode::Wpa2Enterprise,
+            6 => AuthMode::Wpa3Psk,
+            7 => AuthMode::Wpa2Wpa3Psk,
+            8 => AuthMode::WapiPsk,
+            9 => AuthMode::Owe,
+            value => AuthMode::Other(value)
+        }
+    }
+
+    pub fn as_int(&self) -> u8 {
+        match self {
+            AuthMode::Open           => 0,
+            AuthMode::Wep            => 1,
+            AuthMode::WpaPsk         => 2,
+            AuthMode::Wpa2Psk        => 3,
+            AuthMode::WpaWpa2Psk     => 4,
+            AuthMode::Wpa2Enterprise => 5,
+            AuthMode::Wpa3Psk        => 6,
+            AuthMode::Wpa2Wpa3Psk    => 7,
+            AuthMode::WapiPsk        => 8,
+            AuthMode::Owe            => 9,
+            AuthMode::Other(value)   => *value
+        }
+    }
+}
+
+impl NetworkInfo {
+    fn parse(bytes: &[u8]) -> Result<NetworkInfo, FrameError> {
+        if bytes.len() < 10 {
+            return Err(FrameError::NotEnoughBytes);
+        }
+
+        let info = NetworkInfo::from_components(BSSID::parse(bytes)?, bytes[6], AuthMode::from_int(bytes[8]), bytes[9] & NETWORK_INFO_HIDDEN_FLAG != 0)?;
+
+        Ok(info.with_secondary_channel(SecondaryChannel::from_int(bytes[7])?))
+    }
+
+    pub fn from_components(bssid: BSSID, channel: u8, auth_mode: AuthMode, hidden: bool) -> Result<NetworkInfo, FrameError> {
+        let band = Band::from_channel(channel).ok_or(FrameError::ValueOutOfRange)?;
+
+        Ok(NetworkInfo { bssid, channel, band, secondary_channel: SecondaryChannel::None, auth_mode, hidden })
+    }
+
+    pub fn with_secondary_channel(self, secondary_channel: SecondaryChannel) -> NetworkInfo {
+        NetworkInfo { secondary_channel, ..self }
+    }
+
+    pub fn bssid(&self) -> &BSSID {
+        &self.bssid
+    }
+
+    pub fn channel(&self) -> u8 {
+        self.channel
+    }
+
+    pub fn band(&self) -> Band {
+        self.band
+    }
+
+    pub fn secondary_channel(&self) -> SecondaryChannel {
+        self.secondary_channel
+    }
+
+    pub fn auth_mode(&self) -> AuthMode {
+        self.auth_mode
+    }
+
+    pub fn hidden(&self) -> bool {
+        self.hidden
+    }
+
+    pub fn as_bytes(&self) -> [u8; 10] {
+        let bssid = self.bssid.as_bytes();
+        let flags = if self.hidden { NETWORK_INFO_HIDDEN_FLAG } else { 0 };
+
+        [bssid[0], bssid[1], bssid[2], bssid[3], bssid[4], bssid[5], self.channel, self.secondary_channel as u8, self.auth_mode.as_int(), flags]
+    }
+}
+
+impl DeviceErrorCode {
+    pub fn from_int(value: u8) -> DeviceErrorCode {
+        match value {
+            1 => DeviceErrorCode::StepperStall,
+            2 => DeviceErrorCode::SensorInitFailed,
+            3 => DeviceErrorCode::ScanFailed,
+            4 => DeviceErrorCode::LowPower,
+            value => DeviceErrorCode::Other(value)
+        }
+    }
+
+    pub fn as_int(&self) -> u8 {
+        match self {
+            DeviceErrorCode::StepperStall     => 1,
+            DeviceErrorCode::SensorInitFailed => 2,
+            DeviceErrorCode::ScanFailed       => 3,
+            DeviceErrorCode::LowPower         => 4,
+            DeviceErrorCode::Other(value)     => *value
+        }
+    }
+
+    /// Stalls and failed scans tend to go away on a second try. Nothing measured without working
+    /// sensors is worth keeping, and anything we don't know about is taken as a reason to stop
+    pub fn action(&self) -> ErrorAction {
+        match self {
+            DeviceErrorCode::StepperStall     |
+            DeviceErrorCode::ScanFailed       => ErrorAction::RetryPosition,
+            DeviceErrorCode::SensorInitFailed => ErrorAction::FailProject,
+            DeviceErrorCode::LowPower         |
+            DeviceErrorCode::Other(_)         => ErrorAction::Abort
+        }
+    }
+}
+
+impl DeviceError {
+    fn parse(bytes: &[u8]) -> Result<DeviceError, FrameError> {
+        if bytes.len() < DEVICE_ERROR_HEADER_SIZE - 1 {
+            return Err(FrameError::NotEnoughBytes);
+        }
+
+        let context = from_utf8(&bytes[9..]).map_err(|_| FrameError::InvalidUTF8)?;
+
+        Ok(DeviceError {
+            code    : DeviceErrorCode::from_int(bytes[0]),
+            position: Position::parse(&bytes[1..9])?,
+            context : context.to_string()
+        })
+    }
+
+    pub fn new(code: DeviceErrorCode, position: Position, context: String) -> DeviceError {
+        DeviceError { code, position, context }
+    }
+
+    pub fn code(&self) -> DeviceErrorCode {
+        self.code
+    }
+
+    pub fn position(&self) -> &Position {
+        &self.position
+    }
+
+    pub fn context(&self) -> &str {
+        &self.context
+    }
+}
+
+impl From<&DeviceError> for FrameError {
+    fn from(error: &DeviceError) -> Self {
+        FrameError::DeviceFault(error.code)
+    }
+}
+
 impl SSID {
     pub fn new(name: String) -> Self {
         Self { name }
@@ -265,11 +1156,84 @@ impl Position {
         })
     }
 
-    pub fn as_bytes(&self) -> [u8; 8] {
-        let pitch = self.pitch.to_be_bytes();
-        let yaw = self.yaw.to_be_bytes();
+    pub fn pitch(&self) -> u32 {
+        self.pitch
+    }
+
+    pub fn yaw(&self) -> u32 {
+        self.yaw
+    }
+
+    pub fn as_bytes(&self) -> [u8; 8] {
+        let pitch = self.pitch.to_be_bytes();
+        let yaw = self.yaw.to_be_bytes();
+
+        [pitch[0], pitch[1], pitch[2], pitch[3], yaw[0], yaw[1], yaw[2], yaw[3]]
+    }
+}
+
+impl ProtocolInfo {
+    fn parse(bytes: &[u8]) -> Result<ProtocolInfo, FrameError> {
+        if bytes.len() < 6 {
+            return Err(FrameError::NotEnoughBytes);
+        }
+
+        Ok(ProtocolInfo {
+            version     : bytes[0],
+            min_version : bytes[1],
+            capabilities: byte_slice_to_u32(&bytes[2..])?
+        })
+    }
+
+    pub fn from_components(version: u8, min_version: u8, capabilities: u32) -> ProtocolInfo {
+        ProtocolInfo { version, min_version, capabilities }
+    }
+
+    /// What this backend speaks
+    pub fn host() -> ProtocolInfo {
+        ProtocolInfo::from_components(PROTOCOL_VERSION, MIN_PROTOCOL_VERSION, HOST_CAPABILITIES)
+    }
+
+    /// What firmware that answers the StartOfTransmission with a bare Ack speaks
+    pub fn legacy() -> ProtocolInfo {
+        ProtocolInfo::from_components(LEGACY_PROTOCOL_VERSION, LEGACY_PROTOCOL_VERSION, 0)
+    }
+
+    /// Settles on the newest version both ends speak, and the capabilities both have. Fails when
+    /// either end is too new for the other
+    pub fn negotiate(&self, remote: &ProtocolInfo) -> Result<ProtocolInfo, FrameError> {
+        if remote.version < self.min_version || self.version < remote.min_version {
+            return Err(FrameError::UnsupportedProtocolVersion);
+        }
+
+        let version = self.version.min(remote.version);
+
+        Ok(ProtocolInfo {
+            version,
+            min_version : version,
+            capabilities: self.capabilities & remote.capabilities
+        })
+    }
+
+    pub fn version(&self) -> u8 {
+        self.version
+    }
+
+    pub fn supports(&self, capability: u32) -> bool {
+        self.capabilities & capability == capability
+    }
+
+    pub fn capability_names(&self) -> Vec<&'static str> {
+        CAPABILITY_NAMES.iter()
+            .filter(|(capability, _)| self.supports(*capability))
+            .map(|(_, name)| *name)
+            .collect()
+    }
 
-        [pitch[0], pitch[1], pitch[2], pitch[3], yaw[0], yaw[1], yaw[2], yaw[3]]
+    pub fn as_bytes(&self) -> [u8; 6] {
+        let capabilities = self.capabilities.to_be_bytes();
+
+        [self.version, self.min_version, capabilities[0], capabilities[1], capabilities[2], capabilities[3]]
     }
 }
 
@@ -294,17 +1258,6 @@ impl NetworkId {
 }
 
 impl Record {
-    fn parse_multiple(count: u32 , bytes: &[u8]) -> Result<Vec<Record>, FrameError> {
-        let mut read_vec = Vec::with_capacity(count as usize);
-        for index in 0..count {
-            let start = index as usize * 5;
-            let end = (index as usize + 1) * 5;
-            read_vec.push(Record::parse(&bytes[start..end])?);
-        }
-
-        Ok(read_vec)
-    }
-
     fn parse(bytes: &[u8]) -> Result <Record, FrameError> {
         if bytes.len() < 5 {
             return Err(FrameError::NotEnoughBytes);
@@ -330,8 +1283,47 @@ impl Record {
 
 }
 
+impl<'a> RecordIter<'a> {
+    /// Checks every record up front, so iterating can't fail
+    fn parse(count: u32, bytes: &'a [u8]) -> Result<RecordIter<'a>, FrameError> {
+        // The count comes straight off the line, check it against the bytes before sizing anything with it
+        let length = (count as usize).checked_mul(5).ok_or(FrameError::NotEnoughBytes)?;
+        let bytes = bytes.get(..length).ok_or(FrameError::NotEnoughBytes)?;
+
+        for record in bytes.chunks_exact(5) {
+            Record::parse(record)?;
+        }
+
+        Ok(RecordIter { bytes })
+    }
+}
+
+impl Iterator for RecordIter<'_> {
+    type Item = Record;
+
+    fn next(&mut self) -> Option<Record> {
+        if self.bytes.len() < 5 {
+            return None;
+        }
+
+        let (record, rest) = self.bytes.split_at(5);
+        self.bytes = rest;
+        Record::parse(record).ok()
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.bytes.len() / 5, Some(self.bytes.len() / 5))
+    }
+}
+
+impl ExactSizeIterator for RecordIter<'_> {}
+
 impl StepSize {
     fn parse(bytes: &[u8]) -> Result<StepSize, FrameError> {
+        if bytes.len() < 8 {
+            return Err(FrameError::NotEnoughBytes);
+        }
+
         Ok(StepSize {
             pitch_step: byte_slice_to_u32(bytes)?,
             yaw_step  : byte_slice_to_u32(&bytes[4..])?
@@ -375,37 +1367,183 @@ impl StepSize {
     }
 }
 
+impl Grid {
+    pub fn new(origin: Position, step_size: StepSize) -> Grid {
+        Grid { origin, step_size }
+    }
+
+    pub fn origin(&self) -> &Position {
+        &self.origin
+    }
+
+    pub fn step_size(&self) -> &StepSize {
+        &self.step_size
+    }
+
+    /// Where `position` sits on the grid. None if it's somewhere in between steps
+    pub fn step_of(&self, position: &Position) -> Option<GridStep> {
+        Some(GridStep {
+            pitch: Grid::steps_between(self.origin.pitch, position.pitch, self.step_size.pitch_step)?,
+            yaw  : Grid::steps_between(self.origin.yaw  , position.yaw  , self.step_size.yaw_step  )?
+        })
+    }
+
+    pub fn position(&self, step: &GridStep) -> Result<Position, FrameError> {
+        Ok(Position {
+            pitch: Grid::step_from(self.origin.pitch, step.pitch, self.step_size.pitch_step)?,
+            yaw  : Grid::step_from(self.origin.yaw  , step.yaw  , self.step_size.yaw_step  )?
+        })
+    }
+
+    fn steps_between(origin: u32, value: u32, step: u32) -> Option<i64> {
+        let distance = i64::from(value) - i64::from(origin);
+        match step {
+            0 if distance == 0 => Some(0),
+            0 => None,
+            step if distance % i64::from(step) == 0 => Some(distance / i64::from(step)),
+            _ => None
+        }
+    }
+
+    fn step_from(origin: u32, steps: i64, step: u32) -> Result<u32, FrameError> {
+        steps.checked_mul(i64::from(step))
+            .and_then(|distance| distance.checked_add(i64::from(origin)))
+            .and_then(|value| u32::try_from(value).ok())
+            .ok_or(FrameError::ValueOutOfRange)
+    }
+}
+
+impl GridStep {
+    pub fn from_int(pitch: i64, yaw: i64) -> GridStep {
+        GridStep { pitch, yaw }
+    }
+
+    pub fn pitch(&self) -> i64 {
+        self.pitch
+    }
+
+    pub fn yaw(&self) -> i64 {
+        self.yaw
+    }
+}
+
+impl GridRecords {
+    pub fn new(step: GridStep, records: Vec<Record>) -> GridRecords {
+        GridRecords { step, records }
+    }
+
+    pub fn step(&self) -> &GridStep {
+        &self.step
+    }
+
+    pub fn records(&self) -> &[Record] {
+        &self.records
+    }
+
+    pub fn into_records(self) -> Vec<Record> {
+        self.records
+    }
+
+    /// Reads one entry of a `RecordBatch` off the front of `bytes`, its step relative to `previous`.
+    /// Returns it along with how many bytes it took
+    fn parse(previous: &GridStep, bytes: &[u8]) -> Result<(GridRecords, usize), FrameError> {
+        let (pitch, mut consumed) = read_varint(bytes)?;
+        let (yaw  , length) = read_varint(&bytes[consumed..])?;
+        consumed += length;
+        let (count, length) = read_varint(&bytes[consumed..])?;
+        consumed += length;
+
+        // The count comes straight off the line, every record takes at least two bytes
+        if count > ((bytes.len() - consumed) / 2) as u64 {
+            return Err(FrameError::NotEnoughBytes);
+        }
+
+        let mut records = Vec::with_capacity(count as usize);
+        for _ in 0..count {
+            let (id, length) = read_varint(&bytes[consumed..])?;
+            consumed += length;
+
+            let rssi = *bytes.get(consumed).ok_or(FrameError::NotEnoughBytes)?;
+            consumed += 1;
+
+            records.push(Record {
+                internal_id: NetworkId::from_int(u32::try_from(id).map_err(|_| FrameError::ValueOutOfRange)?),
+                rssi       : RSSI::from_int(rssi as i8)?
+            });
+        }
+
+        let step = GridStep {
+            pitch: previous.pitch.wrapping_add(zigzag_decode(pitch)),
+            yaw  : previous.yaw  .wrapping_add(zigzag_decode(yaw  ))
+        };
+
+        Ok((GridRecords { step, records }, consumed))
+    }
+
+    fn write(&self, previous: &GridStep, bytes: &mut Vec<u8>) {
+        write_varint(zigzag_encode(self.step.pitch.wrapping_sub(previous.pitch)), bytes);
+        write_varint(zigzag_encode(self.step.yaw  .wrapping_sub(previous.yaw  )), bytes);
+        write_varint(self.records.len() as u64, bytes);
+
+        for record in &self.records {
+            write_varint(u64::from(record.internal_id.id), bytes);
+            bytes.extend_from_slice(&record.rssi.as_bytes());
+        }
+    }
+}
+
 
 impl Cmd {
 
-    pub fn parse_body(cmd_nibble: u8, length: u16, data: &[u8]) -> Result <Cmd, FrameError> {
+    /// Checks the length in a header is one the command can have, without looking at the body
+    pub fn check_length(cmd_nibble: u8, length: usize) -> Result<(), FrameError> {
+        let valid = match cmd_nibble {
+            0x0 => length == 0 || length == PROTOCOL_INFO_SIZE,
+            0x1 | 0x2 | 0x3 | 0xF => length == 0,
+            0x4 => length == 0x004 || length == 0x004 + PROTOCOL_INFO_SIZE,
+            0x6 => length == 0x004,
+            0x5 => length == 0x008,
+            0x7 => (0x4..=0x24).contains(&length),
+            0x8 => length == 0xA,
+            0x9 => length >= 0x11,
+            0xA => length == 8,
+            0xB => length == 0x11,
+            0xC => length >= 0x00A,
+            0xD => length >= 0x002,
+            0xE => length >= 0x001,
+            _ => return Err(FrameError::InvalidCommandCode)
+        };
+
+        if valid { Ok(()) } else { Err(FrameError::LengthValueOutOfRange) }
+    }
+
+    /// Parses a command's body into owned values
+    pub fn parse_body(cmd_nibble: u8, length: usize, data: &[u8]) -> Result <Cmd, FrameError> {
+        CmdRef::parse(cmd_nibble, length, data).map(CmdRef::into_owned)
+    }
 
+    /// Body of every command `CmdRef` doesn't borrow from, already cut to its length
+    fn parse_owned(cmd_nibble: u8, length: usize, data: &[u8]) -> Result <Cmd, FrameError> {
         match cmd_nibble {
-            0x0 => if length != 0 { Err(FrameError::LengthValueOutOfRange)} else { Ok(Cmd::StartOfTransmission) },
-            0x1 => if length != 0 { Err(FrameError::LengthValueOutOfRange)} else { Ok(Cmd::Reset              ) },
-            0x2 => if length != 0 { Err(FrameError::LengthValueOutOfRange)} else { Ok(Cmd::Ready              ) },
-            0x3 => if length != 0 { Err(FrameError::LengthValueOutOfRange)} else { Ok(Cmd::RequestPosition    ) },
-            0xF => if length != 0 { Err(FrameError::LengthValueOutOfRange)} else { Ok(Cmd::EndOfTransmission  ) },
-            
-            0x4|0x6 => {
-                if length != 0x004 {
-                    return Err(FrameError::LengthValueOutOfRange);
-                }
+            0x0 => {
+                let info = if length == 0 { None } else { Some(ProtocolInfo::parse(data)?) };
 
-                let id = byte_slice_to_u32(data)?;
+                Ok(Cmd::StartOfTransmission { info })
+            },
+            0x1 => Ok(Cmd::Reset              ),
+            0x2 => Ok(Cmd::Ready              ),
+            0x3 => Ok(Cmd::RequestPosition    ),
+            0xF => Ok(Cmd::EndOfTransmission  ),
+            
+            0x4 => {
+                let frame_id = byte_slice_to_u32(data)?;
+                let info = if length == 0x004 { None } else { Some(ProtocolInfo::parse(&data[4..])?) };
 
-                match cmd_nibble {
-                    0x4 => Ok(Cmd::Ack               { frame_id: id }),
-                    0x6 => Ok(Cmd::RequestAck        { frame_id: id }),
-                    _ => panic!("Unrechable!")
-                }
+                Ok(Cmd::Ack { frame_id, info })
             },
+            0x6 => Ok(Cmd::RequestAck { frame_id: byte_slice_to_u32(data)? }),
 
             0x5 => {
-                if length != 0x008 {
-                    return Err(FrameError::LengthValueOutOfRange);
-                }
-
                 let start = byte_slice_to_u32(data)?;
                 let end   = byte_slice_to_u32(&data[4..])?;
 
@@ -413,9 +1551,6 @@ impl Cmd {
             },
             
             0x7 => {
-                if !(0x4..=0x24).contains(&length) {
-                    return Err(FrameError::LengthValueOutOfRange);
-                }
                 let id = NetworkId::parse(&data[0..4])?;
                 let ssid =
                     if length == 0x4 {
@@ -430,10 +1565,6 @@ impl Cmd {
 
 
             0x8 => {
-                if length != 0xA {
-                    return Err(FrameError::LengthValueOutOfRange)
-                }
-
                 Ok(Cmd::AddBSSID {
                         id   : NetworkId::parse(&data[0..4])?,
                         bssid: BSSID::parse(&data[4..])?
@@ -441,24 +1572,7 @@ impl Cmd {
                 )
             }
 
-            0x9 => {
-                if length < 0x11 {
-                    return Err(FrameError::LengthValueOutOfRange);
-                }
-                let count = byte_slice_to_u32(&data[8..=11])?;
-                Ok(Cmd::RecordRSSI {
-                        position: Position::parse(&data[0..=7])?,
-                        record_count: count,
-                        records: Record::parse_multiple(count, &data[12..])?
-                    }
-                )
-            },
-
             0xA => {
-                if length != 8 {
-                    return Err(FrameError::LengthValueOutOfRange);
-                }
-
                 Ok(Cmd::SetPosition {
                         position: Position::parse(&data[0..=7])?
                     }
@@ -466,10 +1580,6 @@ impl Cmd {
             },
         
             0xB =>  {
-                if length != 0x11 {
-                    return Err (FrameError::LengthValueOutOfRange);
-                }
-
                 Ok (Cmd::SetParams {
                         position: Position::parse(&data[0..=7])?,
                         step_size: StepSize::parse(&data[8..=15])?,
@@ -479,10 +1589,6 @@ impl Cmd {
             },
 
             0xC => {
-                if length < 0x00A {
-                    return Err(FrameError::LengthValueOutOfRange);
-                }
-                
                 if let Ok(body_str) = std::str::from_utf8(&data[8..]) {
                     if let Ok(body) =  json::from_str(body_str) {
 
@@ -499,12 +1605,6 @@ impl Cmd {
             }
 
             0xD => {
-                
-                if length < 0x002 {
-                    dbg!(&data);
-                    return Err(FrameError::LengthValueOutOfRange);
-                }
-
                 if let Ok(logs) = std::str::from_utf8(&data) {
                     match json::from_str(logs) {
                         Ok(logs) => return Ok(Cmd::TransmitLogs { logs }),
@@ -519,32 +1619,135 @@ impl Cmd {
                 Err(FrameError::InvalidUTF8)
             }
 
+            0xE => Cmd::parse_extended(length, data),
+
             _ => Err(FrameError::InvalidCommandCode)
         }
 
     }
 
-    pub fn parse(cmd_nibble: u8, length: u16, frame_length: u16, bytes: &[u8]) -> Result<Cmd, FrameError> {    
-        if (0x7..=0xB).contains(&cmd_nibble) && usize::from(length) > bytes.len() - FRAME_HEADER_SIZE {
-            return Err(FrameError::NotEnoughBytes);
+    fn parse_extended(length: usize, data: &[u8]) -> Result<Cmd, FrameError> {
+        match data[0] {
+            SUB_OPCODE_FRAGMENT => {
+                if length < FRAGMENT_HEADER_SIZE {
+                    return Err(FrameError::LengthValueOutOfRange);
+                }
+
+                // fragments carry a slice of a base command's body, extended ones always fit a frame
+                let cmd_nibble = data[1];
+                if cmd_nibble > 0xF || cmd_nibble == EXTENDED_CMD_NIBBLE {
+                    return Err(FrameError::InvalidCommandCode);
+                }
+
+                let sequence = byte_slice_to_u16(&data[2..])?;
+
+                Ok(Cmd::Fragment {
+                    cmd_nibble,
+                    sequence: sequence & !FRAGMENT_LAST_FLAG,
+                    last    : sequence &  FRAGMENT_LAST_FLAG != 0,
+                    payload : data[FRAGMENT_HEADER_SIZE..length].to_vec()
+                })
+            },
+
+            SUB_OPCODE_HEARTBEAT | SUB_OPCODE_PAUSE | SUB_OPCODE_RESUME | SUB_OPCODE_ABORT => {
+                if length != 1 {
+                    return Err(FrameError::LengthValueOutOfRange);
+                }
+
+                match data[0] {
+                    SUB_OPCODE_PAUSE  => Ok(Cmd::Pause ),
+                    SUB_OPCODE_RESUME => Ok(Cmd::Resume),
+                    SUB_OPCODE_ABORT  => Ok(Cmd::Abort ),
+                    _                 => Ok(Cmd::Heartbeat)
+                }
+            },
+
+            SUB_OPCODE_RECORD_BATCH => {
+                // The first entry's step is counted from the grid's origin
+                let mut entries: Vec<GridRecords> = Vec::new();
+                let mut consumed = 1;
+                while consumed < length {
+                    let previous = entries.last().map(|entry| entry.step).unwrap_or_default();
+                    let (entry, entry_length) = GridRecords::parse(&previous, &data[consumed..length])?;
+
+                    entries.push(entry);
+                    consumed += entry_length;
+                }
+
+                Ok(Cmd::RecordBatch { entries })
+            },
+
+            SUB_OPCODE_NETWORK_INFO => {
+                if length != NETWORK_INFO_SIZE {
+                    return Err(FrameError::LengthValueOutOfRange);
+                }
+
+                Ok(Cmd::NetworkInfo {
+                    id  : NetworkId::parse(&data[1..5])?,
+                    info: NetworkInfo::parse(&data[5..])?
+                })
+            },
+
+            SUB_OPCODE_DEVICE_ERROR => {
+                if length < DEVICE_ERROR_HEADER_SIZE {
+                    return Err(FrameError::LengthValueOutOfRange);
+                }
+
+                Ok(Cmd::DeviceError { error: DeviceError::parse(&data[1..length])? })
+            },
+
+            _ => Err(FrameError::InvalidCommandCode)
         }
+    }
 
-        let data = &bytes[FRAME_HEADER_SIZE..(frame_length as usize - CHECKSUM_SIZE)];
+    /// Splits the command into fragments that fit a frame each. One that already fits comes back as is
+    pub fn fragment(self) -> Result<Vec<Cmd>, FrameError> {
+        let body = self.as_bytes()?;
+        if body.len() <= MAX_BODY_SIZE {
+            return Ok(vec![self]);
+        }
 
-        let cmd = Cmd::parse_body(cmd_nibble, length, data)?;
-        
-        Ok( cmd  )
+        let cmd_nibble = self.as_int()?;
+        let count = body.len().div_ceil(MAX_FRAGMENT_PAYLOAD);
+        if cmd_nibble == EXTENDED_CMD_NIBBLE || count > MAX_FRAGMENTS {
+            return Err(FrameError::LengthValueOutOfRange);
+        }
+
+        let fragments = body.chunks(MAX_FRAGMENT_PAYLOAD).enumerate()
+            .map(|(sequence, payload)| Cmd::Fragment {
+                cmd_nibble,
+                sequence: sequence as u16,
+                last    : sequence + 1 == count,
+                payload : payload.to_vec()
+            })
+            .collect();
+
+        Ok(fragments)
+    }
+
+    pub fn parse(cmd_nibble: u8, length: u16, frame_length: u16, bytes: &[u8]) -> Result<Cmd, FrameError> {    
+        CmdRef::parse_frame(cmd_nibble, length, frame_length, bytes).map(CmdRef::into_owned)
     }
 
     pub fn as_bytes(&self) -> Result<Vec<u8>, FrameError> {
         match self {
-            Cmd::StartOfTransmission |
+            Cmd::StartOfTransmission { info } => {
+                Ok(info.as_ref().map(|info| info.as_bytes().to_vec()).unwrap_or_default())
+            }
+
+            Cmd::Ack                 {frame_id, info} => {
+                let mut result = u32::to_be_bytes(*frame_id).to_vec();
+                if let Some(info) = info {
+                    result.extend_from_slice(&info.as_bytes());
+                }
+                Ok(result)
+            }
+
             Cmd::Reset               |
             Cmd::Ready               |
             Cmd::RequestPosition     |
             Cmd::EndOfTransmission   => Ok(vec![]),
 
-            Cmd::Ack                 {frame_id} |
             Cmd::RequestAck          {frame_id}  => {
                 Ok(u32::to_be_bytes(*frame_id).to_vec())
             }
@@ -615,16 +1818,76 @@ impl Cmd {
             Cmd::TransmitLogs { logs } => {
                 let result = logs.to_string().as_bytes().to_vec();
 
+                Ok(result)
+            },
+            Cmd::Fragment { cmd_nibble, sequence, last, payload } => {
+                let sequence = if *last { sequence | FRAGMENT_LAST_FLAG } else { *sequence };
+
+                let mut result = Vec::with_capacity(FRAGMENT_HEADER_SIZE + payload.len());
+                result.push(SUB_OPCODE_FRAGMENT);
+                result.push(*cmd_nibble);
+                result.extend_from_slice(&sequence.to_be_bytes());
+                result.extend_from_slice(payload);
+
+                Ok(result)
+            },
+            Cmd::PictureChunk { position, offset, total_size, data } => {
+                let mut result = Vec::with_capacity(PICTURE_CHUNK_HEADER_SIZE + data.len());
+                result.push(SUB_OPCODE_PICTURE_CHUNK);
+                result.extend_from_slice(&position.as_bytes());
+                result.extend_from_slice(&offset.to_be_bytes());
+                result.extend_from_slice(&total_size.to_be_bytes());
+                result.extend_from_slice(data);
+
+                Ok(result)
+            },
+            Cmd::Heartbeat => Ok(vec![SUB_OPCODE_HEARTBEAT]),
+            Cmd::Pause     => Ok(vec![SUB_OPCODE_PAUSE    ]),
+            Cmd::Resume    => Ok(vec![SUB_OPCODE_RESUME   ]),
+            Cmd::Abort     => Ok(vec![SUB_OPCODE_ABORT    ]),
+            Cmd::RecordBatch { entries } => {
+                let mut result = vec![SUB_OPCODE_RECORD_BATCH];
+                let mut previous = GridStep::default();
+                for entry in entries {
+                    entry.write(&previous, &mut result);
+                    previous = entry.step;
+                }
+
+                Ok(result)
+            },
+            Cmd::NetworkInfo { id, info } => {
+                let mut result = Vec::with_capacity(NETWORK_INFO_SIZE);
+                result.push(SUB_OPCODE_NETWORK_INFO);
+                result.extend_from_slice(&id.as_bytes());
+                result.extend_from_slice(&info.as_bytes());
+
+                Ok(result)
+            },
+            Cmd::DeviceError { error } => {
+                let mut result = Vec::with_capacity(DEVICE_ERROR_HEADER_SIZE + error.context.len());
+                result.push(SUB_OPCODE_DEVICE_ERROR);
+                result.push(error.code.as_int());
+                result.extend_from_slice(&error.position.as_bytes());
+                result.extend_from_slice(error.context.as_bytes());
+
                 Ok(result)
             }
             
         }
     }
 
+    /// Frames that drive the session itself rather than carry data. They aren't sequenced by the window
+    pub fn is_control(&self) -> bool {
+        matches!(self,
+            Cmd::StartOfTransmission { .. } | Cmd::Ack { .. } | Cmd::RequestRetransmit { .. } | Cmd::RequestAck { .. } |
+            Cmd::Heartbeat
+        )
+    }
+
     pub fn as_int(&self) -> Result<u8, FrameError> {
         #[allow(unreachable_patterns)]
         match self {
-            Cmd::StartOfTransmission       => Ok(0x00),
+            Cmd::StartOfTransmission { .. } => Ok(0x00),
             Cmd::Reset                     => Ok(0x01),
             Cmd::Ready                     => Ok(0x02),
             Cmd::RequestPosition           => Ok(0x03),
@@ -639,12 +1902,153 @@ impl Cmd {
             Cmd::TransmitPicture   { .. }  => Ok(0x0C),
             Cmd::TransmitLogs      { .. }  => Ok(0x0D),
             Cmd::EndOfTransmission         => Ok(0x0F),
+            Cmd::Fragment          { .. }  |
+            Cmd::PictureChunk      { .. }  |
+            Cmd::Heartbeat                 |
+            Cmd::RecordBatch       { .. }  |
+            Cmd::NetworkInfo       { .. }  |
+            Cmd::DeviceError       { .. }  |
+            Cmd::Pause                     |
+            Cmd::Resume                    |
+            Cmd::Abort                     => Ok(EXTENDED_CMD_NIBBLE),
             _ => Err(FrameError::InvalidCommandCode)
         }
     }
 
 }
 
+impl<'a> CmdRef<'a> {
+    /// Parses a command's body in place. Never panics: once the length is one the command can have and
+    /// the body holds that many bytes, every field below is in bounds
+    pub fn parse(cmd_nibble: u8, length: usize, data: &'a [u8]) -> Result<CmdRef<'a>, FrameError> {
+        Cmd::check_length(cmd_nibble, length)?;
+        let data = data.get(..length).ok_or(FrameError::NotEnoughBytes)?;
+
+        match (cmd_nibble, data.first().copied()) {
+            (0x9, _) => {
+                let count = byte_slice_to_u32(&data[8..=11])?;
+                Ok(CmdRef::RecordRSSI {
+                    position    : Position::parse(&data[0..=7])?,
+                    record_count: count,
+                    records     : RecordIter::parse(count, &data[12..])?
+                })
+            },
+
+            (EXTENDED_CMD_NIBBLE, Some(SUB_OPCODE_PICTURE_CHUNK)) => {
+                if length < PICTURE_CHUNK_HEADER_SIZE {
+                    return Err(FrameError::LengthValueOutOfRange);
+                }
+
+                Ok(CmdRef::PictureChunk {
+                    position  : Position::parse(&data[1..=8])?,
+                    offset    : byte_slice_to_u32(&data[9..])?,
+                    total_size: byte_slice_to_u32(&data[13..])?,
+                    data      : &data[PICTURE_CHUNK_HEADER_SIZE..]
+                })
+            },
+
+            _ => Cmd::parse_owned(cmd_nibble, length, data).map(CmdRef::Owned)
+        }
+    }
+
+    fn parse_frame(cmd_nibble: u8, length: u16, frame_length: u16, bytes: &'a [u8]) -> Result<CmdRef<'a>, FrameError> {
+        let data = usize::from(frame_length).checked_sub(CHECKSUM_SIZE)
+            .and_then(|end| bytes.get(FRAME_HEADER_SIZE..end))
+            .ok_or(FrameError::NotEnoughBytes)?;
+
+        CmdRef::parse(cmd_nibble, usize::from(length), data)
+    }
+
+    pub fn is_control(&self) -> bool {
+        match self {
+            CmdRef::Owned(cmd) => cmd.is_control(),
+            _ => false
+        }
+    }
+
+    pub fn into_owned(self) -> Cmd {
+        match self {
+            CmdRef::RecordRSSI { position, record_count, records } =>
+                Cmd::RecordRSSI { position, record_count, records: records.collect() },
+            CmdRef::PictureChunk { position, offset, total_size, data } =>
+                Cmd::PictureChunk { position, offset, total_size, data: data.to_vec() },
+            CmdRef::Owned(cmd) => cmd
+        }
+    }
+}
+
+impl<'a> FrameRef<'a> {
+    /// Same as `Frame::parse`, without copying anything out of `bytes`
+    pub fn parse(bytes: &'a [u8]) -> Result<(FrameRef<'a>, u16), FrameError> {
+        let (cmd_nibble, length, frame_length, frame_id) = Frame::parse_header(bytes)?;
+
+        let cmd = CmdRef::parse_frame(cmd_nibble, length, frame_length, bytes)?;
+
+        let consumed = FRAME_HEADER_SIZE as u16 + length;
+
+        let checksum = Checksum::from_int( byte_slice_to_u16(bytes.get(consumed as usize..).unwrap_or_default())?);
+
+        if checksum.check(&bytes[..consumed as usize]) {
+            Ok((FrameRef {cmd, frame_id, checksum: Some(checksum)}, consumed + CHECKSUM_SIZE as u16))
+        } else {
+            Err(FrameError::InvalidChecksum)
+        }
+    }
+
+    /// Same as `Frame::scan`, without copying anything out of `bytes`
+    pub fn scan(bytes: &'a [u8]) -> FrameScan<FrameRef<'a>> {
+        // Earliest header that looks valid but whose body hasn't fully arrived. A lost byte can turn
+        // garbage into a header with a long length, so a complete frame further on still wins over it
+        let mut first_incomplete: Option<usize> = None;
+
+        for offset in 0..bytes.len() {
+            let candidate = &bytes[offset..];
+
+            // Not even a full header left, it may still be the start of a frame
+            let (cmd_nibble, length, frame_length, _) = match Frame::parse_header(candidate) {
+                Ok(header) => header,
+                Err(_) => return FrameScan::Incomplete { skipped: first_incomplete.unwrap_or(offset) }
+            };
+
+            if Cmd::check_length(cmd_nibble, usize::from(length)).is_err() {
+                continue;
+            }
+
+            if candidate.len() < frame_length as usize {
+                first_incomplete.get_or_insert(offset);
+                continue;
+            }
+
+            if let Ok((frame, consumed)) = FrameRef::parse(candidate) {
+                return FrameScan::Found { frame, skipped: offset, consumed: consumed as usize };
+            }
+        }
+
+        FrameScan::Incomplete { skipped: first_incomplete.unwrap_or(bytes.len()) }
+    }
+
+    pub fn into_frame(self) -> Frame {
+        Frame { cmd: self.cmd.into_owned(), frame_id: self.frame_id, checksum: self.checksum }
+    }
+
+    /// Same as `Frame::is_reassembled`
+    pub fn is_reassembled(&self) -> bool {
+        self.checksum.is_none()
+    }
+
+    pub fn get_cmd(&self) -> &CmdRef<'a> {
+        &self.cmd
+    }
+
+    pub fn into_cmd(self) -> CmdRef<'a> {
+        self.cmd
+    }
+
+    pub fn get_frame_id(&self) -> u32 {
+        self.frame_id
+    }
+}
+
 impl Frame {
     pub fn parse_header(bytes: &[u8]) -> Result <(u8, u16, u16, u32), FrameError> {
         if bytes.is_empty() {
@@ -672,33 +2076,35 @@ impl Frame {
     }
 
     pub fn parse(bytes: &[u8]) -> Result<(Frame, u16), FrameError> {
-        let (cmd_nibble, length, frame_length, frame_id) = Self::parse_header(bytes)?;
-
-        let cmd = Cmd::parse(cmd_nibble, length, frame_length, bytes)?;
-
-        let consumed = FRAME_HEADER_SIZE as u16 + length;
-
-        let checksum = Checksum::from_int( byte_slice_to_u16(&bytes[consumed as usize..])?);
+        FrameRef::parse(bytes).map(|(frame, consumed)| (frame.into_frame(), consumed))
+    }
 
-        if checksum.check(&bytes[0..consumed as usize]) {
-            Ok((Frame {cmd, frame_id, checksum}, consumed + CHECKSUM_SIZE as u16))
-        } else {
-            Err(FrameError::InvalidChecksum)
+    /// Looks for the first valid frame in `bytes`, skipping anything in front of it that doesn't
+    /// have both a plausible header and a matching CRC16. Used to get back in sync after lost or
+    /// corrupted bytes, instead of reading every following frame misaligned
+    pub fn scan(bytes: &[u8]) -> FrameScan {
+        match FrameRef::scan(bytes) {
+            FrameScan::Found { frame, skipped, consumed } => FrameScan::Found { frame: frame.into_frame(), skipped, consumed },
+            FrameScan::Incomplete { skipped } => FrameScan::Incomplete { skipped }
         }
     }
 
     pub fn from_cmd(cmd: Cmd, frame_id: u32) -> Result<Frame, FrameError> {
         let mut body    : Vec<u8> = cmd.as_bytes()?;
+        if body.len() > MAX_BODY_SIZE {
+            return Err(FrameError::LengthValueOutOfRange);
+        }
+
         let mut header  : Vec<u8> = Frame::header_as_bytes(cmd.as_int()?, body.len() as u16, frame_id).to_vec();
 
         header.append(&mut body);
 
-        let checksum = Checksum::from_bytes(&header);
+        let checksum = Some(Checksum::from_bytes(&header));
         Ok(Frame { cmd, frame_id, checksum })
     }
 
     pub fn from_components(cmd: Cmd, frame_id: u32, checksum: Checksum) -> Result<Frame, FrameError> {
-        Ok(Frame{ cmd, frame_id, checksum })
+        Ok(Frame{ cmd, frame_id, checksum: Some(checksum) })
     }
 
     fn header_as_bytes(cmd_nibble: u8, length: u16, frame_id: u32) ->[u8; FRAME_HEADER_SIZE] {
@@ -708,8 +2114,16 @@ impl Frame {
 
     pub fn as_bytes(&self) -> Result<Vec<u8>, FrameError> {
         let mut body    : Vec<u8> = self.cmd.as_bytes()?;
-        let mut checksum: Vec<u8> = self.checksum.as_bytes().to_vec(); 
+        if body.len() > MAX_BODY_SIZE {
+            return Err(FrameError::LengthValueOutOfRange);
+        }
+
         let mut header  : Vec<u8> = Frame::header_as_bytes(self.cmd.as_int()?, body.len() as u16, self.frame_id).to_vec();
+        let mut checksum: Vec<u8> = match &self.checksum {
+            Some(checksum) => checksum.as_bytes().to_vec(),
+            // A reassembled frame has no CRC of its own, it gets one when sent as a whole
+            None           => Checksum::from_bytes(&[header.as_slice(), body.as_slice()].concat()).as_bytes().to_vec()
+        };
 
         let mut result : Vec<u8> = Vec::with_capacity(FRAME_HEADER_SIZE + body.len() + CHECKSUM_SIZE);
 
@@ -720,7 +2134,16 @@ impl Frame {
         Ok(result)
     }
 
+    /// `true` when put back together from fragments rather than read off the line
+    pub fn is_reassembled(&self) -> bool {
+        self.checksum.is_none()
+    }
+
     pub fn get_cmd(&self) -> &Cmd {
         &self.cmd
     }
+
+    pub fn get_frame_id(&self) -> u32 {
+        self.frame_id
+    }
 }
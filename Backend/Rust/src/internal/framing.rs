@@ -0,0 +1,163 @@
+// own crates
+use crate::internal::frame_type::{CHECKSUM_SIZE, FRAME_HEADER_SIZE, MAX_BODY_SIZE};
+
+const COBS_DELIMITER: u8 = 0x00;
+
+// RFC 1055
+const SLIP_END    : u8 = 0xC0;
+const SLIP_ESC    : u8 = 0xDB;
+const SLIP_ESC_END: u8 = 0xDC;
+const SLIP_ESC_ESC: u8 = 0xDD;
+
+const MAX_FRAME_SIZE: usize = FRAME_HEADER_SIZE + MAX_BODY_SIZE + CHECKSUM_SIZE;
+
+/// How frames are delimited on the line. Raw frames are found by their header and CRC alone, the
+/// others wrap every frame in a packet with an unambiguous end, so noise never runs into a frame
+#[derive(PartialEq, Debug, Clone, Copy, Default)]
+pub enum Framing {
+    #[default]
+    Raw,
+    /// Consistent Overhead Byte Stuffing, each packet ends with a 0x00
+    Cobs,
+    /// Serial Line IP, each packet starts and ends with a 0xC0
+    Slip
+}
+
+impl Framing {
+    pub fn from_name(name: &str) -> Option<Framing> {
+        match name {
+            "raw"  => Some(Framing::Raw ),
+            "cobs" => Some(Framing::Cobs),
+            "slip" => Some(Framing::Slip),
+            _ => None
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Framing::Raw  => "raw",
+            Framing::Cobs => "cobs",
+            Framing::Slip => "slip",
+        }
+    }
+
+    /// Longest packet a frame can take on the line. Anything longer without a delimiter is noise
+    pub fn max_packet_size(&self) -> usize {
+        match self {
+            Framing::Raw  => MAX_FRAME_SIZE,
+            Framing::Cobs => MAX_FRAME_SIZE + MAX_FRAME_SIZE.div_ceil(254) + 1,
+            Framing::Slip => 2 * MAX_FRAME_SIZE + 2,
+        }
+    }
+
+    /// Wraps the bytes of a frame the way they go on the line
+    pub fn encode(&self, frame: &[u8]) -> Vec<u8> {
+        match self {
+            Framing::Raw  => frame.to_vec(),
+            Framing::Cobs => cobs_encode(frame),
+            Framing::Slip => slip_encode(frame),
+        }
+    }
+
+    /// Looks for the end of the first packet in `bytes`. Returns its contents, or None if they don't
+    /// decode, along with how many bytes it took, delimiter included. Raw frames have no packets
+    pub fn next_packet(&self, bytes: &[u8]) -> Option<(Option<Vec<u8>>, usize)> {
+        let delimiter = match self {
+            Framing::Raw  => return None,
+            Framing::Cobs => COBS_DELIMITER,
+            Framing::Slip => SLIP_END,
+        };
+
+        let end = bytes.iter().position(|byte| *byte == delimiter)?;
+        let packet = match self {
+            Framing::Cobs => cobs_decode(&bytes[..end]),
+            _             => slip_decode(&bytes[..end]),
+        };
+
+        Some((packet, end + 1))
+    }
+}
+
+fn cobs_encode(bytes: &[u8]) -> Vec<u8> {
+    let mut result = Vec::with_capacity(bytes.len() + bytes.len() / 254 + 2);
+
+    // Every block starts with the distance to the next zero, which it stands in for. Blocks top
+    // out at 254 bytes, a full one isn't followed by a zero
+    let mut code_index = 0;
+    let mut code = 1u8;
+    result.push(0);
+
+    for byte in bytes {
+        if *byte != COBS_DELIMITER {
+            result.push(*byte);
+            code += 1;
+        }
+
+        if *byte == COBS_DELIMITER || code == 0xFF {
+            result[code_index] = code;
+            code_index = result.len();
+            result.push(0);
+            code = 1;
+        }
+    }
+
+    result[code_index] = code;
+    result.push(COBS_DELIMITER);
+    result
+}
+
+fn cobs_decode(bytes: &[u8]) -> Option<Vec<u8>> {
+    let mut result = Vec::with_capacity(bytes.len());
+    let mut index = 0;
+
+    while index < bytes.len() {
+        let code = usize::from(bytes[index]);
+        if code == 0 {
+            return None;
+        }
+
+        result.extend_from_slice(bytes.get(index + 1..index + code)?);
+        index += code;
+
+        if code < 0xFF && index < bytes.len() {
+            result.push(COBS_DELIMITER);
+        }
+    }
+
+    Some(result)
+}
+
+fn slip_encode(bytes: &[u8]) -> Vec<u8> {
+    let mut result = Vec::with_capacity(bytes.len() + 2);
+
+    // The leading END flushes whatever noise came before, the receiver drops it as an empty or bad packet
+    result.push(SLIP_END);
+    for byte in bytes {
+        match *byte {
+            SLIP_END => result.extend_from_slice(&[SLIP_ESC, SLIP_ESC_END]),
+            SLIP_ESC => result.extend_from_slice(&[SLIP_ESC, SLIP_ESC_ESC]),
+            byte     => result.push(byte)
+        }
+    }
+    result.push(SLIP_END);
+
+    result
+}
+
+fn slip_decode(bytes: &[u8]) -> Option<Vec<u8>> {
+    let mut result = Vec::with_capacity(bytes.len());
+    let mut bytes = bytes.iter();
+
+    while let Some(byte) = bytes.next() {
+        match *byte {
+            SLIP_ESC => match bytes.next() {
+                Some(&SLIP_ESC_END) => result.push(SLIP_END),
+                Some(&SLIP_ESC_ESC) => result.push(SLIP_ESC),
+                _ => return None
+            },
+            byte => result.push(byte)
+        }
+    }
+
+    Some(result)
+}
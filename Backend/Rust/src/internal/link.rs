@@ -0,0 +1,105 @@
+// std crates
+use std::time::{Duration, Instant};
+
+pub const DEFAULT_HEARTBEAT_INTERVAL: Duration = Duration::from_secs(2);
+pub const DEFAULT_LIVENESS_TIMEOUT  : Duration = Duration::from_secs(10);
+
+// Heartbeats missed in a row before the link counts as degraded
+const MISSED_HEARTBEATS_DEGRADED: u32 = 2;
+
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub enum LinkState {
+    Up,
+    /// Heartbeats are late, but not for long enough to give up on the remote
+    Degraded,
+    Down
+}
+
+impl LinkState {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            LinkState::Up       => "up",
+            LinkState::Degraded => "degraded",
+            LinkState::Down     => "down",
+        }
+    }
+}
+
+/// Keeps track of when the remote was last heard from, and when we last sent it anything. Both ends
+/// send a heartbeat once they've been quiet for `heartbeat_interval`, so a remote quiet for much
+/// longer than that is gone
+#[derive(PartialEq, Debug, Clone)]
+pub struct LinkMonitor {
+    heartbeat_interval: Duration,
+    liveness_timeout  : Duration,
+
+    /// Whether the remote sends heartbeats at all. Firmware that doesn't can be quiet for as long as it likes
+    heartbeats: bool,
+
+    last_rx: Option<Instant>,
+
+    /// Starts out as when the link was set up, the first heartbeat is due an interval after that
+    last_tx: Instant
+}
+
+impl LinkMonitor {
+    pub fn new(heartbeat_interval: Duration, liveness_timeout: Duration) -> LinkMonitor {
+        LinkMonitor { heartbeat_interval, liveness_timeout, heartbeats: true, last_rx: None, last_tx: Instant::now() }
+    }
+
+    pub fn heartbeat_interval(&self) -> Duration {
+        self.heartbeat_interval
+    }
+
+    pub fn liveness_timeout(&self) -> Duration {
+        self.liveness_timeout
+    }
+
+    pub fn set_heartbeats(&mut self, heartbeats: bool) {
+        self.heartbeats = heartbeats;
+    }
+
+    pub fn heard(&mut self) {
+        self.last_rx = Some(Instant::now());
+    }
+
+    /// How long it's been since the remote was last heard from, heartbeats included
+    pub fn quiet(&self) -> Option<Duration> {
+        self.last_rx.map(|last_rx| last_rx.elapsed())
+    }
+
+    pub fn sent(&mut self) {
+        self.last_tx = Instant::now();
+    }
+
+    /// Whether we've been quiet long enough that the remote needs a heartbeat to know we're still here
+    pub fn heartbeat_due(&self) -> bool {
+        self.last_tx.elapsed() >= self.heartbeat_interval
+    }
+
+    pub fn state(&self) -> LinkState {
+        self.state_at(Instant::now())
+    }
+
+    pub fn state_at(&self, now: Instant) -> LinkState {
+        let quiet = match self.last_rx {
+            Some(last_rx) => now.saturating_duration_since(last_rx),
+            None => return LinkState::Down
+        };
+
+        let degraded_after = (self.heartbeat_interval * MISSED_HEARTBEATS_DEGRADED).min(self.liveness_timeout);
+        if !self.heartbeats || quiet < degraded_after {
+            LinkState::Up
+        } else if quiet < self.liveness_timeout {
+            LinkState::Degraded
+        } else {
+            LinkState::Down
+        }
+    }
+}
+
+impl Default for LinkMonitor {
+    fn default() -> Self {
+        LinkMonitor::new(DEFAULT_HEARTBEAT_INTERVAL, DEFAULT_LIVENESS_TIMEOUT)
+    }
+}
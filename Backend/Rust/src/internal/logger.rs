@@ -55,7 +55,7 @@ pub struct Log {
     msg: String
 }
 
-#[derive(Serialize, Deserialize)]
+#[derive(Serialize, Deserialize, Default)]
 #[serde(crate = "rocket::serde")]
 pub struct Logger {
     logs: Vec<Log>
@@ -1,7 +1,20 @@
 pub mod frame_ops;
+pub mod frame_codec;
 pub mod frame_type;
 pub mod procs;
 pub mod utils;
 pub mod logger;
 pub mod threading_comm;
-pub mod config;
\ No newline at end of file
+pub mod config;
+pub mod transport;
+pub mod picture;
+pub mod trace;
+pub mod dissect;
+#[cfg(test)]
+pub mod simulator;
+pub mod link;
+pub mod framing;
+pub mod position;
+pub mod session;
+pub mod discovery;
+pub mod registry;
\ No newline at end of file
@@ -0,0 +1,117 @@
+// std crates
+use std::collections::{BTreeMap, HashMap};
+
+// own crates
+use crate::internal::frame_type::*;
+
+// A picture bigger than this is either corrupt or not a picture, don't buffer it
+pub const MAX_PICTURE_SIZE: usize = 8 * 1024 * 1024;
+
+// Each of them can hold up to a whole picture. An ESP32 that starts pictures without finishing them
+// only gets to leave a few behind
+pub const MAX_PENDING_PICTURES: usize = 4;
+
+/// Chunks of one picture received so far, keyed by their offset
+struct PartialPicture {
+    total_size: usize,
+    chunks    : BTreeMap<u32, Vec<u8>>,
+
+    /// When it was started, counted in pictures. The oldest one makes way first
+    started   : u64
+}
+
+impl PartialPicture {
+    fn is_complete(&self) -> bool {
+        let mut end = 0;
+        for (offset, chunk) in &self.chunks {
+            if *offset as usize > end {
+                return false;
+            }
+            end = end.max(*offset as usize + chunk.len());
+        }
+
+        end == self.total_size
+    }
+
+    fn into_bytes(self) -> Vec<u8> {
+        let mut picture = vec![0u8; self.total_size];
+        for (offset, chunk) in self.chunks {
+            let offset = offset as usize;
+            picture[offset..offset + chunk.len()].copy_from_slice(&chunk);
+        }
+
+        picture
+    }
+}
+
+/// Puts pictures sent as `Cmd::PictureChunk`s back together. Each position holds one picture at
+/// a time, chunks can come in any order, and the same chunk more than once
+#[derive(Default)]
+pub struct PictureAssembler {
+    pending: HashMap<Position, PartialPicture>,
+    started: u64
+}
+
+impl PictureAssembler {
+    pub fn new() -> PictureAssembler {
+        PictureAssembler::default()
+    }
+
+    /// Adds a chunk, returning the whole picture once its last missing chunk comes in
+    pub fn add_chunk(&mut self, position: &Position, offset: u32, total_size: u32, data: &[u8]) -> Result<Option<Vec<u8>>, FrameError> {
+        let total_size = total_size as usize;
+        if total_size > MAX_PICTURE_SIZE || offset as usize + data.len() > total_size {
+            return Err(FrameError::ValueOutOfRange);
+        }
+
+        if !self.pending.contains_key(position) && self.pending.len() >= MAX_PENDING_PICTURES {
+            let oldest = self.pending.iter().min_by_key(|(_, partial)| partial.started).map(|(position, _)| position.clone());
+            if let Some(oldest) = oldest {
+                self.pending.remove(&oldest);
+            }
+        }
+
+        let started = self.started;
+        let partial = self.pending.entry(position.clone())
+            .or_insert_with(|| PartialPicture { total_size, chunks: BTreeMap::new(), started });
+
+        // A different size means a new picture was taken at this position, the old one is abandoned
+        if partial.total_size != total_size {
+            *partial = PartialPicture { total_size, chunks: BTreeMap::new(), started };
+        }
+        if partial.started == started {
+            self.started += 1;
+        }
+
+        partial.chunks.insert(offset, data.to_vec());
+
+        if !partial.is_complete() {
+            return Ok(None);
+        }
+
+        Ok(self.pending.remove(position).map(PartialPicture::into_bytes))
+    }
+
+    pub fn pending(&self) -> usize {
+        self.pending.len()
+    }
+}
+
+/// Splits a picture into chunks that fit a frame each. The ESP32's side of the transfer, sent from here by the simulator
+pub fn chunk_picture(position: &Position, picture: &[u8]) -> Result<Vec<Cmd>, FrameError> {
+    if picture.len() > MAX_PICTURE_SIZE {
+        return Err(FrameError::ValueOutOfRange);
+    }
+
+    let total_size = picture.len() as u32;
+    let chunks = picture.chunks(MAX_PICTURE_CHUNK_SIZE).enumerate()
+        .map(|(index, data)| Cmd::PictureChunk {
+            position  : position.clone(),
+            offset    : (index * MAX_PICTURE_CHUNK_SIZE) as u32,
+            total_size,
+            data      : data.to_vec()
+        })
+        .collect();
+
+    Ok(chunks)
+}
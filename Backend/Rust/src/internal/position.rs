@@ -0,0 +1,59 @@
+// own crates
+use crate::internal::frame_type::*;
+
+/// Where the ESP32 reported being, when that's not where the host expected it
+#[derive(PartialEq, Debug, Clone)]
+pub enum PositionMismatch {
+    /// The first position reported after a move isn't the one the rig was sent to
+    Unexpected { expected: Position, reported: Position },
+
+    /// The position isn't a step of the grid from `SetParams`
+    OffGrid    { reported: Position }
+}
+
+/// Keeps track of the rig's pose from the host's side: where it was sent with `SetParams` and
+/// `SetPosition`, and where the ESP32 says it measured since. It's what the ESP32 is told when it
+/// loses its position and asks with `RequestPosition`
+#[derive(Default, Debug)]
+pub struct PositionTracker {
+    grid    : Option<Grid>,
+    current : Option<Position>,
+
+    /// Where the rig was sent, until the ESP32 reports from there
+    expected: Option<Position>
+}
+
+impl PositionTracker {
+    /// A capture starts at the origin of its grid, that's where `SetParams` sends the rig
+    pub fn new(grid: Option<Grid>) -> PositionTracker {
+        let mut tracker = PositionTracker { grid, current: None, expected: None };
+        if let Some(origin) = tracker.grid.as_ref().map(|grid| grid.origin().clone()) {
+            tracker.command(origin);
+        }
+
+        tracker
+    }
+
+    /// The rig was sent to `position`
+    pub fn command(&mut self, position: Position) {
+        self.current  = Some(position.clone());
+        self.expected = Some(position);
+    }
+
+    /// The ESP32 says it's at `position`. It's taken at its word, what didn't add up is returned
+    pub fn report(&mut self, position: &Position) -> Option<PositionMismatch> {
+        let expected = self.expected.take();
+        self.current = Some(position.clone());
+
+        match expected {
+            Some(expected) if expected != *position => Some(PositionMismatch::Unexpected { expected, reported: position.clone() }),
+            _ if self.grid.as_ref().is_some_and(|grid| grid.step_of(position).is_none()) => Some(PositionMismatch::OffGrid { reported: position.clone() }),
+            _ => None
+        }
+    }
+
+    /// Where the rig last was, as far as the host knows
+    pub fn current(&self) -> Option<&Position> {
+        self.current.as_ref()
+    }
+}
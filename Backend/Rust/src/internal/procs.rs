@@ -1,34 +1,56 @@
 // std crates
 use std::sync::{Arc, Mutex};
+use std::time::Duration;
 
 // External crates
-use serial::prelude::*;
 use rocket::serde::json;
 
 // own crates
 use crate::internal::frame_ops::{self, tx_frame_blocking};
 use crate::internal::logger::{Severity, Logger};
 use crate::internal::frame_type::*;
+use crate::internal::transport::{FrameTransport, HANDSHAKE_TIMEOUT};
+use crate::internal::framing::Framing;
+
+// SoTs sent in the legacy handshake before the ESP32 is taken to be silent
+const LEGACY_HANDSHAKE_ATTEMPTS: u32 = 3;
+
+pub fn proc_tx_reset<T: FrameTransport>(port: &mut T, frame_stack: &mut FrameStack) -> Result<(), FrameError> {
+    proc_tx_reset_at(port, frame_stack, None)
+}
+
+/// Same as `proc_tx_reset`, with the ESP32 starting its walk of the grid at `start` instead of the
+/// origin. That's how an interrupted capture is picked up where it left off
+pub fn proc_tx_reset_at<T: FrameTransport>(port: &mut T, frame_stack: &mut FrameStack, start: Option<&Position>) -> Result<(), FrameError> {
 
-pub fn proc_tx_reset<T: SerialPort>(port: &mut T, frame_stack: &mut FrameStack) -> Result<(), FrameError> {
-    
     // tx SetParams
+    let position  = Position::from_degrees(10f32, 0f32)?;
+    let step_size = StepSize::from_degrees(20f32, 20f32)?;
     let frame = Frame::from_cmd(
         Cmd::SetParams {
-            position: Position::from_degrees(10f32, 0f32)?,
-            step_size: StepSize::from_degrees(20f32, 20f32)?,
+            position : position.clone(),
+            step_size: step_size.clone(),
             measurements_per_step: 1
         }, 
         frame_stack.curr_id()
     )?;
     frame_ops::tx_frame_blocking(frame, frame_stack, port)?;
 
+    // Batched records come as steps on this grid
+    frame_stack.set_grid(Some(Grid::new(position, step_size)));
+
     // rx Ack
-    let _ = frame_ops::rx_frame_blocking_expect(frame_stack, port, Cmd::Ack { frame_id: 1 }.as_int()?)?;
+    let _ = frame_ops::rx_frame_blocking_expect(frame_stack, port, Cmd::Ack { frame_id: 1, info: None }.as_int()?)?;
 
     // rx Ready
     let _ = frame_ops::rx_frame_blocking_expect(frame_stack, port, Cmd::Ready.as_int()?)?;
 
+    // tx SetPosition
+    if let Some(start) = start {
+        let frame = Frame::from_cmd(Cmd::SetPosition { position: start.clone() }, frame_stack.curr_id())?;
+        tx_frame_blocking(frame, frame_stack, port)?;
+    }
+
     // tx ready
     let frame = Frame::from_cmd(Cmd::Ready, frame_stack.curr_id())?;
     tx_frame_blocking(frame, frame_stack, port)?;
@@ -37,44 +59,178 @@ pub fn proc_tx_reset<T: SerialPort>(port: &mut T, frame_stack: &mut FrameStack)
 }
 
 
-pub fn proc_tx_handshake<T: SerialPort>(port: &mut T, frame_stack: &mut FrameStack, logger : Arc<Mutex<Logger>>) -> Result<(), FrameError> {
+/// Performs the handshake, settling on a protocol with the ESP32 in the StartOfTransmission and its Ack.
+/// Returns the protocol agreed on, which is also left in the frame stack
+pub fn proc_tx_handshake<T: FrameTransport>(port: &mut T, frame_stack: &mut FrameStack, logger : Arc<Mutex<Logger>>) -> Result<ProtocolInfo, FrameError> {
     let mut handshake_frame_stack = FrameStack::new();
+    handshake_frame_stack.set_trace_recorder(frame_stack.trace_recorder().cloned());
+    handshake_frame_stack.set_framing(frame_stack.framing());
+
+    let protocol = match proc_tx_sot(port, &mut handshake_frame_stack, frame_stack) {
+        Ok(protocol) => protocol,
+        // Neither gets any better with the legacy handshake
+        Err(e @ (FrameError::UnsupportedProtocolVersion | FrameError::ConnectionClosed)) => return Err(e),
+        Err(_) => {
+            if let Ok(mut handle) = logger.lock() {
+                handle.log(Severity::INFO, "ESP32 didn't answer the StartOfTransmission with our protocol, falling back to the legacy handshake");
+            }
+
+            // tx SoT
+            let sot = Frame::from_cmd(Cmd::StartOfTransmission { info: None }, 0)?;
+            frame_ops::tx_frame_blocking(sot, &mut handshake_frame_stack, port)?;
+
+            // rx Ack
+            let mut attempts = 1;
+            while let Err(e) = frame_ops::rx_frame(frame_stack, port) {
+                // Retransmit until we get a response, or it's clear there won't be one
+                if e == FrameError::ConnectionClosed {
+                    return Err(e);
+                }
+                if attempts >= LEGACY_HANDSHAKE_ATTEMPTS {
+                    return Err(FrameError::TransmissionTimedOut);
+                }
+                attempts += 1;
+
+                if let Ok(mut handle) = logger.lock() {
+                    handle.log(Severity::ERROR, "Failed to perform handshake; no answer. Trying again...");
+                }
+                let sot = Frame::from_cmd(Cmd::StartOfTransmission { info: None }, 0)?;
+                frame_ops::retx_frame_blocking(sot, frame_stack, port)?;
+            }
+
+            ProtocolInfo::legacy()
+        }
+    };
+    frame_stack.set_protocol(protocol.clone());
 
-    // tx SoT
-    let sot = Frame::from_cmd(Cmd::StartOfTransmission, 0)?;
-    frame_ops::tx_frame_blocking(sot, &mut handshake_frame_stack, port)?;
+    // tx Reset
+    let frame = Frame::from_cmd(Cmd::Reset, 1)?;
+    frame_ops::tx_frame_blocking(frame, &mut handshake_frame_stack, port)?;
 
     // rx Ack
-    while frame_ops::rx_frame(frame_stack, port).is_err() {
-        // Retransmit until we get a response
-        if let Ok(mut handle) = logger.lock() {
-            handle.log(Severity::ERROR, "Failed to perform handshake; no answer. Trying again...");
+    let _ = frame_ops::rx_frame_blocking_expect(frame_stack, port, Cmd::Ack { frame_id: 1, info: None }.as_int()?)?;
+
+    Ok(protocol)
+}
+
+/// Same as `proc_tx_handshake`, trying each of `baud_rates` in turn until the ESP32 answers at one.
+/// The line is left at that rate. A firmware we can't talk to won't answer any better at another
+pub fn proc_tx_handshake_fallback<T: FrameTransport>(port: &mut T, frame_stack: &mut FrameStack, logger : Arc<Mutex<Logger>>, baud_rates: &[u32]) -> Result<ProtocolInfo, FrameError> {
+    // Nothing to fall back to, the line stays at whatever rate it was opened at
+    if baud_rates.is_empty() {
+        return proc_tx_handshake(port, frame_stack, logger);
+    }
+
+    let mut result = Err(FrameError::TransmissionTimedOut);
+    for (i, baud_rate) in baud_rates.iter().enumerate() {
+        if i > 0 {
+            if let Ok(mut handle) = logger.lock() {
+                handle.log(Severity::WARNING, &format!("No handshake with the ESP32 ({:?}), falling back to {} baud", result, baud_rate));
+            }
+        }
+
+        port.set_baud_rate(*baud_rate)?;
+
+        // Whatever came in at the last rate is noise at this one
+        let pending = frame_stack.rx_buffer().len();
+        frame_stack.drop_rx_bytes(pending);
+
+        result = proc_tx_handshake(port, frame_stack, logger.clone());
+        match result {
+            Ok(_) | Err(FrameError::UnsupportedProtocolVersion) => return result,
+            Err(_) => {}
         }
-        let sot = Frame::from_cmd(Cmd::StartOfTransmission, 0)?;
-        frame_ops::retx_frame_blocking(sot, port)?;
     }
 
-    // tx Reset
-    let frame = Frame::from_cmd(Cmd::Reset, 1)?;
-    frame_ops::tx_frame_blocking(frame, &mut handshake_frame_stack, port)?;
+    result
+}
+
+/// Sends a StartOfTransmission and waits up to `timeout` for anything that reads as a frame. That's
+/// enough to tell an ESP32 is on the other end, the handshake proper comes after
+pub fn proc_tx_probe<T: FrameTransport>(port: &mut T, framing: Framing, timeout: Duration) -> Result<(), FrameError> {
+    let mut frame_stack = FrameStack::new();
+    frame_stack.set_framing(framing);
+
+    // tx SoT
+    let sot = Frame::from_cmd(Cmd::StartOfTransmission { info: None }, 0)?;
+    frame_ops::retx_frame_blocking(sot, &frame_stack, port)?;
+
+    // rx anything
+    let read_timeout = port.read_timeout();
+    port.set_read_timeout(timeout)?;
+    let answer = frame_ops::rx_frame(&mut frame_stack, port);
+    port.set_read_timeout(read_timeout)?;
+
+    answer.map(|_| ())
+}
+
+/// Sends a StartOfTransmission with the protocol this backend speaks, and settles on one with what the
+/// ESP32 answers in its Ack. Firmware from before the protocol info answers with a bare Ack, if at all
+fn proc_tx_sot<T: FrameTransport>(port: &mut T, handshake_frame_stack: &mut FrameStack, frame_stack: &mut FrameStack) -> Result<ProtocolInfo, FrameError> {
+    // tx SoT
+    let sot = Frame::from_cmd(Cmd::StartOfTransmission { info: Some(ProtocolInfo::host()) }, 0)?;
+    frame_ops::tx_frame_blocking(sot, handshake_frame_stack, port)?;
 
     // rx Ack
-    let _ = frame_ops::rx_frame_blocking_expect(frame_stack, port, Cmd::Ack { frame_id:1 }.as_int()?);
+    let read_timeout = port.read_timeout();
+    port.set_read_timeout(HANDSHAKE_TIMEOUT)?;
+    let answer = frame_ops::rx_frame(frame_stack, port);
+    port.set_read_timeout(read_timeout)?;
+
+    match answer?.get_cmd() {
+        Cmd::Ack { info: Some(info), .. } => ProtocolInfo::host().negotiate(info),
+        Cmd::Ack { info: None      , .. } => Ok(ProtocolInfo::legacy()),
+        _ => Err(FrameError::InvalidCommandSequence)
+    }
+}
 
-    Ok(())
+/// Sends a heartbeat if nothing else went out for a heartbeat interval, and the remote knows what one is.
+/// Returns whether it did
+pub fn proc_tx_heartbeat<T: FrameTransport>(port: &mut T, frame_stack: &mut FrameStack) -> Result<bool, FrameError> {
+    if !frame_stack.protocol().supports(CAP_HEARTBEAT) || !frame_stack.heartbeat_due() {
+        return Ok(false);
+    }
+
+    // Takes the next frame id like any other frame, so the remote can tell if it's lost
+    let frame = Frame::from_cmd(Cmd::Heartbeat, frame_stack.curr_id())?;
+    frame_ops::tx_frame_blocking(frame, frame_stack, port)?;
+
+    Ok(true)
 }
 
-pub fn proc_tx_request_retransmit<T: SerialPort>(port: &mut T, frame_stack: &mut FrameStack, frame_id_start: u32, frame_id_end: u32) -> Result<(), FrameError> {
+pub fn proc_tx_request_retransmit<T: FrameTransport>(port: &mut T, frame_stack: &mut FrameStack, frame_id_start: u32, frame_id_end: u32) -> Result<(), FrameError> {
     frame_ops::tx_new_frame(Cmd::RequestRetransmit { frame_id_start, frame_id_end }, frame_stack, port)
 }
 
 
-pub fn proc_tx_ack<T: SerialPort>(port: &mut T, frame_stack: &mut FrameStack, frame_id: u32) -> Result<(), FrameError> {
-    frame_ops::tx_new_frame(Cmd::Ack { frame_id }, frame_stack, port)
+pub fn proc_rx_request_retransmit<T: FrameTransport>(port: &mut T, frame_stack: &mut FrameStack, frame_id_start: u32, frame_id_end: u32, logger : Arc<Mutex<Logger>>) -> Result<(), FrameError> {
+    let frames = match frame_stack.requested_tx_frames(frame_id_start, frame_id_end) {
+        Ok(frames) => frames,
+        Err(e) => {
+            if let Ok(mut handle) = logger.lock() {
+                handle.log(Severity::ERROR, &format!("Can't retransmit frames [{} - {}), some were already acked and evicted", frame_id_start, frame_id_end));
+            }
+            return Err(e);
+        }
+    };
+
+    for frame in frames {
+        if let Ok(mut handle) = logger.lock() {
+            handle.log(Severity::DEBUG, &format!("Retransmit of frame with id={}", frame.get_frame_id()));
+        }
+        frame_ops::retx_frame_blocking(frame, frame_stack, port)?;
+    }
+
+    Ok(())
+}
+
+
+pub fn proc_tx_ack<T: FrameTransport>(port: &mut T, frame_stack: &mut FrameStack, frame_id: u32) -> Result<(), FrameError> {
+    frame_ops::tx_new_frame(Cmd::Ack { frame_id, info: None }, frame_stack, port)
 }
 
 
-pub fn proc_rx_request_ack<T: SerialPort>(port: &mut T, frame_stack: &mut FrameStack, logger : Arc<Mutex<Logger>>) -> Result<(), FrameError> {
+pub fn proc_rx_request_ack<T: FrameTransport>(port: &mut T, frame_stack: &mut FrameStack, logger : Arc<Mutex<Logger>>) -> Result<(), FrameError> {
     let rx_queue = frame_stack.get_rx_frame_queue();
     let mut rx_ids: Vec<u32> = Vec::with_capacity(rx_queue.len());
 
@@ -0,0 +1,110 @@
+// std crates
+use std::sync::{mpsc, Arc, Mutex};
+
+// External crates
+use rocket::tokio::task::JoinHandle;
+use tokio_util::sync::CancellationToken;
+
+// own crates
+use crate::internal::threading_comm::{Esp32Status, Message};
+
+/// One rig as the web thread sees it: the channels to the backend thread driving its ESP32, and
+/// what that thread reports about the link
+pub struct Rig {
+    id    : String,
+    tx    : mpsc::Sender<Message>,
+    rx    : Mutex<mpsc::Receiver<Message>>,
+    status: Arc<Mutex<Esp32Status>>
+}
+
+impl Rig {
+    pub fn new(id: &str, tx: mpsc::Sender<Message>, rx: mpsc::Receiver<Message>, status: Arc<Mutex<Esp32Status>>) -> Rig {
+        Rig { id: id.to_string(), tx, rx: Mutex::new(rx), status }
+    }
+
+    pub fn id(&self) -> &str {
+        &self.id
+    }
+
+    /// Orders for the rig's backend thread
+    pub fn sender(&self) -> &mpsc::Sender<Message> {
+        &self.tx
+    }
+
+    /// Answers from the rig's backend thread
+    pub fn receiver(&self) -> &Mutex<mpsc::Receiver<Message>> {
+        &self.rx
+    }
+
+    pub fn status(&self) -> &Arc<Mutex<Esp32Status>> {
+        &self.status
+    }
+}
+
+/// Every rig the backend drives, in the order they're configured. Requests that don't name a rig
+/// go to the first one
+#[derive(Default)]
+pub struct RigRegistry {
+    rigs: Vec<Rig>,
+
+    /// The tasks driving the rigs' sessions, and what tells them to stop
+    sessions: Mutex<Vec<JoinHandle<()>>>,
+    cancel  : CancellationToken
+}
+
+impl RigRegistry {
+    pub fn new() -> RigRegistry {
+        RigRegistry::default()
+    }
+
+    /// Adds `rig`, unless there's one by its id already. Returns whether it was added
+    pub fn register(&mut self, rig: Rig) -> bool {
+        if self.rigs.iter().any(|other| other.id == rig.id) {
+            return false;
+        }
+
+        self.rigs.push(rig);
+        true
+    }
+
+    /// The rig by `id`, or the first one without
+    pub fn get(&self, id: Option<&str>) -> Option<&Rig> {
+        match id {
+            Some(id) => self.rigs.iter().find(|rig| rig.id == id),
+            None => self.rigs.first()
+        }
+    }
+
+    pub fn rigs(&self) -> &[Rig] {
+        &self.rigs
+    }
+
+    /// Cancelled once the backend shuts down. Sessions check on it between reads
+    pub fn cancellation(&self) -> &CancellationToken {
+        &self.cancel
+    }
+
+    /// Keeps `session` to be waited on when shutting down
+    pub fn add_session(&self, session: JoinHandle<()>) {
+        if let Ok(mut sessions) = self.sessions.lock() {
+            sessions.push(session);
+        }
+    }
+
+    /// Stops every session and waits for them to wind down. A capture under way is aborted, so the
+    /// ESP32 is left stopped and what it sent so far is still stored
+    pub async fn shutdown(&self) {
+        self.cancel.cancel();
+        for rig in &self.rigs {
+            let _ = rig.sender().send(Message::AbortCapture);
+        }
+
+        let sessions = match self.sessions.lock() {
+            Ok(mut sessions) => std::mem::take(&mut *sessions),
+            Err(_) => return
+        };
+        for session in sessions {
+            let _ = session.await;
+        }
+    }
+}
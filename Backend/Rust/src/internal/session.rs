@@ -0,0 +1,74 @@
+// own crates
+use crate::internal::frame_type::*;
+
+/// Where the backend is in its exchange with the ESP32
+#[derive(PartialEq, Debug, Clone, Copy, Default)]
+pub enum SessionState {
+    /// No line to the ESP32, or it was lost
+    #[default]
+    Disconnected,
+    Handshaking,
+
+    /// Handshake done, the capture is being set up
+    Configuring,
+    Capturing,
+    Finished,
+    Failed
+}
+
+impl SessionState {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            SessionState::Disconnected => "disconnected",
+            SessionState::Handshaking  => "handshaking",
+            SessionState::Configuring  => "configuring",
+            SessionState::Capturing    => "capturing",
+            SessionState::Finished     => "finished",
+            SessionState::Failed       => "failed",
+        }
+    }
+
+    /// Whether a session in this state can go on to `next`. Staying put is always fine, a step that
+    /// didn't work out is tried again
+    pub fn can_enter(&self, next: SessionState) -> bool {
+        use SessionState::*;
+
+        match (self, next) {
+            _ if *self == next => true,
+            (_, Disconnected) => true,
+            (Disconnected | Finished | Failed, Handshaking) => true,
+            (Handshaking, Configuring) => true,
+            (Configuring, Capturing  ) => true,
+            (Capturing  , Finished   ) => true,
+            (Disconnected | Handshaking | Configuring | Capturing, Failed) => true,
+            _ => false
+        }
+    }
+
+    /// Whether the ESP32 can send `cmd` in this state. Link upkeep and logs can come at any time
+    /// once connected, commands only the host sends never do
+    pub fn accepts_cmd(&self, cmd: &Cmd) -> bool {
+        use SessionState::*;
+
+        match cmd {
+            Cmd::Ack { .. } | Cmd::RequestAck { .. } | Cmd::RequestRetransmit { .. } | Cmd::Heartbeat |
+            Cmd::Fragment { .. } | Cmd::TransmitLogs { .. } | Cmd::DeviceError { .. } => *self != Disconnected,
+
+            Cmd::Ready => matches!(self, Configuring | Capturing),
+
+            Cmd::AddSSID { .. } | Cmd::AddBSSID { .. } | Cmd::NetworkInfo { .. } | Cmd::RecordRSSI { .. } |
+            Cmd::RecordBatch { .. } | Cmd::TransmitPicture { .. } | Cmd::PictureChunk { .. } |
+            Cmd::RequestPosition | Cmd::EndOfTransmission => *self == Capturing,
+
+            _ => false
+        }
+    }
+
+    /// Same as `accepts_cmd`, for a command read in place
+    pub fn accepts(&self, cmd: &CmdRef) -> bool {
+        match cmd {
+            CmdRef::RecordRSSI { .. } | CmdRef::PictureChunk { .. } => *self == SessionState::Capturing,
+            CmdRef::Owned(cmd) => self.accepts_cmd(cmd)
+        }
+    }
+}
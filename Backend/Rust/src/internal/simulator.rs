@@ -0,0 +1,618 @@
+// std crates
+use std::collections::{HashMap, VecDeque};
+use std::io::{self, Read, Write};
+use std::sync::{mpsc, Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+// External crates
+use rocket::serde::json;
+
+// own crates
+use crate::internal::frame_ops::{retx_expired_frames, retx_frame_blocking, rx_frame, tx_frame_blocking, tx_new_frame};
+use crate::internal::frame_type::*;
+use crate::internal::logger::{Logger, Severity};
+use crate::internal::picture::chunk_picture;
+use crate::internal::link::DEFAULT_HEARTBEAT_INTERVAL;
+use crate::internal::framing::Framing;
+use crate::internal::procs::{proc_rx_request_ack, proc_rx_request_retransmit, proc_tx_heartbeat};
+use crate::internal::transport::{FrameTransport, READ_TIMEOUT};
+
+// Bounds the firmware walks the grid within, in degrees. It lets the pitch overshoot by a degree
+const PITCH_RANGE_DEG: (f64, f64) = (10.0, 80.0);
+const YAW_RANGE_DEG  : (f64, f64) = (0.0, 360.0);
+
+// How long to wait on an Ack once the window is full, before asking for it again
+const ACK_TIMEOUT : Duration = Duration::from_secs(1);
+const POLL_TIMEOUT: Duration = Duration::from_millis(1);
+
+// Positions sent together in a RecordBatch, a column of the grid holds about as many
+const RECORD_BATCH_POSITIONS: usize = 8;
+
+/// One end of an in-memory serial line. Whatever is written on one end is read on the other, and
+/// dropping an end closes the line for the other
+pub struct SimulatedLink {
+    rx     : mpsc::Receiver<Vec<u8>>,
+    tx     : mpsc::Sender<Vec<u8>>,
+    pending: VecDeque<u8>,
+    timeout: Duration
+}
+
+impl SimulatedLink {
+    /// Both ends of a new line
+    pub fn pair() -> (SimulatedLink, SimulatedLink) {
+        let (tx_host, rx_device) = mpsc::channel();
+        let (tx_device, rx_host) = mpsc::channel();
+
+        (SimulatedLink::new(rx_host, tx_host), SimulatedLink::new(rx_device, tx_device))
+    }
+
+    fn new(rx: mpsc::Receiver<Vec<u8>>, tx: mpsc::Sender<Vec<u8>>) -> SimulatedLink {
+        SimulatedLink { rx, tx, pending: VecDeque::new(), timeout: READ_TIMEOUT }
+    }
+}
+
+impl Read for SimulatedLink {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.pending.is_empty() {
+            match self.rx.recv_timeout(self.timeout) {
+                Ok(bytes) => self.pending.extend(bytes),
+                Err(mpsc::RecvTimeoutError::Timeout) => return Err(io::Error::from(io::ErrorKind::TimedOut)),
+                Err(mpsc::RecvTimeoutError::Disconnected) => return Ok(0)
+            }
+        }
+
+        let count = buf.len().min(self.pending.len());
+        for (byte, read) in buf.iter_mut().zip(self.pending.drain(..count)) {
+            *byte = read;
+        }
+
+        Ok(count)
+    }
+}
+
+impl Write for SimulatedLink {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if !buf.is_empty() {
+            self.tx.send(buf.to_vec()).map_err(|_| io::Error::from(io::ErrorKind::BrokenPipe))?;
+        }
+
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl FrameTransport for SimulatedLink {
+    fn set_read_timeout(&mut self, timeout: Duration) -> io::Result<()> {
+        self.timeout = timeout;
+        Ok(())
+    }
+}
+
+/// Made up access point the simulated ESP32 picks up
+#[derive(PartialEq, Debug, Clone)]
+pub struct SimulatedNetwork {
+    pub ssid   : String,
+    pub bssid  : [u8; 6],
+    pub channel: u8,
+    pub auth_mode: AuthMode,
+
+    /// RSSI when pointing straight at it, from the bottom of the pitch range
+    pub rssi   : i8,
+    pub yaw_deg: f64
+}
+
+impl SimulatedNetwork {
+    /// Loses a dB every 6° away from the network, and one more every 10° the scanner looks up
+    pub fn rssi_at(&self, position: &Position) -> i8 {
+        let offset = (raw_as_deg(position.yaw()) - self.yaw_deg).abs() % 360.0;
+        let offset = offset.min(360.0 - offset);
+        let loss   = offset / 6.0 + (raw_as_deg(position.pitch()) - PITCH_RANGE_DEG.0).max(0.0) / 10.0;
+
+        (f64::from(self.rssi) - loss).clamp(-127.0, 0.0) as i8
+    }
+}
+
+#[derive(PartialEq, Debug, Clone)]
+pub struct SimulatorConfig {
+    /// A legacy version makes it ignore a StartOfTransmission carrying protocol info, like firmware from before it did
+    pub protocol: ProtocolInfo,
+    pub networks: Vec<SimulatedNetwork>,
+
+    /// Sent from the first position, if the backend takes binary pictures
+    pub picture : Option<Vec<u8>>,
+    pub framing : Framing,
+
+    /// Reported once instead of measuring the position at that index of the grid, if the backend takes device errors
+    pub fault   : Option<(usize, DeviceErrorCode)>,
+
+    /// Loses power once before measuring the position at that index of the grid. Whatever it hadn't sent
+    /// yet is lost with it, and it asks the backend where it is before going on
+    pub brownout: Option<usize>,
+
+    /// Drops the line before measuring the position at that index of the grid, like a pulled cable
+    pub disconnect: Option<usize>,
+
+    /// How long the steppers take to get from one position to the next
+    pub step_delay: Duration
+}
+
+impl Default for SimulatorConfig {
+    fn default() -> Self {
+        let network = |ssid: &str, last: u8, channel, auth_mode, rssi, yaw_deg| SimulatedNetwork {
+            ssid : ssid.to_string(),
+            bssid: [0x24, 0x0A, 0xC4, 0x00, 0x00, last],
+            channel,
+            auth_mode,
+            rssi,
+            yaw_deg
+        };
+
+        Self {
+            protocol: ProtocolInfo::host(),
+            networks: vec![
+                network("Lab"  , 0x01,  1, AuthMode::Wpa2Enterprise, -40,   0.0),
+                network("Lab"  , 0x02, 36, AuthMode::Wpa2Enterprise, -55, 180.0),
+                network("Guest", 0x03,  6, AuthMode::Open          , -62,  90.0),
+            ],
+            picture : None,
+            framing : Framing::Raw,
+            fault   : None,
+            brownout: None,
+            disconnect: None,
+            step_delay: Duration::ZERO
+        }
+    }
+}
+
+/// Device side of the protocol, the way the ESP32 firmware speaks it: answers the handshake and the
+/// reset, walks the grid from `SetParams` and reports its made up networks at every position
+pub struct Esp32Simulator {
+    config     : SimulatorConfig,
+    frame_stack: FrameStack,
+    logger     : Arc<Mutex<Logger>>,
+    active     : bool,
+    paused     : bool,
+
+    /// The backend aborted the capture, rather than ending the transmission itself
+    aborted    : bool
+}
+
+impl Esp32Simulator {
+    pub fn new(config: SimulatorConfig) -> Esp32Simulator {
+        let mut frame_stack = FrameStack::new();
+        frame_stack.set_framing(config.framing);
+
+        Esp32Simulator {
+            config,
+            frame_stack,
+            logger     : Arc::new(Mutex::new(Logger::new())),
+            active     : false,
+            paused     : false,
+            aborted    : false
+        }
+    }
+
+    /// Runs a simulated ESP32 on its own thread, for a single capture. Returns the backend's end of the line
+    pub fn spawn(config: SimulatorConfig) -> SimulatedLink {
+        let (host, mut device) = SimulatedLink::pair();
+
+        thread::spawn(move || {
+            if let Err(e) = Esp32Simulator::new(config).run(&mut device) {
+                println!("[ERROR][SIM]Simulated ESP32 stopped with error '{:?}'", e);
+            }
+        });
+
+        host
+    }
+
+    /// Runs a device that sends every frame straight back once decoded, like the firmware's ping test
+    #[allow(dead_code)]
+    pub fn spawn_echo() -> SimulatedLink {
+        let (host, mut device) = SimulatedLink::pair();
+
+        thread::spawn(move || {
+            let mut rx = Vec::new();
+            let mut chunk = [0u8; 256];
+            loop {
+                // Frames are only decoded and sent back as they came, a loopback has no window or session to check them against
+                if let FrameScan::Found { frame, skipped, consumed } = Frame::scan(&rx) {
+                    rx.drain(..skipped + consumed);
+                    match frame.as_bytes() {
+                        Ok(bytes) if device.write_all(&bytes).is_ok() => continue,
+                        _ => break
+                    }
+                }
+
+                match device.read(&mut chunk) {
+                    Ok(0) => break,
+                    Ok(read) => rx.extend_from_slice(&chunk[..read]),
+                    Err(e) if e.kind() == io::ErrorKind::TimedOut => continue,
+                    Err(_) => break
+                }
+            }
+        });
+
+        host
+    }
+
+    pub fn run<T: FrameTransport>(&mut self, port: &mut T) -> Result<(), FrameError> {
+        self.handshake(port)?;
+        let (position, step_size, resume_at) = self.reset(port)?;
+
+        // Nobody's left to serve once the line is dropped
+        match self.capture(port, &position, &step_size, resume_at) {
+            Err(FrameError::ConnectionClosed) => return Ok(()),
+            result => result?
+        }
+
+        // The firmware stays up after a capture, keep serving the backend until it hangs up
+        loop {
+            match self.receive(port, READ_TIMEOUT) {
+                Ok(Some(frame)) => self.handle(frame, port)?,
+                Ok(None) => {},
+                Err(FrameError::ConnectionClosed) => return Ok(()),
+                Err(e) => return Err(e)
+            }
+        }
+    }
+
+    fn handshake<T: FrameTransport>(&mut self, port: &mut T) -> Result<(), FrameError> {
+        // Handshake frames don't go through the frame stack, the backend and the firmware send them from one of their own
+        let mut handshake_frame_stack = FrameStack::new();
+        handshake_frame_stack.set_framing(self.frame_stack.framing());
+
+        loop {
+            let frame = match rx_frame(&mut handshake_frame_stack, port) {
+                Ok(frame) => frame,
+                Err(FrameError::TransmissionTimedOut) => continue,
+                Err(e) => return Err(e)
+            };
+
+            match frame.get_cmd() {
+                // rx SoT, tx Ack with what we speak. Whether they agree is for the backend to say
+                Cmd::StartOfTransmission { info: Some(info) } if self.config.protocol.version() != LEGACY_PROTOCOL_VERSION => {
+                    self.tx_handshake(Cmd::Ack { frame_id: 0, info: Some(self.config.protocol.clone()) }, port)?;
+                    if let Ok(protocol) = self.config.protocol.negotiate(info) {
+                        self.frame_stack.set_protocol(protocol);
+                    }
+                },
+
+                // rx SoT, tx Ack
+                Cmd::StartOfTransmission { info: None } => {
+                    self.frame_stack.set_protocol(ProtocolInfo::legacy());
+                    self.tx_handshake(Cmd::Ack { frame_id: 0, info: None }, port)?;
+                },
+
+                // rx Reset, tx Ack
+                Cmd::Reset => return self.tx_handshake(Cmd::Ack { frame_id: frame.get_frame_id() + 1, info: None }, port),
+                _ => {}
+            }
+        }
+    }
+
+    /// Returns the grid from `SetParams`, and where on it to start if the backend picks an interrupted capture back up
+    fn reset<T: FrameTransport>(&mut self, port: &mut T) -> Result<(Position, StepSize, Option<Position>), FrameError> {
+        // rx SetParams
+        let (frame_id, position, step_size) = loop {
+            let frame = self.receive(port, READ_TIMEOUT)?;
+            if let Some(Cmd::SetParams { position, step_size, .. }) = frame.as_ref().map(Frame::get_cmd) {
+                break (frame.as_ref().map_or(0, Frame::get_frame_id), position.clone(), step_size.clone());
+            }
+        };
+
+        // tx Ack
+        self.tx_control(Cmd::Ack { frame_id: frame_id + 1, info: None }, port)?;
+
+        // tx Ready
+        self.send(Cmd::Ready, port)?;
+
+        // rx SetPosition, if resuming, and rx Ready. tx Ack for each, the backend only lets go of what we acked
+        let mut resume_at = None;
+        loop {
+            let frame = match self.receive(port, READ_TIMEOUT)? {
+                Some(frame) => frame,
+                None => continue
+            };
+
+            match frame.get_cmd() {
+                Cmd::SetPosition { position } => resume_at = Some(position.clone()),
+                Cmd::Ready => {},
+                _ => continue
+            }
+            self.tx_control(Cmd::Ack { frame_id: frame.get_frame_id() + 1, info: None }, port)?;
+
+            if frame.get_cmd() == &Cmd::Ready {
+                break;
+            }
+        }
+
+        self.active = true;
+        self.frame_stack.set_grid(Some(Grid::new(position.clone(), step_size.clone())));
+        Ok((position, step_size, resume_at))
+    }
+
+    fn capture<T: FrameTransport>(&mut self, port: &mut T, start: &Position, step_size: &StepSize, resume_at: Option<Position>) -> Result<(), FrameError> {
+        let logs = json::json!({ "logs": [{ "severity": Severity::INFO.value(), "msg": "Reset performed successfully" }] });
+        self.send(Cmd::TransmitLogs { logs }, port)?;
+
+        // Every network is announced before its first record. BSSIDs take the id of their SSID
+        let mut ssid_ids: HashMap<String, NetworkId> = HashMap::new();
+        for network in self.config.networks.clone() {
+            if !ssid_ids.contains_key(&network.ssid) {
+                let id = NetworkId::from_int(ssid_ids.len() as u32 + 1);
+                ssid_ids.insert(network.ssid.clone(), id.clone());
+                self.send(Cmd::AddSSID { id, ssid: SSID::new(network.ssid.clone()) }, port)?;
+            }
+
+            self.send(Cmd::AddBSSID { id: ssid_ids[&network.ssid].clone(), bssid: BSSID::new(network.bssid) }, port)?;
+
+            if self.frame_stack.protocol().supports(CAP_NETWORK_INFO) {
+                let info = NetworkInfo::from_components(BSSID::new(network.bssid), network.channel, network.auth_mode, network.ssid.is_empty())?;
+                self.send(Cmd::NetworkInfo { id: ssid_ids[&network.ssid].clone(), info }, port)?;
+            }
+        }
+
+        // Firmware that can batches records up, positions on the grid only take a couple of bytes that way
+        let batching = self.frame_stack.protocol().supports(CAP_RECORD_BATCHES);
+        let grid = Grid::new(start.clone(), step_size.clone());
+        let mut batch: Vec<GridRecords> = Vec::new();
+
+        let positions = grid_positions(start, step_size);
+        let mut index = match resume_at {
+            Some(resume_at) => positions.iter().position(|known| *known == resume_at).ok_or(FrameError::ValueOutOfRange)?,
+            None => 0
+        };
+        while let Some(position) = positions.get(index).cloned() {
+            // Whatever the backend sent while we were busy measuring
+            while let Some(frame) = self.receive(port, POLL_TIMEOUT)? {
+                self.handle(frame, port)?;
+            }
+
+            // Stay put while paused, the link is kept up all the same
+            while self.paused && self.active {
+                if let Some(frame) = self.receive(port, READ_TIMEOUT)? {
+                    self.handle(frame, port)?;
+                }
+            }
+
+            if !self.active {
+                return self.stop(port, batch);
+            }
+
+            thread::sleep(self.config.step_delay);
+
+            if self.config.disconnect == Some(index) {
+                return Err(FrameError::ConnectionClosed);
+            }
+
+            if self.config.brownout == Some(index) {
+                self.config.brownout = None;
+                batch.clear();
+
+                // Picks the walk back up from wherever the backend says we are
+                self.send(Cmd::RequestPosition, port)?;
+                index = match self.await_position(port)? {
+                    Some(position) => positions.iter().position(|known| *known == position).ok_or(FrameError::ValueOutOfRange)?,
+                    None => return self.stop(port, batch)
+                };
+                continue;
+            }
+
+            let fault = self.config.fault.filter(|(at, _)| *at == index && self.frame_stack.protocol().supports(CAP_DEVICE_ERRORS));
+            if let Some((_, code)) = fault {
+                // Whatever was measured before the fault still counts
+                if !batch.is_empty() {
+                    self.send(Cmd::RecordBatch { entries: std::mem::take(&mut batch) }, port)?;
+                }
+
+                self.config.fault = None;
+                if !self.report_fault(port, code, &position)? {
+                    return self.stop(port, batch);
+                }
+            }
+
+            if let Some(picture) = self.config.picture.clone().filter(|_| index == 0 && self.frame_stack.protocol().supports(CAP_BINARY_PICTURES)) {
+                for chunk in chunk_picture(&position, &picture)? {
+                    self.send(chunk, port)?;
+                }
+            }
+
+            let records = self.config.networks.iter()
+                .map(|network| Ok(Record::from_components(ssid_ids[&network.ssid].clone(), RSSI::from_int(network.rssi_at(&position))?)))
+                .collect::<Result<Vec<Record>, FrameError>>()?;
+            match grid.step_of(&position).filter(|_| batching) {
+                Some(step) => {
+                    batch.push(GridRecords::new(step, records));
+                    if batch.len() >= RECORD_BATCH_POSITIONS {
+                        self.send(Cmd::RecordBatch { entries: std::mem::take(&mut batch) }, port)?;
+                    }
+                },
+                None => self.send(Cmd::RecordRSSI { position, record_count: records.len() as u32, records }, port)?
+            }
+
+            // Ask for the Ack well before the window fills up, so we don't have to stop and wait on it
+            if self.frame_stack.tx_in_flight() >= self.frame_stack.tx_window_free() {
+                self.tx_control(Cmd::RequestAck { frame_id: self.frame_stack.curr_id() }, port)?;
+            }
+
+            index += 1;
+        }
+
+        if !batch.is_empty() {
+            self.send(Cmd::RecordBatch { entries: batch }, port)?;
+        }
+
+        self.send(Cmd::EndOfTransmission, port)
+    }
+
+    /// Ends a capture cut short. An abort still gets out what was measured, then the EndOfTransmission.
+    /// A backend that ended the transmission itself wants neither
+    fn stop<T: FrameTransport>(&mut self, port: &mut T, batch: Vec<GridRecords>) -> Result<(), FrameError> {
+        if !self.aborted {
+            return Ok(());
+        }
+
+        if !batch.is_empty() {
+            self.send(Cmd::RecordBatch { entries: batch }, port)?;
+        }
+
+        self.send(Cmd::EndOfTransmission, port)
+    }
+
+    /// Reports a fault at `position`, then waits on the backend to either send us back there or stop us.
+    /// Returns whether to go on measuring
+    fn report_fault<T: FrameTransport>(&mut self, port: &mut T, code: DeviceErrorCode, position: &Position) -> Result<bool, FrameError> {
+        let error = DeviceError::new(code, position.clone(), format!("Simulated {:?}", code));
+        self.send(Cmd::DeviceError { error }, port)?;
+
+        Ok(self.await_position(port)?.is_some())
+    }
+
+    /// Waits on the backend to send us somewhere with a `SetPosition`, which is returned. None if it ended
+    /// the transmission instead
+    fn await_position<T: FrameTransport>(&mut self, port: &mut T) -> Result<Option<Position>, FrameError> {
+        while self.active {
+            let frame = match self.receive(port, READ_TIMEOUT)? {
+                Some(frame) => frame,
+                None => continue
+            };
+
+            // rx SetPosition, tx Ack
+            if let Cmd::SetPosition { position } = frame.get_cmd() {
+                self.tx_control(Cmd::Ack { frame_id: frame.get_frame_id() + 1, info: None }, port)?;
+                return Ok(Some(position.clone()));
+            }
+
+            self.handle(frame, port)?;
+        }
+
+        Ok(None)
+    }
+
+    /// Next frame from the backend, if one comes within `timeout`. Acks are applied on the way in. Never
+    /// waits longer than a heartbeat interval, so the backend keeps hearing from us while we wait
+    fn receive<T: FrameTransport>(&mut self, port: &mut T, timeout: Duration) -> Result<Option<Frame>, FrameError> {
+        proc_tx_heartbeat(port, &mut self.frame_stack)?;
+        port.set_read_timeout(timeout.min(DEFAULT_HEARTBEAT_INTERVAL))?;
+
+        match rx_frame(&mut self.frame_stack, port) {
+            Ok(frame) => Ok(Some(frame)),
+            Err(FrameError::TransmissionTimedOut) => Ok(None),
+            Err(e) => Err(e)
+        }
+    }
+
+    fn handle<T: FrameTransport>(&mut self, frame: Frame, port: &mut T) -> Result<(), FrameError> {
+        match frame.get_cmd() {
+            Cmd::RequestAck        { .. } => proc_rx_request_ack(port, &mut self.frame_stack, self.logger.clone()),
+            Cmd::RequestRetransmit { frame_id_start, frame_id_end } => {
+                proc_rx_request_retransmit(port, &mut self.frame_stack, *frame_id_start, *frame_id_end, self.logger.clone())
+            },
+            Cmd::EndOfTransmission => {
+                self.active = false;
+                Ok(())
+            },
+
+            // rx Pause, Resume or Abort, tx Ack
+            Cmd::Pause | Cmd::Resume | Cmd::Abort => {
+                match frame.get_cmd() {
+                    Cmd::Pause  => self.paused = true,
+                    Cmd::Resume => self.paused = false,
+                    _ => {
+                        self.active  = false;
+                        self.aborted = true;
+                    }
+                }
+
+                self.tx_control(Cmd::Ack { frame_id: frame.get_frame_id() + 1, info: None }, port)
+            },
+            _ => Ok(())
+        }
+    }
+
+    /// Sends a data frame, waiting on the backend's Acks first while the window is full
+    fn send<T: FrameTransport>(&mut self, cmd: Cmd, port: &mut T) -> Result<(), FrameError> {
+        if self.frame_stack.tx_window_full() {
+            // tx RequestAck
+            let frame_id    = self.frame_stack.curr_id();
+            let request_ack = Frame::from_cmd(Cmd::RequestAck { frame_id }, frame_id)?;
+            tx_frame_blocking(request_ack.clone(), &mut self.frame_stack, port)?;
+
+            // rx Ack, or rx RequestRetransmit and tx the same RequestAck again, like the firmware does
+            while self.frame_stack.tx_window_full() {
+                retx_expired_frames(&mut self.frame_stack, port)?;
+
+                match self.receive(port, ACK_TIMEOUT)? {
+                    Some(frame) => {
+                        let retransmit = matches!(frame.get_cmd(), Cmd::RequestRetransmit { .. });
+                        self.handle(frame, port)?;
+                        if retransmit {
+                            retx_frame_blocking(request_ack.clone(), &self.frame_stack, port)?;
+                        }
+                    },
+                    None => retx_frame_blocking(request_ack.clone(), &self.frame_stack, port)?
+                }
+            }
+        }
+
+        tx_new_frame(cmd, &mut self.frame_stack, port)
+    }
+
+    /// Sends a control frame, whether the window is full or not. It takes the next frame id like any
+    /// other frame, the firmware numbers every frame it sends
+    fn tx_control<T: FrameTransport>(&mut self, cmd: Cmd, port: &mut T) -> Result<(), FrameError> {
+        tx_new_frame(cmd, &mut self.frame_stack, port)
+    }
+
+    /// Sends a handshake frame from a frame stack of its own, so it goes out with id 0 and the session's
+    /// numbering starts at 0 once the handshake is done, as it does on the firmware
+    fn tx_handshake<T: FrameTransport>(&mut self, cmd: Cmd, port: &mut T) -> Result<(), FrameError> {
+        let mut handshake_frame_stack = FrameStack::new();
+        handshake_frame_stack.set_trace_recorder(self.frame_stack.trace_recorder().cloned());
+        handshake_frame_stack.set_framing(self.frame_stack.framing());
+
+        tx_new_frame(cmd, &mut handshake_frame_stack, port)?;
+        self.frame_stack.mark_sent();
+        Ok(())
+    }
+}
+
+/// Positions the firmware visits for a `SetParams`, going up and down the pitch range one yaw step at a time
+pub fn grid_positions(start: &Position, step_size: &StepSize) -> Vec<Position> {
+    let pitches = steps(start.pitch(), step_size.pitch(), deg_as_raw(PITCH_RANGE_DEG.1 + 1.0));
+    // A full turn ends back where it started, don't measure that spot twice
+    let yaws    = steps(start.yaw()  , step_size.yaw()  , deg_as_raw(YAW_RANGE_DEG.1).saturating_sub(u64::from(step_size.yaw() / 2)));
+
+    let mut positions = Vec::with_capacity(pitches.len() * yaws.len());
+    for (column, yaw) in yaws.into_iter().enumerate() {
+        let column_pitches: Vec<u32> = if column % 2 == 0 { pitches.clone() } else { pitches.iter().rev().copied().collect() };
+        positions.extend(column_pitches.into_iter().map(|pitch| Position::from_int(pitch, yaw)));
+    }
+
+    positions
+}
+
+fn steps(start: u32, step: u32, end: u64) -> Vec<u32> {
+    // Without a step there's nowhere to go
+    if step == 0 {
+        return vec![start];
+    }
+
+    (0..)
+        .map(|i: u64| u64::from(start) + i * u64::from(step))
+        .take_while(|value| *value <= end)
+        .map(|value| value as u32)
+        .collect()
+}
+
+fn deg_as_raw(deg: f64) -> u64 {
+    (deg / 360.0 * f64::from(u32::MAX)) as u64
+}
+
+fn raw_as_deg(raw: u32) -> f64 {
+    f64::from(raw) / f64::from(u32::MAX) * 360.0
+}
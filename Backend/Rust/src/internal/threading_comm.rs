@@ -1,7 +1,82 @@
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+use crate::internal::discovery::DiscoveredPort;
+use crate::internal::frame_type::ProtocolInfo;
+use crate::internal::link::{LinkMonitor, LinkState};
+use crate::internal::session::SessionState;
+
 #[derive(PartialEq)]
 pub enum Message {
     StartCapture(crate::model::types::Project),
     BackendReady(bool),
 
     BackendStatusRequest,
-}
\ No newline at end of file
+
+    /// Orders for the capture under way. Ignored when there's none
+    PauseCapture,
+    ResumeCapture,
+    AbortCapture,
+}
+
+/// State of the link to the ESP32, written by its backend thread and read by the web API
+#[derive(Default)]
+pub struct Esp32Status {
+    protocol: Option<ProtocolInfo>,
+
+    /// The backend's view of the link, while it has one
+    link    : Option<Arc<Mutex<LinkMonitor>>>,
+    session : Option<Arc<Mutex<SessionState>>>,
+
+    /// The serial port the ESP32 was last found on, and what the search for it turned up
+    port    : Option<PathBuf>,
+    ports   : Vec<DiscoveredPort>
+}
+
+impl Esp32Status {
+    pub fn protocol(&self) -> Option<&ProtocolInfo> {
+        self.protocol.as_ref()
+    }
+
+    pub fn set_protocol(&mut self, protocol: Option<ProtocolInfo>) {
+        self.protocol = protocol;
+    }
+
+    pub fn link_state(&self) -> LinkState {
+        match &self.link {
+            Some(link) => link.lock().map_or(LinkState::Down, |link| link.state()),
+            None => LinkState::Down
+        }
+    }
+
+    pub fn set_link(&mut self, link: Option<Arc<Mutex<LinkMonitor>>>) {
+        self.link = link;
+    }
+
+    pub fn session_state(&self) -> SessionState {
+        match &self.session {
+            Some(session) => session.lock().map_or(SessionState::Failed, |state| *state),
+            None => SessionState::Disconnected
+        }
+    }
+
+    pub fn set_session(&mut self, session: Option<Arc<Mutex<SessionState>>>) {
+        self.session = session;
+    }
+
+    pub fn port(&self) -> Option<&Path> {
+        self.port.as_deref()
+    }
+
+    pub fn set_port(&mut self, port: Option<PathBuf>) {
+        self.port = port;
+    }
+
+    pub fn ports(&self) -> &[DiscoveredPort] {
+        &self.ports
+    }
+
+    pub fn set_ports(&mut self, ports: Vec<DiscoveredPort>) {
+        self.ports = ports;
+    }
+}
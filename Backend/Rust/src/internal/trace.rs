@@ -0,0 +1,163 @@
+// std crates
+use std::collections::VecDeque;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, BufWriter, Read, Write};
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+// External crates
+use rocket::serde::json;
+
+// own crates
+use crate::internal::transport::FrameTransport;
+use crate::internal::utils::{bytes_to_hex, hex_to_bytes};
+
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub enum Direction {
+    Rx,
+    Tx
+}
+
+/// One frame as it went over the wire. A trace file holds one of these per line, as JSON:
+/// `{"timestamp_ms": 1718000000000, "direction": "rx", "frame": "2000000000010BBC6"}`
+#[derive(PartialEq, Debug, Clone)]
+pub struct TraceEntry {
+    pub timestamp_ms: u64,
+    pub direction   : Direction,
+    pub bytes       : Vec<u8>
+}
+
+impl TraceEntry {
+    pub fn new(direction: Direction, bytes: &[u8]) -> TraceEntry {
+        let timestamp_ms = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or(Duration::ZERO).as_millis() as u64;
+
+        TraceEntry { timestamp_ms, direction, bytes: bytes.to_vec() }
+    }
+
+    pub fn to_line(&self) -> String {
+        let direction = match self.direction {
+            Direction::Rx => "rx",
+            Direction::Tx => "tx",
+        };
+
+        json::json!({
+            "timestamp_ms": self.timestamp_ms,
+            "direction"   : direction,
+            "frame"       : bytes_to_hex(&self.bytes)
+        }).to_string()
+    }
+
+    pub fn parse(line: &str) -> Option<TraceEntry> {
+        let entry = json::from_str::<json::Value>(line).ok()?;
+        let entry = entry.as_object()?;
+
+        let direction = match entry.get("direction")?.as_str()? {
+            "rx" => Direction::Rx,
+            "tx" => Direction::Tx,
+            _ => return None
+        };
+
+        Some(TraceEntry {
+            timestamp_ms: entry.get("timestamp_ms")?.as_u64()?,
+            direction,
+            bytes: hex_to_bytes(entry.get("frame")?.as_str()?)?
+        })
+    }
+}
+
+/// Appends every frame handed to it to a trace. Clones share the same trace, so the frame stacks of a
+/// session can all write to one file
+#[derive(Clone)]
+pub struct TraceRecorder {
+    sink: Arc<Mutex<dyn Write + Send>>
+}
+
+impl TraceRecorder {
+    pub fn create(path: &Path) -> io::Result<TraceRecorder> {
+        Ok(TraceRecorder::from_writer(BufWriter::new(File::create(path)?)))
+    }
+
+    pub fn from_writer<W: Write + Send + 'static>(writer: W) -> TraceRecorder {
+        TraceRecorder { sink: Arc::new(Mutex::new(writer)) }
+    }
+
+    /// Tracing is a debugging aid, failing to write one never gets in the way of the link
+    pub fn record(&self, direction: Direction, bytes: &[u8]) {
+        if let Ok(mut sink) = self.sink.lock() {
+            let _ = writeln!(sink, "{}", TraceEntry::new(direction, bytes).to_line());
+            let _ = sink.flush();
+        }
+    }
+}
+
+/// Reads a trace, one entry per line. Blank lines are skipped, anything else unreadable is an error
+pub fn read_trace<R: Read>(reader: R) -> io::Result<Vec<TraceEntry>> {
+    let mut entries = vec![];
+
+    for (index, line) in BufReader::new(reader).lines().enumerate() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        match TraceEntry::parse(&line) {
+            Some(entry) => entries.push(entry),
+            None => return Err(io::Error::new(io::ErrorKind::InvalidData, format!("Invalid trace entry on line {}", index + 1)))
+        }
+    }
+
+    Ok(entries)
+}
+
+pub fn load_trace(path: &Path) -> io::Result<Vec<TraceEntry>> {
+    read_trace(File::open(path)?)
+}
+
+/// Transport that plays back the frames received in a trace, and drops whatever is written to it.
+/// The session closes once the trace runs out
+pub struct TraceReplay {
+    rx: VecDeque<u8>
+}
+
+impl TraceReplay {
+    pub fn open(path: &Path) -> io::Result<TraceReplay> {
+        Ok(TraceReplay::from_entries(&load_trace(path)?))
+    }
+
+    pub fn from_entries(entries: &[TraceEntry]) -> TraceReplay {
+        let rx = entries.iter()
+            .filter(|entry| entry.direction == Direction::Rx)
+            .flat_map(|entry| entry.bytes.iter().copied())
+            .collect();
+
+        TraceReplay { rx }
+    }
+}
+
+impl Read for TraceReplay {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let count = buf.len().min(self.rx.len());
+        for (byte, replayed) in buf.iter_mut().zip(self.rx.drain(..count)) {
+            *byte = replayed;
+        }
+
+        Ok(count)
+    }
+}
+
+impl Write for TraceReplay {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl FrameTransport for TraceReplay {
+    fn set_read_timeout(&mut self, _: Duration) -> io::Result<()> {
+        Ok(())
+    }
+}
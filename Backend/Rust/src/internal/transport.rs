@@ -0,0 +1,192 @@
+// std crates
+use std::io::{self, Read, Write};
+use std::net::TcpStream;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+// External crates
+use serial::prelude::*;
+use serial::unix::TTYPort;
+
+// own crates
+use crate::internal::config::{SerialConfig, TransportConfig};
+use crate::internal::discovery::{candidate_ports, discover_port, unclaimed_ports, DiscoveredPort};
+use crate::internal::frame_ops::create_port_conn;
+use crate::internal::trace::TraceReplay;
+#[cfg(test)]
+use crate::internal::simulator::{Esp32Simulator, SimulatedLink, SimulatorConfig};
+use crate::internal::framing::Framing;
+
+pub const READ_TIMEOUT     : Duration = Duration::from_secs(25);
+pub const CONNECT_TIMEOUT  : Duration = Duration::from_secs(5);
+
+// firmware that predates the protocol info may not answer a StartOfTransmission carrying it, don't keep the handshake waiting on it
+pub const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Byte stream the frame protocol runs over. Anything that can read, write and time out a read
+/// can carry frames, be it a serial port or a socket to an ESP32 on the network
+pub trait FrameTransport: Read + Write {
+    fn set_read_timeout(&mut self, timeout: Duration) -> io::Result<()>;
+
+    /// How long a read currently waits. Only a serial port is set up with anything but the default
+    fn read_timeout(&self) -> Duration {
+        READ_TIMEOUT
+    }
+
+    /// Switches the line to another speed. Only means something to a serial port
+    fn set_baud_rate(&mut self, _baud_rate: u32) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl<T: SerialPort> FrameTransport for T {
+    fn set_read_timeout(&mut self, timeout: Duration) -> io::Result<()> {
+        SerialPort::set_timeout(self, timeout).map_err(io::Error::from)
+    }
+
+    fn read_timeout(&self) -> Duration {
+        SerialPort::timeout(self)
+    }
+
+    fn set_baud_rate(&mut self, baud_rate: u32) -> io::Result<()> {
+        self.reconfigure(&|settings| settings.set_baud_rate(serial::BaudRate::from_speed(baud_rate as usize))).map_err(io::Error::from)
+    }
+}
+
+pub struct TcpTransport {
+    stream: TcpStream
+}
+
+impl TcpTransport {
+    pub fn connect(address: &std::net::SocketAddr) -> io::Result<TcpTransport> {
+        let stream = TcpStream::connect_timeout(address, CONNECT_TIMEOUT)?;
+
+        // frames are small and latency sensitive, don't let Nagle hold them back
+        stream.set_nodelay(true)?;
+
+        Ok(TcpTransport { stream })
+    }
+}
+
+impl Read for TcpTransport {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.stream.read(buf)
+    }
+}
+
+impl Write for TcpTransport {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.stream.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.stream.flush()
+    }
+}
+
+impl FrameTransport for TcpTransport {
+    fn set_read_timeout(&mut self, timeout: Duration) -> io::Result<()> {
+        self.stream.set_read_timeout(Some(timeout))
+    }
+}
+
+/// Transport picked at runtime from the config
+pub enum Transport {
+    Serial(TTYPort),
+    Tcp   (TcpTransport),
+    Replay(TraceReplay),
+    #[cfg(test)]
+    Simulated(SimulatedLink)
+}
+
+impl Read for Transport {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self {
+            Transport::Serial(port  ) => port.read(buf),
+            Transport::Tcp   (stream) => stream.read(buf),
+            Transport::Replay(trace ) => trace.read(buf),
+            #[cfg(test)]
+            Transport::Simulated(link) => link.read(buf),
+        }
+    }
+}
+
+impl Write for Transport {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            Transport::Serial(port  ) => port.write(buf),
+            Transport::Tcp   (stream) => stream.write(buf),
+            Transport::Replay(trace ) => trace.write(buf),
+            #[cfg(test)]
+            Transport::Simulated(link) => link.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            Transport::Serial(port  ) => port.flush(),
+            Transport::Tcp   (stream) => stream.flush(),
+            Transport::Replay(trace ) => trace.flush(),
+            #[cfg(test)]
+            Transport::Simulated(link) => link.flush(),
+        }
+    }
+}
+
+impl FrameTransport for Transport {
+    fn set_read_timeout(&mut self, timeout: Duration) -> io::Result<()> {
+        match self {
+            Transport::Serial(port  ) => FrameTransport::set_read_timeout(port, timeout),
+            Transport::Tcp   (stream) => stream.set_read_timeout(timeout),
+            Transport::Replay(trace ) => trace.set_read_timeout(timeout),
+            #[cfg(test)]
+            Transport::Simulated(link) => link.set_read_timeout(timeout),
+        }
+    }
+
+    fn read_timeout(&self) -> Duration {
+        match self {
+            Transport::Serial(port) => FrameTransport::read_timeout(port),
+            _ => READ_TIMEOUT
+        }
+    }
+
+    fn set_baud_rate(&mut self, baud_rate: u32) -> io::Result<()> {
+        match self {
+            Transport::Serial(port) => FrameTransport::set_baud_rate(port, baud_rate),
+            _ => Ok(())
+        }
+    }
+}
+
+/// Opens the link to the ESP32. `framing` only matters to a simulated one, which has to speak it too
+pub fn open_transport(config: &TransportConfig, framing: Framing) -> io::Result<Transport> {
+    let mut transport = match config {
+        TransportConfig::Serial { port, settings } => Transport::Serial(create_port_conn(port, settings)?),
+        TransportConfig::Discover { settings     } => discover_transport(settings, framing, None, &[]).1.map(|(_, port)| port)?,
+        TransportConfig::Tcp    { address        } => Transport::Tcp   (TcpTransport::connect(address)?),
+        TransportConfig::Replay { trace          } => Transport::Replay(TraceReplay::open(trace)?),
+        #[cfg(test)]
+        TransportConfig::Simulated                   => Transport::Simulated(Esp32Simulator::spawn(SimulatorConfig { framing, ..SimulatorConfig::default() })),
+    };
+
+    transport.set_read_timeout(config.read_timeout())?;
+
+    Ok(transport)
+}
+
+/// Looks for the ESP32 on every serial port there is but the `claimed` ones, `remembered` first. Returns
+/// what turned up on each port probed, and the port it answered on
+pub fn discover_transport(settings: &SerialConfig, framing: Framing, remembered: Option<&Path>, claimed: &[PathBuf]) -> (Vec<DiscoveredPort>, io::Result<(PathBuf, Transport)>) {
+    let candidates = unclaimed_ports(candidate_ports(), claimed);
+    let (found, port) = discover_port(&candidates, remembered, &settings.baud_rates(), framing, |path| {
+        create_port_conn(&path.to_string_lossy(), settings)
+    });
+
+    let port = match port {
+        Some((path, port)) => Ok((path, Transport::Serial(port))),
+        None => Err(io::Error::new(io::ErrorKind::NotFound, format!("no ESP32 answered on any of {} serial ports", candidates.len())))
+    };
+
+    (found, port)
+}
@@ -31,3 +31,58 @@ pub fn byte_slice_to_u16(bytes: &[u8]) -> Result<u16, FrameError> {
     Ok(u16::from_be_bytes(bytes))
 
 }
+
+pub fn bytes_to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02X}", byte)).collect()
+}
+
+/// Reads hex digits two at a time, ignoring whitespace in between. None if anything else is in there
+pub fn hex_to_bytes(hex: &str) -> Option<Vec<u8>> {
+    let digits: Vec<u8> = hex.bytes().filter(|c| !c.is_ascii_whitespace()).collect();
+    if !digits.len().is_multiple_of(2) {
+        return None;
+    }
+
+    digits.chunks(2)
+        .map(|pair| u8::from_str_radix(std::str::from_utf8(pair).ok()?, 16).ok())
+        .collect()
+}
+
+/// Appends `value` as a LEB128 varint: seven bits to a byte, lowest first, with the top bit set on
+/// every byte but the last
+pub fn write_varint(value: u64, bytes: &mut Vec<u8>) {
+    let mut value = value;
+    while value >= 0x80 {
+        bytes.push((value as u8 & 0x7F) | 0x80);
+        value >>= 7;
+    }
+    bytes.push(value as u8);
+}
+
+/// Reads a varint off the front of `bytes`. Returns it along with how many bytes it took
+pub fn read_varint(bytes: &[u8]) -> Result<(u64, usize), FrameError> {
+    let mut value = 0u64;
+
+    for (index, byte) in bytes.iter().enumerate() {
+        // Ten bytes hold 64 bits, the last of them only has room for one
+        if index == 9 && *byte > 0x01 {
+            return Err(FrameError::ValueOutOfRange);
+        }
+
+        value |= u64::from(byte & 0x7F) << (7 * index);
+        if byte & 0x80 == 0 {
+            return Ok((value, index + 1));
+        }
+    }
+
+    Err(FrameError::NotEnoughBytes)
+}
+
+/// Maps signed values onto unsigned ones so small negative values make small varints too
+pub fn zigzag_encode(value: i64) -> u64 {
+    ((value << 1) ^ (value >> 63)) as u64
+}
+
+pub fn zigzag_decode(value: u64) -> i64 {
+    ((value >> 1) as i64) ^ -((value & 1) as i64)
+}
@@ -0,0 +1,13 @@
+pub mod internal;
+pub mod controller;
+pub mod model;
+mod tests;
+
+extern crate serial;
+
+#[macro_use] extern crate rocket;
+
+// own crate imports
+pub use crate::internal::frame_type::*;
+use crate::internal::frame_ops::*;
+use crate::internal::procs::*;
@@ -1,36 +1,37 @@
-mod tests;
-mod internal;
-mod controller;
-mod model;
-
-extern crate serial;
-
 #[macro_use] extern crate rocket;
 
 // std imports
-use std::sync::{mpsc, Arc, Mutex};
-use std::thread;
+use std::sync::{Arc, Mutex};
 
 // crate imports
+use rocket::fairing::AdHoc;
 use rocket::fs::{FileServer, relative};
 use rocket_oauth2::OAuth2;
 
 // own crate imports
-pub use crate::internal::frame_type::*;
-use crate::internal::frame_ops::*;
-use crate::internal::procs::*;
-use crate::internal::logger::Logger;
-use crate::internal::threading_comm::Message;
+use backend::{controller, internal};
+use backend::internal::logger::Logger;
+use backend::internal::registry::RigRegistry;
 
+// Async so the rigs' sessions start out on Rocket's runtime
 #[launch]
-fn launch() -> _ {
+async fn launch() -> _ {
     println!("[DEBUG]Launching API Server");
     let fileserver = FileServer::from(relative!("../../Frontend/public/"));
     let logger = Arc::new(Mutex::new(Logger::new()));
-    let (tx_web, rx_web) = mpsc::channel::<Message>();
-    let (tx_esp, rx_esp) = mpsc::channel::<Message>();
 
-    let rocket = rocket::build()
+    // One backend task per rig, each with its own ESP32
+    // A config that's wrong stops the backend right away, rather than leaving every capture request hanging
+    let configs = match internal::config::load_rig_configs() {
+        Ok(configs) => configs,
+        Err(e) => {
+            eprintln!("[ERROR]Failed to load the config with error {:?}", e);
+            std::process::exit(1);
+        }
+    };
+    let rigs = controller::esp32_backend::launch_esp32_backends(logger.clone(), configs);
+
+    rocket::build()
         .mount("/public", fileserver)
         .mount("/", routes![
             controller::web::index,
@@ -46,19 +47,22 @@ fn launch() -> _ {
 
             controller::api::get_project_list,
             controller::api::get_connection_status,
+            controller::api::get_serial_ports,
             controller::api::get_terminal_contents,
             controller::api::post_capture_request,
+            controller::api::post_capture_pause,
+            controller::api::post_capture_resume,
+            controller::api::post_capture_abort,
         ])
-        .manage(logger.clone())
-        .manage((tx_web, Mutex::new(rx_esp)))
-        .attach(OAuth2::<controller::auth::Google>::fairing("google"));
-
-    thread::spawn( move || {
-        controller::esp32_backend::launch_esp32_backend(logger, rx_web, tx_esp);
-    } );
-    
-
-    rocket
+        .manage(logger)
+        .manage(rigs)
+        .attach(OAuth2::<controller::auth::Google>::fairing("google"))
+        .attach(AdHoc::on_shutdown("Rig sessions", |rocket| Box::pin(async move {
+            // Captures under way are aborted and stored before the runtime goes, rather than cut off
+            if let Some(rigs) = rocket.state::<RigRegistry>() {
+                rigs.shutdown().await;
+            }
+        })))
 }
 
 
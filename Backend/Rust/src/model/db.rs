@@ -119,6 +119,45 @@ pub async fn new_project(user: types::User, title: String, description: String)
     }
 }
 
+pub async fn insert_image(data: &[u8]) -> Result<i64, sqlx::Error> {
+    let connection = connect().await;
+
+    match connection {
+        Err(err) => {
+            eprintln!("[ERROR]Cannot connect to Database [{}]", err);
+            Err(err)
+        },
+        Ok(pool) => {
+            let result = sqlx::query("INSERT INTO Image(data) VALUES (?)")
+                .bind(data)
+                .execute(&pool)
+                .await?;
+
+            Ok(result.last_insert_id() as i64)
+        }
+    }
+}
+
+pub async fn set_project_image(project: &types::Project, image_id: i64) -> Result<(), sqlx::Error> {
+    let connection = connect().await;
+
+    match connection {
+        Err(err) => {
+            eprintln!("[ERROR]Cannot connect to Database [{}]", err);
+            Err(err)
+        },
+        Ok(pool) => {
+            sqlx::query("UPDATE Projects SET image_id = ? WHERE project_id = ?")
+                .bind(image_id)
+                .bind(project.project_id())
+                .execute(&pool)
+                .await?;
+
+            Ok(())
+        }
+    }
+}
+
 type ProjectRecords = HashMap<internal::frame_type::Position , Vec<internal::frame_type::Record>>;
 type ProjectSSIDs   = HashMap<internal::frame_type::NetworkId, Vec<internal::frame_type::SSID  >>;
 type ProjectBSSIDs  = HashMap<internal::frame_type::NetworkId, Vec<internal::frame_type::BSSID >>;
@@ -24,7 +24,7 @@ impl AuthProvider {
     pub fn get_provider_id(&self) -> i64 { return self.provider_id; }
 }
 
-#[derive(sqlx::FromRow, Debug, PartialEq, Eq, Clone, Serialize)]
+#[derive(sqlx::FromRow, Debug, PartialEq, Eq, Clone, Serialize, Default)]
 pub struct Project {
     project_id          : i64,
     project_title       : String,
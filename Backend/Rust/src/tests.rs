@@ -4,9 +4,6 @@ use std::str::FromStr;
 #[cfg(test)]
 use rocket::serde::json;
 
-#[cfg(test)]
-use serial::SerialPort;
-
 #[cfg(test)]
 use crate::FrameError;
 
@@ -17,6 +14,59 @@ use crate::FrameStack;
 #[cfg(test)]
 use crate::internal::frame_type::{Checksum, Frame, Position, StepSize, BSSID, Cmd, NetworkId, Record, RSSI, SSID};
 
+#[cfg(test)]
+use crate::internal::transport::FrameTransport;
+
+/// In-memory transport. Reads drain `rx` and time out once it's empty, writes land in `tx`
+#[cfg(test)]
+#[derive(Default)]
+struct MockTransport {
+    rx: std::collections::VecDeque<u8>,
+    tx: Vec<u8>
+}
+
+#[cfg(test)]
+impl MockTransport {
+    fn with_rx(bytes: &[u8]) -> MockTransport {
+        MockTransport { rx: bytes.iter().copied().collect(), tx: vec![] }
+    }
+}
+
+#[cfg(test)]
+impl std::io::Read for MockTransport {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        if self.rx.is_empty() {
+            return Err(std::io::Error::from(std::io::ErrorKind::TimedOut));
+        }
+
+        // hand bytes out a few at a time, like a slow line would
+        let count = buf.len().min(self.rx.len()).min(7);
+        for byte in buf.iter_mut().take(count) {
+            *byte = self.rx.pop_front().unwrap();
+        }
+        Ok(count)
+    }
+}
+
+#[cfg(test)]
+impl std::io::Write for MockTransport {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.tx.extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+impl FrameTransport for MockTransport {
+    fn set_read_timeout(&mut self, _: std::time::Duration) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
 #[test]
 fn test_parse_header() {
     let bytes: [u8; 5] = [0x10, 0x00, 0x33, 0x44, 0x55];
@@ -37,10 +87,10 @@ fn test_frame_parse_sot() {
     assert_eq!(Frame::parse(&bytes), Err(FrameError::NotEnoughBytes));
 
     let bytes: [u8; 8] = [0x00, 0x00, 0x00, 0x00, 0x00, 0x01, 0xDB, 0xC1];
-    assert_eq!(Frame::parse(&bytes), Ok((Frame::from_cmd(Cmd::StartOfTransmission, 1).unwrap() , 8)));
+    assert_eq!(Frame::parse(&bytes), Ok((Frame::from_cmd(Cmd::StartOfTransmission { info: None }, 1).unwrap() , 8)));
 
     let bytes: [u8; 10] = [0x00, 0x00, 0x00, 0x00, 0x00, 0x01, 0xDB, 0xC1, 0x54, 0xF3];
-    assert_eq!(Frame::parse(&bytes), Ok((Frame::from_cmd(Cmd::StartOfTransmission, 1).unwrap() , 8)));
+    assert_eq!(Frame::parse(&bytes), Ok((Frame::from_cmd(Cmd::StartOfTransmission { info: None }, 1).unwrap() , 8)));
 }
 
 #[test]
@@ -49,13 +99,13 @@ fn test_frame_parse_ack() {
     assert_eq!(Frame::parse(&bytes), Err(FrameError::NotEnoughBytes));
 
     let bytes: [u8; 12] = [0x40, 0x04, 0x00, 0x00, 0x00, 0x0A, 0x00, 0x00, 0x00, 0x05, 0x11, 0x18];
-    assert_eq!(Frame::parse(&bytes), Ok((Frame::from_cmd(Cmd::Ack {frame_id: 5}, 10).unwrap(), 12)));
+    assert_eq!(Frame::parse(&bytes), Ok((Frame::from_cmd(Cmd::Ack { frame_id: 5, info: None }, 10).unwrap(), 12)));
 
     let bytes: [u8; 14] = [0x40, 0x04, 0x00, 0x00, 0x00, 0x0A, 0x00, 0x00, 0x00, 0x05, 0x11, 0x18, 0x54, 0xF3];
-    assert_eq!(Frame::parse(&bytes), Ok((Frame::from_cmd(Cmd::Ack {frame_id: 5}, 10).unwrap(), 12)));
+    assert_eq!(Frame::parse(&bytes), Ok((Frame::from_cmd(Cmd::Ack { frame_id: 5, info: None }, 10).unwrap(), 12)));
 
     let bytes: [u8; 16] = [0x40, 0x04, 0x00, 0x00, 0x00, 0x0A, 0x00, 0x00, 0x00, 0x05, 0x11, 0x18, 0x8E, 0xD6, 0x54, 0xF3];
-    assert_eq!(Frame::parse(&bytes), Ok((Frame::from_cmd(Cmd::Ack {frame_id: 5}, 10).unwrap(), 12)));
+    assert_eq!(Frame::parse(&bytes), Ok((Frame::from_cmd(Cmd::Ack { frame_id: 5, info: None }, 10).unwrap(), 12)));
 }
 
 #[test]
@@ -302,7 +352,7 @@ fn test_frame_parse() {
 fn test_frame_to_bytes() {
     // Start of transmission
     let bytes: [u8; 8] = [0x00, 0x00, 0x00, 0x00, 0x00, 0x01, 0xDB, 0xC1];
-    assert_eq!(bytes.to_vec(), Frame::from_cmd(Cmd::StartOfTransmission, 1).unwrap().as_bytes().unwrap());
+    assert_eq!(bytes.to_vec(), Frame::from_cmd(Cmd::StartOfTransmission { info: None }, 1).unwrap().as_bytes().unwrap());
 
     // Reset
     let bytes: [u8; 8] = [0x10, 0x00, 0x00, 0x00, 0x00, 0x01, 0x4B, 0xC3];
@@ -318,7 +368,7 @@ fn test_frame_to_bytes() {
 
     // Ack
     let bytes: [u8; 12] = [0x40, 0x04, 0x00, 0x00, 0x00, 0x0A, 0x00, 0x00, 0x00, 0x05, 0x11, 0x18];
-    assert_eq!(bytes.to_vec(), Frame::from_cmd(Cmd::Ack {frame_id: 5}, 10).unwrap().as_bytes().unwrap());
+    assert_eq!(bytes.to_vec(), Frame::from_cmd(Cmd::Ack { frame_id: 5, info: None }, 10).unwrap().as_bytes().unwrap());
 
     // RequestRetransmit
     let bytes: [u8; 16] = [0x50, 0x08, 0x00, 0x00, 0x00, 0x0A, 0x00, 0x00, 0x00, 0x05, 0x00, 0x00, 0x00, 0x05, 0xBA, 0x96];
@@ -366,20 +416,28 @@ fn test_frame_ping() {
     //                                    └───PC────┴───────── ESP32 ─────────┴─── PC ───┘
     // Tests the whole loop, both for Rust and Python
 
-    use crate::internal::frame_ops::{create_port_conn, rx_frame_blocking};
-    let mut conn = create_port_conn("/dev/ttyUSB0").unwrap();
+    // The simulated ESP32 stands in for the firmware's ping test, so this runs without the board
+    use crate::internal::simulator::Esp32Simulator;
+    let mut conn = Esp32Simulator::spawn_echo();
 
     
-    fn ping_frame<T: SerialPort>(port: &mut T, frame: &crate::Frame) -> Vec<u8> {
+    fn ping_frame<T: FrameTransport>(port: &mut T, frame: &crate::Frame) -> Vec<u8> {
 
-        let mut frame_stack = FrameStack::new();
         port.write_all(&frame.as_bytes().unwrap()).unwrap();
-        rx_frame_blocking(&mut frame_stack, port).unwrap().as_bytes().unwrap()
+
+        // Decoded as it comes back, the window has no say over frames that are only echoed
+        let mut rx = Vec::new();
+        let mut chunk = [0u8; 256];
+        while Frame::parse(&rx).is_err() {
+            let read = port.read(&mut chunk).unwrap();
+            rx.extend_from_slice(&chunk[..read]);
+        }
+        Frame::parse(&rx).unwrap().0.as_bytes().unwrap()
     }
 
     // StartOfTransmission
     let bytes: [u8; 8] = [0x00, 0x00, 0x00, 0x00, 0x00, 0x01, 0xDB, 0xC1];
-    let frame = Frame::from_cmd(Cmd::StartOfTransmission, 1).unwrap();
+    let frame = Frame::from_cmd(Cmd::StartOfTransmission { info: None }, 1).unwrap();
     let rx = ping_frame(&mut conn, &frame);
     assert_eq!(bytes.to_vec(), rx);
 
@@ -403,7 +461,7 @@ fn test_frame_ping() {
     
     // Ack
     let bytes: [u8; 12] = [0x40, 0x04, 0x00, 0x00, 0x00, 0x0A, 0x00, 0x00, 0x00, 0x05, 0x11, 0x18];
-    let frame = Frame::from_cmd(Cmd::Ack {frame_id: 5}, 10).unwrap();
+    let frame = Frame::from_cmd(Cmd::Ack { frame_id: 5, info: None }, 10).unwrap();
     let rx = ping_frame(&mut conn, &frame);
     assert_eq!(bytes.to_vec(), rx);
     
@@ -497,28 +555,1935 @@ fn test_ping_jumbo_frames() {
     assert_eq!(bytes.to_vec(), rx);
 }*/
 
-/*
 #[test]
 fn test_transmission_loop() {
-    use crate::frame_ops::*;
-    use crate::procs::*;
+    use std::sync::{Arc, Mutex};
+    use crate::internal::frame_ops::rx_frame_blocking;
+    use crate::internal::procs::{proc_rx_request_ack, proc_tx_handshake, proc_tx_reset};
+    use crate::internal::frame_type::{ProtocolInfo, CAP_BINARY_PICTURES};
+    use crate::internal::logger::Logger;
+    use crate::internal::simulator::{grid_positions, Esp32Simulator, SimulatorConfig};
 
-    let mut conn = create_port_conn("/dev/ttyUSB0").unwrap();
+    let logger = Arc::new(Mutex::new(Logger::new()));
+    let config = SimulatorConfig::default();
+    let mut conn = Esp32Simulator::spawn(config.clone());
 
     let mut frame_stack = FrameStack::new();
 
-    assert_eq!(Ok(()), proc_tx_handshake(&mut conn, &mut frame_stack));
+    let negotiated = ProtocolInfo::host().negotiate(&config.protocol);
+    assert_eq!(negotiated, proc_tx_handshake(&mut conn, &mut frame_stack, logger.clone()));
     assert_eq!(Ok(()), proc_tx_reset    (&mut conn, &mut frame_stack));
 
+    // Our Ready is only let go of once the ESP32 acks it
+    assert_eq!(1, frame_stack.tx_in_flight());
+
+    let mut ssids = vec![];
+    let mut bssids = vec![];
+    let mut positions = vec![];
+    let mut frame_ids = vec![];
     loop {
-        let frame = frame_ops::rx_frame_blocking(&mut frame_stack,&mut conn).unwrap();
-        println!("{frame:?}");
+        let frame = rx_frame_blocking(&mut frame_stack,&mut conn).unwrap();
+        frame_ids.push(frame.get_frame_id());
 
         match frame.get_cmd() {
             Cmd::EndOfTransmission => break,
-            Cmd::RequestAck { frame_id } => procs::proc_rx_request_ack(&mut conn, frame, &mut frame_stack).unwrap(),
-            other => {}
+            Cmd::RequestAck { .. } => proc_rx_request_ack(&mut conn, &mut frame_stack, logger.clone()).unwrap(),
+            Cmd::AddSSID    { ssid, .. } => ssids.push(ssid.clone()),
+            Cmd::AddBSSID   { bssid, .. } => bssids.push(bssid.clone()),
+            Cmd::RecordRSSI { position, record_count, records } => {
+                assert_eq!(*record_count as usize, records.len());
+                assert_eq!(config.networks.len(), records.len());
+                positions.push(position.clone());
+            },
+            Cmd::RecordBatch { entries } => {
+                let grid = frame_stack.grid().unwrap();
+                for entry in entries {
+                    assert_eq!(config.networks.len(), entry.records().len());
+                    positions.push(grid.position(entry.step()).unwrap());
+                }
+            },
+            _ => {}
+        }
+    }
+
+    // Every network gets announced, and every position on the grid gets measured
+    assert_eq!(vec![SSID::from_str("Lab").unwrap(), SSID::from_str("Guest").unwrap()], ssids);
+    assert_eq!(config.networks.len(), bssids.len());
+
+    let grid = grid_positions(&Position::from_degrees(10.0, 0.0).unwrap(), &StepSize::from_degrees(20.0, 20.0).unwrap());
+    assert_eq!(4 * 18, grid.len());
+    assert_eq!(grid, positions);
+
+    // Numbered the way the firmware does it: the Ack and Ready of the reset took ids 0 and 1, and every frame after them,
+    // starting with the Ack of our Ready, the next one
+    assert_eq!((2..2 + frame_ids.len() as u32).collect::<Vec<u32>>(), frame_ids);
+    assert_eq!(0, frame_stack.tx_in_flight());
+
+    // A picture only goes out once the backend said it takes them
+    let picture = vec![0xFF, 0xD8, 0x01, 0x02, 0xFF, 0xD9];
+    let mut conn = Esp32Simulator::spawn(SimulatorConfig { picture: Some(picture.clone()), ..SimulatorConfig::default() });
+    let mut frame_stack = FrameStack::new();
+
+    let protocol = proc_tx_handshake(&mut conn, &mut frame_stack, logger.clone()).unwrap();
+    assert!(protocol.supports(CAP_BINARY_PICTURES));
+    proc_tx_reset(&mut conn, &mut frame_stack).unwrap();
+
+    let capture = crate::controller::esp32_backend::run_capture(&logger, &mut frame_stack, &mut conn);
+    assert_eq!(vec![(grid[0].clone(), picture)], capture.pictures);
+    assert_eq!(grid.len(), capture.rssi_records.len());
+}
+
+#[test]
+fn test_simulated_backend() {
+    use std::sync::{mpsc, Arc, Mutex};
+    use tokio_util::sync::CancellationToken;
+    use crate::controller::esp32_backend::run_esp32_backend;
+    use crate::internal::config::load_config_from;
+    use crate::internal::logger::Logger;
+    use crate::internal::threading_comm::{Esp32Status, Message};
+    use crate::internal::link::LinkState;
+    use crate::internal::session::SessionState;
+
+    let path = std::env::temp_dir().join(format!("simulated_backend_{}.json", std::process::id()));
+    std::fs::write(&path, r#"{ "config": { "esp32": { "transport": "simulated" }, "esp32_cam": { "ip": "10.42.0.65" } } }"#).unwrap();
+    let config = load_config_from(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+
+    let logger = Arc::new(Mutex::new(Logger::new()));
+    let status = Arc::new(Mutex::new(Esp32Status::default()));
+    let (tx_web, rx_web) = mpsc::channel::<Message>();
+    let (tx_esp, rx_esp) = mpsc::channel::<Message>();
+
+    let backend = {
+        let (logger, status) = (logger.clone(), status.clone());
+        std::thread::spawn(move || run_esp32_backend(logger, status, &config, &rx_web, &tx_esp, &CancellationToken::new()))
+    };
+
+    // Capture orders only make it through once the handshake is done, like when a user starts one
+    let start = std::time::Instant::now();
+    while status.lock().unwrap().protocol().is_none() {
+        assert!(start.elapsed() < std::time::Duration::from_secs(10));
+        std::thread::sleep(std::time::Duration::from_millis(10));
+    }
+
+    // Both ends keep the link up with heartbeats while nothing else goes on
+    std::thread::sleep(std::time::Duration::from_secs(3));
+    assert_eq!(LinkState::Up, status.lock().unwrap().link_state());
+    assert_eq!(SessionState::Configuring, status.lock().unwrap().session_state());
+
+    // The backend runs the whole capture on its own, and says it's done once it is
+    tx_web.send(Message::StartCapture(crate::model::types::Project::default())).unwrap();
+    assert!(Ok(Message::BackendReady(false)) == rx_esp.recv_timeout(std::time::Duration::from_secs(60)));
+    assert!(backend.join().unwrap().is_ok());
+    assert!(status.lock().unwrap().protocol().is_none());
+    assert_eq!(LinkState::Down, status.lock().unwrap().link_state());
+    assert_eq!(SessionState::Finished, status.lock().unwrap().session_state());
+
+    // Logs from the simulated ESP32 made it through too
+    let logs: Vec<String> = logger.lock().unwrap().get_logs().iter().map(|log| json::to_string(log).unwrap()).collect();
+    assert!(logs.iter().any(|log| log.contains("Sucessful handshake with ESP32, protocol v2")));
+    assert!(logs.iter().any(|log| log.contains("Reset performed successfully")));
+
+    // The ESP32 sent everything when the session expected it
+    assert!(!logs.iter().any(|log| log.contains("Illegal session transition") || log.contains("wrong point of the session")));
+}
+
+#[test]
+fn test_replayed_backend() {
+    use std::sync::{mpsc, Arc, Mutex};
+    use tokio_util::sync::CancellationToken;
+    use crate::controller::esp32_backend::{run_capture, run_esp32_backend};
+    use crate::internal::config::load_config_from;
+    use crate::internal::logger::Logger;
+    use crate::internal::procs::{proc_tx_handshake, proc_tx_reset};
+    use crate::internal::simulator::{Esp32Simulator, SimulatorConfig};
+    use crate::internal::threading_comm::{Esp32Status, Message};
+    use crate::internal::trace::TraceRecorder;
+    use crate::internal::session::SessionState;
+
+    // A capture with the simulated ESP32, recorded the way the backend records one
+    let trace = std::env::temp_dir().join(format!("replayed_backend_{}.trace", std::process::id()));
+    {
+        let logger = Arc::new(Mutex::new(Logger::new()));
+        let mut conn = Esp32Simulator::spawn(SimulatorConfig::default());
+        let mut frame_stack = FrameStack::new();
+        frame_stack.set_trace_recorder(Some(TraceRecorder::create(&trace).unwrap()));
+
+        proc_tx_handshake(&mut conn, &mut frame_stack, logger.clone()).unwrap();
+        proc_tx_reset(&mut conn, &mut frame_stack).unwrap();
+        assert!(!run_capture(&logger, &mut frame_stack, &mut conn).rssi_records.is_empty());
+    }
+
+    let run = |trace: &std::path::Path| {
+        let path = std::env::temp_dir().join(format!("replayed_backend_{}.json", std::process::id()));
+        let esp32 = json::json!({ "transport": "replay", "trace": trace });
+        std::fs::write(&path, format!(r#"{{ "config": {{ "esp32": {esp32}, "esp32_cam": {{ "ip": "10.42.0.65" }} }} }}"#)).unwrap();
+        let config = load_config_from(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        let logger = Arc::new(Mutex::new(Logger::new()));
+        let status = Arc::new(Mutex::new(Esp32Status::default()));
+        let (tx_web, rx_web) = mpsc::channel::<Message>();
+        let (tx_esp, rx_esp) = mpsc::channel::<Message>();
+
+        let backend = {
+            let (logger, status) = (logger.clone(), status.clone());
+            std::thread::spawn(move || run_esp32_backend(logger, status, &config, &rx_web, &tx_esp, &CancellationToken::new()))
+        };
+        (logger, status, tx_web, rx_esp, backend)
+    };
+
+    // Nothing is read from the trace while waiting for the order, so the capture still gets all of it
+    let (logger, status, tx_web, rx_esp, backend) = run(&trace);
+    let start = std::time::Instant::now();
+    while status.lock().unwrap().protocol().is_none() {
+        assert!(start.elapsed() < std::time::Duration::from_secs(10));
+        std::thread::sleep(std::time::Duration::from_millis(10));
+    }
+    std::thread::sleep(std::time::Duration::from_secs(1));
+
+    tx_web.send(Message::StartCapture(crate::model::types::Project::default())).unwrap();
+    assert!(Ok(Message::BackendReady(false)) == rx_esp.recv_timeout(std::time::Duration::from_secs(60)));
+    assert!(backend.join().unwrap().is_ok());
+    assert_eq!(SessionState::Finished, status.lock().unwrap().session_state());
+
+    let logs: Vec<String> = logger.lock().unwrap().get_logs().iter().map(|log| json::to_string(log).unwrap()).collect();
+    assert!(logs.iter().any(|log| log.contains("Reset performed successfully")));
+    assert!(!logs.iter().any(|log| log.contains("Error on reset")));
+
+    // A trace that runs out before the handshake is done is given up on, instead of retried for good
+    let empty = trace.with_extension("empty");
+    std::fs::write(&empty, "").unwrap();
+    let (_, status, _tx_web, rx_esp, backend) = run(&empty);
+    assert!(Ok(Message::BackendReady(false)) == rx_esp.recv_timeout(std::time::Duration::from_secs(10)));
+    assert_eq!(Err(FrameError::ConnectionClosed), backend.join().unwrap());
+    assert_eq!(SessionState::Failed, status.lock().unwrap().session_state());
+
+    std::fs::remove_file(&empty).unwrap();
+    std::fs::remove_file(&trace).unwrap();
+}
+
+#[test]
+fn test_tcp_transport_roundtrip() {
+    use std::net::TcpListener;
+    use crate::internal::frame_ops::{rx_frame_blocking, tx_frame_blocking};
+    use crate::internal::transport::{open_transport, TcpTransport};
+    use crate::internal::config::TransportConfig;
+    use crate::internal::framing::Framing;
+
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let address  = listener.local_addr().unwrap();
+
+    // the "device" echoes back every byte it receives
+    let device = std::thread::spawn(move || {
+        let (mut stream, _) = listener.accept().unwrap();
+        let mut buff = [0u8; 16];
+        let read = std::io::Read::read(&mut stream, &mut buff).unwrap();
+        std::io::Write::write_all(&mut stream, &buff[..read]).unwrap();
+    });
+
+    let mut conn = open_transport(&TransportConfig::Tcp { address }, Framing::Raw).unwrap();
+    let mut frame_stack = FrameStack::new();
+
+    let frame = Frame::from_cmd(Cmd::SetPosition { position: Position::from_int(1, 2) }, 1).unwrap();
+    tx_frame_blocking(frame.clone(), &mut frame_stack, &mut conn).unwrap();
+    assert_eq!(Ok(frame), rx_frame_blocking(&mut frame_stack, &mut conn));
+
+    device.join().unwrap();
+
+    // nothing is listening anymore
+    assert!(TcpTransport::connect(&address).is_err());
+}
+
+#[rocket::async_test]
+async fn test_frame_codec_async() {
+    use rocket::tokio::io::{duplex, AsyncWriteExt};
+    use crate::internal::frame_codec::*;
+    use crate::internal::framing::Framing;
+
+    let frame   = |cmd: Cmd, frame_id: u32| Frame::from_cmd(cmd, frame_id).unwrap();
+    let ready   = |frame_id: u32| frame(Cmd::Ready, frame_id);
+    let set_pos = |frame_id: u32| frame(Cmd::SetPosition { position: Position::from_int(1, 2) }, frame_id);
+    let bytes   = |frame: &Frame| frame.as_bytes().unwrap();
+
+    let (host, mut device) = duplex(64);
+    let mut host = FrameCodec::framed(host, FrameStack::new());
+
+    // Boot garbage and a frame that lost a byte are skipped like on a blocking port, a frame split
+    // across writes is only yielded once it's whole
+    device.write_all(&[0x00, 0x7F, 0xFF, 0x13]).await.unwrap();
+    device.write_all(&[&bytes(&set_pos(2))[..9], &bytes(&set_pos(2))[10..]].concat()).await.unwrap();
+    device.write_all(&bytes(&ready(1))[..5]).await.unwrap();
+    device.write_all(&bytes(&ready(1))[5..]).await.unwrap();
+    assert_eq!(Ok(ready(1)), rx_frame_async(&mut host).await);
+    assert_eq!(4 + 15, host.codec().frame_stack().dropped_bytes());
+
+    // The window goes for async streams too: heartbeats and duplicates aren't handed out
+    let line = [bytes(&frame(Cmd::Heartbeat, 2)), bytes(&ready(1)), bytes(&set_pos(3))].concat();
+    device.write_all(&line).await.unwrap();
+    assert_eq!(Ok(set_pos(3)), rx_frame_async(&mut host).await);
+    assert_eq!(1, host.codec().frame_stack().duplicate_frames());
+
+    // What goes out waits on its Ack, and is the same bytes the blocking path writes
+    tx_new_frame_async(Cmd::Reset, &mut host).await.unwrap();
+    assert_eq!(1, host.codec().frame_stack().tx_in_flight());
+    let mut device = FrameCodec::framed(device, FrameStack::new());
+    assert_eq!(Ok(frame(Cmd::Reset, 0)), rx_frame_async(&mut device).await);
+
+    // Framing is undone on the way in and applied on the way out
+    let (host, mut device) = duplex(64);
+    let mut frame_stack = FrameStack::new();
+    frame_stack.set_framing(Framing::Cobs);
+    let mut host = FrameCodec::framed(host, frame_stack);
+    device.write_all(&Framing::Cobs.encode(&bytes(&ready(1)))).await.unwrap();
+    assert_eq!(Ok(ready(1)), rx_frame_async(&mut host).await);
+
+    let mut frame_stack = FrameStack::new();
+    frame_stack.set_framing(Framing::Cobs);
+    let mut device = FrameCodec::framed(device, frame_stack);
+    tx_new_frame_async(Cmd::Ready, &mut host).await.unwrap();
+    assert_eq!(Ok(ready(0)), rx_frame_async(&mut device).await);
+
+    // Closing the other end ends the session
+    drop(host);
+    assert_eq!(Err(FrameError::ConnectionClosed), rx_frame_async(&mut device).await);
+}
+
+#[test]
+fn test_frame_scan() {
+    use crate::internal::frame_type::FrameScan;
+
+    let ready: [u8; 8] = [0x20, 0x00, 0x00, 0x00, 0x00, 0x01, 0xBB, 0xC6];
+    let frame = Frame::from_cmd(Cmd::Ready, 1).unwrap();
+
+    // Aligned
+    assert_eq!(Frame::scan(&ready), FrameScan::Found { frame: frame.clone(), skipped: 0, consumed: 8 });
+
+    // Garbage in front, including an invalid command nibble
+    let bytes = [&[0xE0, 0xFF, 0x13][..], &ready[..]].concat();
+    assert_eq!(Frame::scan(&bytes), FrameScan::Found { frame: frame.clone(), skipped: 3, consumed: 8 });
+
+    // First byte of a frame lost, the rest of it is garbage now
+    let bytes = [&ready[1..], &ready[..]].concat();
+    assert_eq!(Frame::scan(&bytes), FrameScan::Found { frame: frame.clone(), skipped: 7, consumed: 8 });
+
+    // Bad checksum
+    let bytes: [u8; 8] = [0x20, 0x00, 0x00, 0x00, 0x00, 0x01, 0xBB, 0xC7];
+    assert_eq!(Frame::scan(&bytes), FrameScan::Incomplete { skipped: 1 });
+
+    // Partial frame, nothing to throw away yet
+    assert_eq!(Frame::scan(&ready[..5]), FrameScan::Incomplete { skipped: 0 });
+    assert_eq!(Frame::scan(&ready[..7]), FrameScan::Incomplete { skipped: 0 });
+    assert_eq!(Frame::scan(&[]), FrameScan::Incomplete { skipped: 0 });
+}
+
+#[test]
+fn test_rx_frame_resync() {
+    use crate::internal::frame_ops::rx_frame;
+
+    // Each frame takes an id of its own, the window drops ids it already handed out
+    let frame   = |cmd: Cmd, frame_id: u32| Frame::from_cmd(cmd, frame_id).unwrap();
+    let ready   = |frame_id: u32| frame(Cmd::Ready, frame_id);
+    let set_pos = |frame_id: u32| frame(Cmd::SetPosition { position: Position::from_int(1, 2) }, frame_id);
+    let bytes   = |frame: &Frame| frame.as_bytes().unwrap();
+    assert_eq!(vec![0xA0, 0x08, 0x00, 0x00, 0x00, 0x02, 0x00, 0x00, 0x00, 0x01, 0x00, 0x00, 0x00, 0x02], bytes(&set_pos(2))[..14].to_vec());
+
+    // boot garbage, a frame that lost a byte in the middle, then clean frames
+    let mut line = vec![0x00, 0x7F, 0xFF, 0x13];
+    line.extend_from_slice(&bytes(&set_pos(2))[..9]);
+    line.extend_from_slice(&bytes(&set_pos(2))[10..]);
+    line.extend_from_slice(&bytes(&ready(1)));
+    line.extend_from_slice(&bytes(&set_pos(2)));
+    line.extend_from_slice(&bytes(&frame(Cmd::EndOfTransmission, 3)));
+
+    let mut port = MockTransport::with_rx(&line);
+    let mut frame_stack = FrameStack::new();
+
+    assert_eq!(Ok(ready(1)), rx_frame(&mut frame_stack, &mut port));
+    assert_eq!(4 + 15, frame_stack.dropped_bytes());
+
+    assert_eq!(Ok(set_pos(2)), rx_frame(&mut frame_stack, &mut port));
+    assert_eq!(Ok(frame(Cmd::EndOfTransmission, 3)), rx_frame(&mut frame_stack, &mut port));
+    assert_eq!(4 + 15, frame_stack.dropped_bytes());
+
+    // A header promising more bytes than ever come doesn't hold back the frame behind it
+    let bogus_header: [u8; 6] = [0xD0, 0x20, 0x00, 0x00, 0x00, 0x00];
+    let mut port = MockTransport::with_rx(&[&bogus_header[..], &bytes(&ready(4))[..]].concat());
+
+    assert_eq!(Ok(ready(4)), rx_frame(&mut frame_stack, &mut port));
+    assert_eq!(4 + 15 + 6, frame_stack.dropped_bytes());
+
+    // Nor does it stall the line once it goes quiet
+    let mut port = MockTransport::with_rx(&bogus_header);
+    assert_eq!(Err(FrameError::TransmissionTimedOut), rx_frame(&mut frame_stack, &mut port));
+
+    port.rx.extend(bytes(&ready(5)));
+    assert_eq!(Ok(ready(5)), rx_frame(&mut frame_stack, &mut port));
+    assert_eq!(4 + 15 + 6 + 6, frame_stack.dropped_bytes());
+    assert_eq!(Err(FrameError::TransmissionTimedOut), rx_frame(&mut frame_stack, &mut port));
+
+    // A frame the line went quiet in the middle of is kept whole for when the rest comes
+    let mut port = MockTransport::with_rx(&bytes(&set_pos(6))[..9]);
+    assert_eq!(Err(FrameError::TransmissionTimedOut), rx_frame(&mut frame_stack, &mut port));
+    port.rx.extend(&bytes(&set_pos(6))[9..]);
+    assert_eq!(Ok(set_pos(6)), rx_frame(&mut frame_stack, &mut port));
+    assert_eq!(4 + 15 + 6 + 6, frame_stack.dropped_bytes());
+
+    // A frame already handed out isn't handed out again
+    let mut port = MockTransport::with_rx(&bytes(&set_pos(6)));
+    assert_eq!(Err(FrameError::TransmissionTimedOut), rx_frame(&mut frame_stack, &mut port));
+    assert_eq!(1, frame_stack.duplicate_frames());
+
+    // Only a second timeout with nothing new in between gives up on it
+    let mut port = MockTransport::with_rx(&bytes(&set_pos(7))[..9]);
+    assert_eq!(Err(FrameError::TransmissionTimedOut), rx_frame(&mut frame_stack, &mut port));
+    assert_eq!(4 + 15 + 6 + 6, frame_stack.dropped_bytes());
+    assert_eq!(Err(FrameError::TransmissionTimedOut), rx_frame(&mut frame_stack, &mut port));
+    assert!(frame_stack.dropped_bytes() > 4 + 15 + 6 + 6);
+}
+
+#[test]
+fn test_sliding_window() {
+    use std::time::Duration;
+    use crate::internal::frame_ops::{retx_expired_frames, rx_frame_blocking, tx_new_frame};
+
+    let mut port = MockTransport::default();
+    let mut frame_stack = FrameStack::with_window(2, Duration::ZERO, 1);
+    let set_position = || Cmd::SetPosition { position: Position::from_int(1, 2) };
+
+    // Data frames take consecutive ids until the window fills up, control frames go out regardless and take the next id too
+    tx_new_frame(set_position(), &mut frame_stack, &mut port).unwrap();
+    tx_new_frame(set_position(), &mut frame_stack, &mut port).unwrap();
+    assert_eq!(Err(FrameError::WindowFull), tx_new_frame(set_position(), &mut frame_stack, &mut port));
+    assert_eq!(Ok(()), tx_new_frame(Cmd::RequestAck { frame_id: 2 }, &mut frame_stack, &mut port));
+    assert_eq!(3, frame_stack.curr_id());
+
+    let sent = [
+        Frame::from_cmd(set_position(), 0).unwrap().as_bytes().unwrap(),
+        Frame::from_cmd(set_position(), 1).unwrap().as_bytes().unwrap(),
+        Frame::from_cmd(Cmd::RequestAck { frame_id: 2 }, 2).unwrap().as_bytes().unwrap(),
+    ].concat();
+    assert_eq!(sent, port.tx);
+
+    // Data frames overdue on an Ack go out again, until they run out of retries
+    port.tx.clear();
+    assert_eq!(Ok(2), retx_expired_frames(&mut frame_stack, &mut port));
+    assert_eq!(sent[..32], port.tx[..]);
+    assert_eq!(Err(FrameError::RetransmitLimitReached), retx_expired_frames(&mut frame_stack, &mut port));
+
+    // An Ack frees everything below the id it carries
+    let ack = Frame::from_cmd(Cmd::Ack { frame_id: 1, info: None }, 0).unwrap();
+    port.rx.extend(ack.as_bytes().unwrap());
+    assert_eq!(Ok(ack.clone()), rx_frame_blocking(&mut frame_stack, &mut port));
+    assert_eq!(1, frame_stack.tx_in_flight());
+    assert_eq!(Ok(()), tx_new_frame(set_position(), &mut frame_stack, &mut port));
+
+    // A control frame sent again still reaches the caller, but its id only counts once
+    port.rx.extend(ack.as_bytes().unwrap());
+    assert_eq!(Ok(ack), rx_frame_blocking(&mut frame_stack, &mut port));
+    assert_eq!(1, frame_stack.get_rx_frame_queue().len());
+
+    // Duplicates, and frames too far ahead of the last Ack, never reach the caller. The Ack took id 0
+    let ready = |id| Frame::from_cmd(Cmd::Ready, id).unwrap();
+    for id in [1, 1, 5, 0] {
+        port.rx.extend(ready(id).as_bytes().unwrap());
+    }
+    assert_eq!(Ok(ready(1)), rx_frame_blocking(&mut frame_stack, &mut port));
+    assert_eq!(Err(FrameError::TransmissionTimedOut), rx_frame_blocking(&mut frame_stack, &mut port));
+    assert_eq!(3, frame_stack.duplicate_frames());
+
+    // Acking our side of it keeps the rx queue bounded
+    frame_stack.set_remote_ackd_frame_id(2);
+    assert!(frame_stack.get_rx_frame_queue().is_empty());
+    port.rx.extend(ready(1).as_bytes().unwrap());
+    port.rx.extend(ready(2).as_bytes().unwrap());
+    assert_eq!(Ok(ready(2)), rx_frame_blocking(&mut frame_stack, &mut port));
+    assert_eq!(4, frame_stack.duplicate_frames());
+}
+
+#[test]
+fn test_serve_request_retransmit() {
+    use std::sync::{Arc, Mutex};
+    use crate::internal::frame_ops::tx_new_frame;
+    use crate::internal::procs::proc_rx_request_retransmit;
+    use crate::internal::logger::Logger;
+
+    let logger = Arc::new(Mutex::new(Logger::new()));
+    let mut port = MockTransport::default();
+    let mut frame_stack = FrameStack::new();
+    let set_position = |pitch| Cmd::SetPosition { position: Position::from_int(pitch, 0) };
+
+    for pitch in 0..3 {
+        tx_new_frame(set_position(pitch), &mut frame_stack, &mut port).unwrap();
+    }
+    frame_stack.ack_tx_frames(1);
+    port.tx.clear();
+
+    // Requested frames go out again as they were first sent
+    assert_eq!(Ok(()), proc_rx_request_retransmit(&mut port, &mut frame_stack, 1, 3, logger.clone()));
+    let resent = [
+        Frame::from_cmd(set_position(1), 1).unwrap().as_bytes().unwrap(),
+        Frame::from_cmd(set_position(2), 2).unwrap().as_bytes().unwrap(),
+    ].concat();
+    assert_eq!(resent, port.tx);
+
+    // Ids past the last one sent are ignored
+    port.tx.clear();
+    assert_eq!(Ok(()), proc_rx_request_retransmit(&mut port, &mut frame_stack, 2, 10, logger.clone()));
+    assert_eq!(resent[16..], port.tx[..]);
+
+    // Frame 0 was acked and is gone, nothing is sent
+    port.tx.clear();
+    assert_eq!(Err(FrameError::FrameEvicted), proc_rx_request_retransmit(&mut port, &mut frame_stack, 0, 2, logger.clone()));
+    assert!(port.tx.is_empty());
+
+    // Control frames take an id too, one the remote lost goes out again like any other
+    tx_new_frame(Cmd::RequestAck { frame_id: 3 }, &mut frame_stack, &mut port).unwrap();
+    port.tx.clear();
+    assert_eq!(Ok(()), proc_rx_request_retransmit(&mut port, &mut frame_stack, 3, 4, logger.clone()));
+    assert_eq!(Frame::from_cmd(Cmd::RequestAck { frame_id: 3 }, 3).unwrap().as_bytes().unwrap(), port.tx);
+}
+
+#[test]
+fn test_fragmentation() {
+    use crate::internal::frame_ops::{rx_frame_blocking, tx_new_frame};
+    use crate::internal::frame_type::{MAX_FRAGMENTS, MAX_FRAGMENT_PAYLOAD};
+
+    let logs: Vec<json::Value> = (0..150)
+        .map(|i| json::json!({"severity": 2, "msg": format!("pitch={i}, yaw={i}, measurement taken")}))
+        .collect();
+    let logs = Cmd::TransmitLogs { logs: json::json!({ "logs": logs }) };
+    let body_len = logs.as_bytes().unwrap().len();
+    assert_eq!(3, body_len.div_ceil(MAX_FRAGMENT_PAYLOAD));
+
+    // Too large for a single frame
+    assert_eq!(Err(FrameError::LengthValueOutOfRange), Frame::from_cmd(logs.clone(), 0));
+
+    // Split into consecutive frames, every one but the last filled up
+    let mut host = MockTransport::default();
+    let mut host_stack = FrameStack::new();
+    tx_new_frame(Cmd::Ready, &mut host_stack, &mut host).unwrap();
+    tx_new_frame(logs.clone(), &mut host_stack, &mut host).unwrap();
+    assert_eq!(4, host_stack.curr_id());
+
+    let mut frames = vec![];
+    let mut line = &host.tx[..];
+    while let Ok((frame, consumed)) = Frame::parse(line) {
+        frames.push(frame);
+        line = &line[consumed as usize..];
+    }
+    assert_eq!(4, frames.len());
+    match frames[1].get_cmd() {
+        Cmd::Fragment { cmd_nibble: 0xD, sequence: 0, last: false, payload } => assert_eq!(MAX_FRAGMENT_PAYLOAD, payload.len()),
+        other => panic!("Expected the first fragment, got {other:?}")
+    }
+    match frames[3].get_cmd() {
+        Cmd::Fragment { cmd_nibble: 0xD, sequence: 2, last: true, payload } => assert_eq!(body_len - 2 * MAX_FRAGMENT_PAYLOAD, payload.len()),
+        other => panic!("Expected the last fragment, got {other:?}")
+    }
+
+    // Reassembled on the other end, even when fragments arrive out of order or twice
+    let mut device = MockTransport::default();
+    for index in [0, 3, 1, 3, 2] {
+        device.rx.extend(frames[index].as_bytes().unwrap());
+    }
+
+    let mut device_stack = FrameStack::new();
+    assert_eq!(Ok(Frame::from_cmd(Cmd::Ready, 0).unwrap()), rx_frame_blocking(&mut device_stack, &mut device));
+
+    let frame = rx_frame_blocking(&mut device_stack, &mut device).unwrap();
+    assert_eq!(&logs, frame.get_cmd());
+    assert_eq!(1, frame.get_frame_id());
+    assert!(frame.is_reassembled());
+    assert_eq!(1, device_stack.duplicate_frames());
+    assert_eq!(Err(FrameError::TransmissionTimedOut), rx_frame_blocking(&mut device_stack, &mut device));
+
+    // Nothing past what one command may span is held on to
+    let fragment = |sequence: u16, last: bool, frame_id: u32, payload: &str| {
+        Frame::from_cmd(Cmd::Fragment { cmd_nibble: 0xD, sequence, last, payload: payload.as_bytes().to_vec() }, frame_id).unwrap()
+    };
+    let mut stack = FrameStack::new();
+    assert_eq!(Err(FrameError::LengthValueOutOfRange), stack.reassemble_in_place(fragment(MAX_FRAGMENTS as u16, true, 1000, "{}")));
+
+    // With too many commands left partial, the one waited on the longest goes, even if later ids wrapped below it
+    assert_eq!(Ok(false), stack.reassemble_in_place(fragment(0, false, 100, "{\"logs\"")));
+    for first_id in [0, 10, 20, 30] {
+        assert_eq!(Ok(false), stack.reassemble_in_place(fragment(0, false, first_id, "{\"logs\"")));
+    }
+    assert_eq!(Ok(false), stack.reassemble_in_place(fragment(1, true, 101, ":[]}")));
+
+    assert_eq!(Ok(true), stack.reassemble_in_place(fragment(1, true, 11, ":[]}")));
+    let frame = stack.lend_reassembled().unwrap().into_frame();
+    assert_eq!(&Cmd::TransmitLogs { logs: json::json!({ "logs": [] }) }, frame.get_cmd());
+}
+
+#[test]
+fn test_protocol_negotiation() {
+    use std::sync::{Arc, Mutex};
+    use crate::internal::frame_ops::tx_new_frame;
+    use crate::internal::frame_type::{ProtocolInfo, CAP_FRAGMENTATION};
+    use crate::internal::procs::proc_tx_handshake;
+    use crate::internal::logger::Logger;
+
+    let logger = Arc::new(Mutex::new(Logger::new()));
+    let bytes = |cmd, id| Frame::from_cmd(cmd, id).unwrap().as_bytes().unwrap();
+    let ack   = |frame_id| Cmd::Ack { frame_id, info: None };
+
+    // The protocol rides on the StartOfTransmission and its Ack, bare ones are still what legacy firmware sends
+    let sot = Frame::from_cmd(Cmd::StartOfTransmission { info: Some(ProtocolInfo::host()) }, 0).unwrap();
+    assert_eq!(0x00, sot.as_bytes().unwrap()[0] & 0xF0);
+    assert_eq!(Ok((sot.clone(), 14)), Frame::parse(&sot.as_bytes().unwrap()));
+    let device_ack = |info: &ProtocolInfo| Cmd::Ack { frame_id: 0, info: Some(info.clone()) };
+    assert_eq!(Ok((Frame::from_cmd(device_ack(&ProtocolInfo::host()), 0).unwrap(), 18)), Frame::parse(&bytes(device_ack(&ProtocolInfo::host()), 0)));
+
+    // A device on the same version keeps every shared capability
+    let device_info = ProtocolInfo::from_components(2, 2, CAP_FRAGMENTATION | 1 << 31);
+    let mut port = MockTransport::with_rx(&[bytes(device_ack(&device_info), 0), bytes(ack(1), 0)].concat());
+    let mut frame_stack = FrameStack::new();
+
+    let expected = ProtocolInfo::from_components(2, 2, CAP_FRAGMENTATION);
+    assert_eq!(Ok(expected.clone()), proc_tx_handshake(&mut port, &mut frame_stack, logger.clone()));
+    assert_eq!(&expected, frame_stack.protocol());
+    assert_eq!(vec!["fragmentation"], expected.capability_names());
+    assert_eq!([sot.as_bytes().unwrap(), bytes(Cmd::Reset, 1)].concat(), port.tx);
+
+    // The Reset has to be acked as well
+    let mut port = MockTransport::with_rx(&bytes(device_ack(&device_info), 0));
+    assert_eq!(Err(FrameError::TransmissionTimedOut), proc_tx_handshake(&mut port, &mut FrameStack::new(), logger.clone()));
+
+    // Firmware from before the protocol info answers with a bare Ack
+    let mut port = MockTransport::with_rx(&[bytes(ack(0), 0), bytes(ack(1), 0)].concat());
+    assert_eq!(Ok(ProtocolInfo::legacy()), proc_tx_handshake(&mut port, &mut FrameStack::new(), logger.clone()));
+    assert_eq!([sot.as_bytes().unwrap(), bytes(Cmd::Reset, 1)].concat(), port.tx);
+
+    // or chokes on it, the handshake degrades to the legacy one
+    let garbled = Cmd::RequestRetransmit { frame_id_start: 0, frame_id_end: 0 };
+    let mut port = MockTransport::with_rx(&[bytes(garbled, 0), bytes(ack(0), 0), bytes(ack(1), 0)].concat());
+    let mut frame_stack = FrameStack::new();
+
+    assert_eq!(Ok(ProtocolInfo::legacy()), proc_tx_handshake(&mut port, &mut frame_stack, logger.clone()));
+    assert_eq!([sot.as_bytes().unwrap(), bytes(Cmd::StartOfTransmission { info: None }, 0), bytes(Cmd::Reset, 1)].concat(), port.tx);
+
+    // which can't put fragments back together
+    let logs = Cmd::TransmitLogs { logs: json::json!({ "logs": "A".repeat(5000) }) };
+    assert_eq!(Err(FrameError::LengthValueOutOfRange), tx_new_frame(logs, &mut frame_stack, &mut port));
+
+    // A device too new for us is refused
+    let device_info = ProtocolInfo::from_components(4, 3, CAP_FRAGMENTATION);
+    let mut port = MockTransport::with_rx(&bytes(device_ack(&device_info), 0));
+    assert_eq!(Err(FrameError::UnsupportedProtocolVersion), proc_tx_handshake(&mut port, &mut FrameStack::new(), logger.clone()));
+}
+
+#[test]
+fn test_picture_chunks() {
+    use crate::internal::frame_type::MAX_PICTURE_CHUNK_SIZE;
+    use crate::internal::picture::{chunk_picture, PictureAssembler, MAX_PENDING_PICTURES};
+
+    // JPEG markers around a body that needs three chunks
+    let mut jpeg = vec![0xFF, 0xD8];
+    jpeg.extend((0..2 * MAX_PICTURE_CHUNK_SIZE + 100).map(|i| (i % 251) as u8));
+    jpeg.extend([0xFF, 0xD9]);
+
+    let position = Position::from_int(10, 20);
+    let chunks = chunk_picture(&position, &jpeg).unwrap();
+    assert_eq!(3, chunks.len());
+
+    // Every chunk fits a frame as is, bytes and all
+    let frames: Vec<Frame> = chunks.iter().enumerate()
+        .map(|(id, chunk)| Frame::from_cmd(chunk.clone(), id as u32).unwrap())
+        .collect();
+    for frame in &frames {
+        let bytes = frame.as_bytes().unwrap();
+        assert_eq!(Ok((frame.clone(), bytes.len() as u16)), Frame::parse(&bytes));
+    }
+
+    // Out of order and repeated chunks still give back the same picture
+    let mut pictures = PictureAssembler::new();
+    let mut add = |index: usize| match frames[index].get_cmd() {
+        Cmd::PictureChunk { position, offset, total_size, data } => pictures.add_chunk(position, *offset, *total_size, data),
+        other => panic!("Expected a picture chunk, got {other:?}")
+    };
+    assert_eq!(Ok(None), add(2));
+    assert_eq!(Ok(None), add(0));
+    assert_eq!(Ok(None), add(0));
+    assert_eq!(Ok(Some(jpeg.clone())), add(1));
+
+    // A chunk past the end of its own picture is refused
+    let mut pictures = PictureAssembler::new();
+    assert_eq!(Err(FrameError::ValueOutOfRange), pictures.add_chunk(&position, 10, 12, &[0; 4]));
+
+    // A new picture at the same position replaces the one in progress
+    assert_eq!(Ok(None), pictures.add_chunk(&position, 0, 8, &[1; 4]));
+    assert_eq!(Ok(None), pictures.add_chunk(&position, 0, 6, &[2; 3]));
+    assert_eq!(Ok(Some(vec![2, 2, 2, 3, 3, 3])), pictures.add_chunk(&position, 3, 6, &[3; 3]));
+    assert_eq!(0, pictures.pending());
+
+    // Pictures left unfinished don't pile up, the oldest one makes way for a new one
+    for yaw in 0..MAX_PENDING_PICTURES as u32 + 2 {
+        assert_eq!(Ok(None), pictures.add_chunk(&Position::from_int(0, yaw), 0, 8, &[1; 4]));
+    }
+    assert_eq!(MAX_PENDING_PICTURES, pictures.pending());
+    assert_eq!(Ok(None), pictures.add_chunk(&Position::from_int(0, 0), 4, 8, &[1; 4]));
+    assert_eq!(Ok(Some(vec![1; 8])), pictures.add_chunk(&Position::from_int(0, MAX_PENDING_PICTURES as u32 + 1), 4, 8, &[1; 4]));
+}
+
+/// Writer a test can still read after handing it over to a `TraceRecorder`
+#[cfg(test)]
+#[derive(Clone, Default)]
+struct SharedBuffer(std::sync::Arc<std::sync::Mutex<Vec<u8>>>);
+
+#[cfg(test)]
+impl std::io::Write for SharedBuffer {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.0.lock().unwrap().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+#[test]
+fn test_trace_record_replay() {
+    use std::sync::{Arc, Mutex};
+    use crate::controller::esp32_backend::run_capture;
+    use crate::internal::logger::Logger;
+    use crate::internal::trace::{read_trace, Direction, TraceEntry, TraceRecorder, TraceReplay};
+
+    let logger = Arc::new(Mutex::new(Logger::new()));
+    let bytes = |cmd, id| Frame::from_cmd(cmd, id).unwrap().as_bytes().unwrap();
+
+    let ssid   = Cmd::AddSSID { id: NetworkId::from_int(1), ssid: SSID::from_str("lab").unwrap() };
+    let record = Record::from_components(NetworkId::from_int(1), RSSI::from_int(-60).unwrap());
+    let rssi   = Cmd::RecordRSSI { position: Position::from_int(1, 2), record_count: 1, records: vec![record.clone()] };
+    let session = [bytes(ssid, 1), bytes(rssi, 2), bytes(Cmd::RequestAck { frame_id: 3 }, 3), bytes(Cmd::EndOfTransmission, 4)];
+
+    // Entries survive a round trip through their line
+    let entry = TraceEntry { timestamp_ms: 1_718_000_000_000, direction: Direction::Tx, bytes: session[0].clone() };
+    assert_eq!(Some(entry.clone()), TraceEntry::parse(&entry.to_line()));
+    assert_eq!(None, TraceEntry::parse(r#"{"timestamp_ms": 1, "direction": "sideways", "frame": "00"}"#));
+
+    // Record a live capture
+    let buffer = SharedBuffer::default();
+    let mut frame_stack = FrameStack::new();
+    frame_stack.set_trace_recorder(Some(TraceRecorder::from_writer(buffer.clone())));
+
+    let mut port = MockTransport::with_rx(&session.concat());
+    let live = run_capture(&logger, &mut frame_stack, &mut port);
+    assert_eq!(Some(&vec![record]), live.rssi_records.get(&Position::from_int(1, 2)));
+
+    // Every frame is in there, in the order it went over the wire, the Ack included
+    let entries = read_trace(buffer.0.lock().unwrap().as_slice()).unwrap();
+    let directions: Vec<Direction> = entries.iter().map(|entry| entry.direction).collect();
+    assert_eq!(vec![Direction::Rx, Direction::Rx, Direction::Rx, Direction::Tx, Direction::Rx], directions);
+    assert_eq!(port.tx, entries[3].bytes);
+    assert_eq!(session[3], entries[4].bytes);
+
+    // Replaying it without the device gives back the same capture
+    let replayed = run_capture(&logger, &mut FrameStack::new(), &mut TraceReplay::from_entries(&entries));
+    assert_eq!(live.ssids, replayed.ssids);
+    assert_eq!(live.rssi_records, replayed.rssi_records);
+
+    // A trace cut short ends the capture instead of waiting on frames that never come
+    let truncated = run_capture(&logger, &mut FrameStack::new(), &mut TraceReplay::from_entries(&entries[..1]));
+    assert_eq!(live.ssids, truncated.ssids);
+    assert!(truncated.rssi_records.is_empty());
+}
+
+#[test]
+fn test_dissect() {
+    use crate::internal::dissect::{dissect_capture, dissect_frames, parse_hex_input, CrcStatus, Dissected};
+
+    // Every way a test vector gets written reads the same
+    let ready = vec![0x20, 0x00, 0x00, 0x00, 0x00, 0x01, 0xBB, 0xC6];
+    assert_eq!(Some(ready.clone()), parse_hex_input("[0x20, 0x00, 0x00, 0x00, 0x00, 0x01, 0xBB, 0xC6]"));
+    assert_eq!(Some(ready.clone()), parse_hex_input("20 00 00 00 00 01 BB C6"));
+    assert_eq!(Some(ready.clone()), parse_hex_input("200000000001bbc6"));
+    assert_eq!(None, parse_hex_input("0x200"));
+    assert_eq!(None, parse_hex_input("2G"));
+
+    let frame = |dissected: &Dissected| match dissected {
+        Dissected::Frame(dissection) => dissection.clone(),
+        other => panic!("Expected a frame, got {other:?}")
+    };
+
+    // A good frame
+    let dissected = dissect_frames(&ready);
+    assert_eq!(1, dissected.len());
+    let dissection = frame(&dissected[0]);
+    assert_eq!(("Ready", 1, 0, Ok(Cmd::Ready)), (dissection.name, dissection.frame_id, dissection.length, dissection.cmd.clone()));
+    assert!(dissection.is_valid());
+
+    // A bad CRC is still decoded, along with the CRC it should have had
+    let mut corrupt = ready.clone();
+    corrupt[7] = 0xC7;
+    let dissection = frame(&dissect_frames(&corrupt)[0]);
+    assert_eq!(Some(CrcStatus { received: 0xBBC7, expected: 0xBBC6 }), dissection.crc);
+    assert_eq!(Ok(Cmd::Ready), dissection.cmd);
+    assert!(!dissection.is_valid());
+
+    // Lengths the command can't have, or frames cut short, are reported without a CRC
+    let dissection = frame(&dissect_frames(&[0x80, 0x03, 0x00, 0x00, 0x00, 0x01, 0x01, 0x01, 0xFF])[0]);
+    assert_eq!(("AddBSSID", Err(FrameError::LengthValueOutOfRange), None), (dissection.name, dissection.cmd, dissection.crc));
+    let dissection = frame(&dissect_frames(&[0x80, 0x0A, 0x00, 0x00, 0x00, 0x01, 0x00])[0]);
+    assert_eq!((Err(FrameError::NotEnoughBytes), None), (dissection.cmd, dissection.crc));
+
+    // Extended commands are named after their sub-opcode
+    let heartbeat = Frame::from_cmd(Cmd::Heartbeat, 0).unwrap().as_bytes().unwrap();
+    assert_eq!("Heartbeat", frame(&dissect_frames(&heartbeat)[0]).name);
+
+    // A raw capture gets resynchronized: noise, a corrupt frame and a good one
+    let capture = [vec![0xFF, 0xFF], corrupt.clone(), ready.clone(), vec![0x20, 0x00]].concat();
+    let dissected = dissect_capture(&capture);
+    assert_eq!(4, dissected.len());
+    assert_eq!(Dissected::Garbage { offset: 0, bytes: vec![0xFF, 0xFF] }, dissected[0]);
+    assert_eq!((2, false), (frame(&dissected[1]).offset, frame(&dissected[1]).is_valid()));
+    assert_eq!((10, true), (frame(&dissected[2]).offset, frame(&dissected[2]).is_valid()));
+    assert_eq!(Dissected::Garbage { offset: 18, bytes: vec![0x20, 0x00] }, dissected[3]);
+}
+
+#[test]
+fn test_frame_parse_malformed() {
+    // A record count far past the end of the body
+    let mut bytes = vec![0x90, 0x11, 0x00, 0x00, 0x00, 0x01];
+    bytes.extend_from_slice(&[0x00; 8]);
+    bytes.extend_from_slice(&u32::MAX.to_be_bytes());
+    bytes.extend_from_slice(&[0x00, 0x00, 0x00, 0x01, 0xD8]);
+    let checksum = Checksum::from_bytes(&bytes).as_bytes();
+    bytes.extend_from_slice(&checksum);
+    assert_eq!(Err(FrameError::NotEnoughBytes), Frame::parse(&bytes));
+
+    // Headers whose length runs past the bytes there are, for every command
+    for cmd_nibble in 0..=0xF_u8 {
+        let bytes = [cmd_nibble << 4 | 0x0F, 0xFF, 0x00, 0x00, 0x00, 0x01, 0x00];
+        assert!(Frame::parse(&bytes).is_err());
+    }
+
+    // Bodies shorter than the length they're parsed with
+    assert_eq!(Err(FrameError::NotEnoughBytes), Cmd::parse_body(0xB, 0x11, &[0x00; 9]));
+    assert_eq!(Err(FrameError::NotEnoughBytes), Cmd::parse_body(0x5, 0x08, &[0x00; 4]));
+    assert_eq!(Err(FrameError::NotEnoughBytes), Cmd::parse_body(0xE, 0x11, &[0x04]));
+    assert_eq!(Err(FrameError::NotEnoughBytes), Cmd::parse(0x4, 4, 0, &[0x40, 0x04, 0x00, 0x00, 0x00, 0x01]));
+}
+
+#[test]
+fn test_frame_ref() {
+    use crate::internal::frame_type::{CmdRef, FrameRef};
+
+    let records: Vec<Record> = (0..3).map(|id| Record::from_components(NetworkId::from_int(id), RSSI::from_int(-40).unwrap())).collect();
+    let cmd = Cmd::RecordRSSI { position: Position::from_int(1, 2), record_count: 3, records: records.clone() };
+    let bytes = Frame::from_cmd(cmd, 5).unwrap().as_bytes().unwrap();
+
+    // Records are read lazily, and read the same as the owned ones
+    let (frame, consumed) = FrameRef::parse(&bytes).unwrap();
+    assert_eq!((5, bytes.len() as u16), (frame.get_frame_id(), consumed));
+    match frame.get_cmd() {
+        CmdRef::RecordRSSI { position, record_count: 3, records: iter } => {
+            assert_eq!((&Position::from_int(1, 2), 3), (position, iter.len()));
+            assert_eq!(records, iter.clone().collect::<Vec<Record>>());
+        },
+        other => panic!("Expected borrowed records, got {other:?}")
+    }
+    assert_eq!(Frame::parse(&bytes).unwrap().0, frame.into_frame());
+
+    // Picture data points right into the frame
+    let cmd = Cmd::PictureChunk { position: Position::from_int(3, 4), offset: 8, total_size: 16, data: vec![0xAB; 8] };
+    let bytes = Frame::from_cmd(cmd, 6).unwrap().as_bytes().unwrap();
+    match FrameRef::parse(&bytes).unwrap().0.get_cmd() {
+        CmdRef::PictureChunk { offset: 8, total_size: 16, data, .. } => {
+            assert_eq!(&[0xAB; 8], data);
+            assert_eq!(bytes[bytes.len() - 10..].as_ptr(), data.as_ptr());
+        },
+        other => panic!("Expected a borrowed picture chunk, got {other:?}")
+    }
+
+    // Everything else is owned
+    let bytes = Frame::from_cmd(Cmd::Ready, 7).unwrap().as_bytes().unwrap();
+    assert_eq!(&CmdRef::Owned(Cmd::Ready), FrameRef::parse(&bytes).unwrap().0.get_cmd());
+
+    // A bad record fails the whole frame up front, not halfway through iterating
+    let mut bytes = vec![0x90, 0x11, 0x00, 0x00, 0x00, 0x01];
+    bytes.extend_from_slice(&[0x00; 8]);
+    bytes.extend_from_slice(&1_u32.to_be_bytes());
+    bytes.extend_from_slice(&[0x00, 0x00, 0x00, 0x01, 0x7F]);
+    let checksum = Checksum::from_bytes(&bytes).as_bytes();
+    bytes.extend_from_slice(&checksum);
+    assert_eq!(Frame::parse(&bytes).unwrap_err(), FrameRef::parse(&bytes).unwrap_err());
+}
+
+#[test]
+fn test_rx_frame_ref() {
+    use crate::internal::frame_ops::rx_frame_ref;
+    use crate::internal::frame_type::CmdRef;
+
+    let record  = Record::from_components(NetworkId::from_int(1), RSSI::from_int(-60).unwrap());
+    let small   = Cmd::RecordRSSI { position: Position::from_int(0, 0), record_count: 1, records: vec![record.clone()] };
+    let large   = Cmd::RecordRSSI { position: Position::from_int(0, 1), record_count: 1000, records: vec![record; 1000] };
+    let small   = Frame::from_cmd(small, 0).unwrap().as_bytes().unwrap();
+
+    // The same frame twice, a command too large for one frame, then something small
+    let mut bytes = [small.clone(), small].concat();
+    let fragments = large.fragment().unwrap();
+    let count = fragments.len() as u32;
+    for (id, fragment) in (1..).zip(fragments) {
+        bytes.extend(Frame::from_cmd(fragment, id).unwrap().as_bytes().unwrap());
+    }
+    bytes.extend(Frame::from_cmd(Cmd::Ready, count + 1).unwrap().as_bytes().unwrap());
+
+    let mut port = MockTransport::with_rx(&bytes);
+    let mut frame_stack = FrameStack::new();
+
+    let frame = rx_frame_ref(&mut frame_stack, &mut port).unwrap();
+    assert!(!frame.is_reassembled());
+    match frame.into_cmd() {
+        CmdRef::RecordRSSI { position, records, .. } => assert_eq!((Position::from_int(0, 0), 1), (position, records.len())),
+        other => panic!("Expected the small batch, got {other:?}")
+    }
+
+    // The duplicate is skipped, and the fragments come out as the one command they carry
+    let frame = rx_frame_ref(&mut frame_stack, &mut port).unwrap();
+    assert_eq!(1, frame.get_frame_id());
+    assert!(frame.is_reassembled());
+    match frame.into_cmd() {
+        CmdRef::RecordRSSI { position, records, .. } => assert_eq!((Position::from_int(0, 1), 1000), (position, records.len())),
+        other => panic!("Expected the reassembled batch, got {other:?}")
+    }
+
+    assert_eq!(&CmdRef::Owned(Cmd::Ready), rx_frame_ref(&mut frame_stack, &mut port).unwrap().get_cmd());
+    assert_eq!(1, frame_stack.duplicate_frames());
+
+    // The last frame's bytes are let go of on the next receive
+    assert!(!frame_stack.rx_buffer().is_empty());
+    assert_eq!(Err(FrameError::TransmissionTimedOut), rx_frame_ref(&mut frame_stack, &mut port).map(|frame| frame.into_frame()));
+    assert!(frame_stack.rx_buffer().is_empty());
+    assert_eq!(0, frame_stack.dropped_bytes());
+}
+
+#[test]
+fn test_link_liveness() {
+    use std::time::{Duration, Instant};
+    use crate::internal::config::{load_config_from, ConfigError};
+    use crate::internal::frame_ops::rx_frame;
+    use crate::internal::frame_type::{ProtocolInfo, CAP_HEARTBEAT, HOST_CAPABILITIES};
+    use crate::internal::link::{LinkMonitor, LinkState};
+    use crate::internal::procs::proc_tx_heartbeat;
+
+    // Degraded once two heartbeats are missed, down after the timeout
+    let mut link = LinkMonitor::new(Duration::from_millis(100), Duration::from_millis(500));
+    assert_eq!(LinkState::Down, link.state());
+    link.heard();
+    let now = Instant::now();
+    assert_eq!(LinkState::Up      , link.state_at(now));
+    assert_eq!(LinkState::Degraded, link.state_at(now + Duration::from_millis(250)));
+    assert_eq!(LinkState::Down    , link.state_at(now + Duration::from_millis(600)));
+
+    // Firmware without heartbeats can't be judged by how quiet it is
+    link.set_heartbeats(false);
+    assert_eq!(LinkState::Up, link.state_at(now + Duration::from_millis(600)));
+
+    // Heartbeats go out as control frames once we've been quiet for an interval, and only to a remote that knows them
+    let heartbeat = Frame::from_cmd(Cmd::Heartbeat, 3).unwrap();
+    assert!(heartbeat.get_cmd().is_control());
+    assert_eq!(vec![0xE0, 0x01, 0x00, 0x00, 0x00, 0x03, 0x05], heartbeat.as_bytes().unwrap()[..7].to_vec());
+
+    let mut port = MockTransport::default();
+    let mut frame_stack = FrameStack::new();
+    frame_stack.set_link_monitor(LinkMonitor::new(Duration::from_millis(100), Duration::from_secs(10)));
+    assert_eq!(Ok(false), proc_tx_heartbeat(&mut port, &mut frame_stack));
+    std::thread::sleep(Duration::from_millis(150));
+    assert_eq!(Ok(true ), proc_tx_heartbeat(&mut port, &mut frame_stack));
+    assert_eq!(Ok(false), proc_tx_heartbeat(&mut port, &mut frame_stack));
+    assert_eq!(Ok((Frame::from_cmd(Cmd::Heartbeat, 0).unwrap(), 9)), Frame::parse(&port.tx));
+    assert_eq!(0, frame_stack.tx_in_flight());
+    assert_eq!(1, frame_stack.curr_id());
+
+    let mut frame_stack = FrameStack::new();
+    frame_stack.set_link_monitor(LinkMonitor::new(Duration::ZERO, Duration::from_secs(10)));
+    frame_stack.set_protocol(ProtocolInfo::from_components(2, 1, HOST_CAPABILITIES & !CAP_HEARTBEAT));
+    assert_eq!(Ok(false), proc_tx_heartbeat(&mut port, &mut frame_stack));
+
+    // Received heartbeats keep the link up, but aren't handed out
+    let bytes = [heartbeat.as_bytes().unwrap(), Frame::from_cmd(Cmd::Ready, 4).unwrap().as_bytes().unwrap()].concat();
+    let mut port = MockTransport::with_rx(&bytes);
+    let mut frame_stack = FrameStack::new();
+    assert_eq!(LinkState::Down, frame_stack.link_state());
+    assert_eq!(&Cmd::Ready, rx_frame(&mut frame_stack, &mut port).unwrap().get_cmd());
+    assert_eq!(LinkState::Up, frame_stack.link_state());
+
+    // The timeout has to leave room for at least one heartbeat
+    let path = std::env::temp_dir().join(format!("link_config_{}.json", std::process::id()));
+    let config = |esp32: &str| {
+        std::fs::write(&path, format!(r#"{{ "config": {{ "esp32": {esp32}, "esp32_cam": {{ "ip": "10.42.0.65" }} }} }}"#)).unwrap();
+        load_config_from(&path)
+    };
+    let link = config(r#"{ "port": "/dev/ttyUSB0", "heartbeat_interval_ms": 500, "liveness_timeout_ms": 3000 }"#).unwrap().esp32_link().clone();
+    assert_eq!((Duration::from_millis(500), Duration::from_secs(3)), (link.heartbeat_interval, link.liveness_timeout));
+    assert_eq!(Some(ConfigError::InvalidField("liveness_timeout_ms")), config(r#"{ "port": "/dev/ttyUSB0", "heartbeat_interval_ms": 500, "liveness_timeout_ms": 400 }"#).err());
+    assert_eq!(Some(ConfigError::InvalidField("heartbeat_interval_ms")), config(r#"{ "port": "/dev/ttyUSB0", "heartbeat_interval_ms": 0 }"#).err());
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn test_framing() {
+    use std::sync::{Arc, Mutex};
+    use crate::controller::esp32_backend::run_capture;
+    use crate::internal::frame_ops::rx_frame;
+    use crate::internal::framing::Framing;
+    use crate::internal::logger::Logger;
+    use crate::internal::procs::{proc_tx_handshake, proc_tx_reset};
+    use crate::internal::simulator::{Esp32Simulator, SimulatorConfig};
+
+    // Known encodings, zeros and delimiters included
+    assert_eq!(vec![0x01, 0x01, 0x00], Framing::Cobs.encode(&[0x00]));
+    assert_eq!(vec![0x03, 0x11, 0x22, 0x02, 0x33, 0x00], Framing::Cobs.encode(&[0x11, 0x22, 0x00, 0x33]));
+    assert_eq!(vec![0xC0, 0x01, 0xDB, 0xDC, 0xDB, 0xDD, 0xC0], Framing::Slip.encode(&[0x01, 0xC0, 0xDB]));
+    assert_eq!(Some((None, 3)), Framing::Slip.next_packet(&[0xDB, 0x01, 0xC0]));
+    assert_eq!(Some((None, 3)), Framing::Cobs.next_packet(&[0x05, 0x01, 0x00]));
+    assert_eq!(None, Framing::Cobs.next_packet(&[0x02, 0x01]));
+
+    let ready = Frame::from_cmd(Cmd::Ready, 1).unwrap();
+    let ssid  = Frame::from_cmd(Cmd::AddSSID { id: NetworkId::from_int(1), ssid: SSID::from_str("lab").unwrap() }, 2).unwrap();
+
+    for framing in [Framing::Cobs, Framing::Slip] {
+        // Boot noise, a packet holding a corrupt frame, then good ones split across reads
+        let mut corrupt = ready.as_bytes().unwrap();
+        corrupt[3] ^= 0xFF;
+        let bytes = [
+            vec![0x13, 0x37, 0xC0, 0x00, 0x42],
+            framing.encode(&corrupt),
+            framing.encode(&ready.as_bytes().unwrap()),
+            framing.encode(&ssid.as_bytes().unwrap())
+        ].concat();
+
+        let mut port = MockTransport::with_rx(&bytes);
+        let mut frame_stack = FrameStack::new();
+        frame_stack.set_framing(framing);
+        assert_eq!(Ok(ready.clone()), rx_frame(&mut frame_stack, &mut port));
+        assert_eq!(Ok(ssid .clone()), rx_frame(&mut frame_stack, &mut port));
+        assert!(frame_stack.dropped_bytes() > 0);
+        assert_eq!(Err(FrameError::TransmissionTimedOut), rx_frame(&mut frame_stack, &mut port));
+    }
+
+    // A whole capture over COBS
+    let logger = Arc::new(Mutex::new(Logger::new()));
+    let mut conn = Esp32Simulator::spawn(SimulatorConfig { framing: Framing::Cobs, ..SimulatorConfig::default() });
+    let mut frame_stack = FrameStack::new();
+    frame_stack.set_framing(Framing::Cobs);
+
+    proc_tx_handshake(&mut conn, &mut frame_stack, logger.clone()).unwrap();
+    proc_tx_reset(&mut conn, &mut frame_stack).unwrap();
+    let capture = run_capture(&logger, &mut frame_stack, &mut conn);
+    assert_eq!(4 * 18, capture.rssi_records.len());
+    assert_eq!(0, frame_stack.dropped_bytes());
+}
+
+#[test]
+fn test_record_batch() {
+    use std::sync::{Arc, Mutex};
+    use crate::controller::esp32_backend::run_capture;
+    use crate::internal::frame_type::{Grid, GridRecords, GridStep, ProtocolInfo, CAP_RECORD_BATCHES, HOST_CAPABILITIES, PROTOCOL_VERSION};
+    use crate::internal::logger::Logger;
+    use crate::internal::procs::{proc_tx_handshake, proc_tx_reset};
+    use crate::internal::simulator::{grid_positions, Esp32Simulator, SimulatorConfig};
+    use crate::internal::utils::{read_varint, write_varint, zigzag_decode, zigzag_encode};
+
+    // Varints
+    let mut bytes = vec![];
+    write_varint(300, &mut bytes);
+    assert_eq!(vec![0xAC, 0x02], bytes);
+    assert_eq!(Ok((300, 2)), read_varint(&[0xAC, 0x02, 0xFF]));
+    assert_eq!(Err(FrameError::NotEnoughBytes), read_varint(&[0xAC]));
+    assert_eq!(Err(FrameError::ValueOutOfRange), read_varint(&[0xFF; 10]));
+    assert_eq!((0, 1, 2, 3), (zigzag_encode(0), zigzag_encode(-1), zigzag_encode(1), zigzag_encode(-2)));
+    assert_eq!(i64::MIN, zigzag_decode(zigzag_encode(i64::MIN)));
+
+    // Positions on the grid and off it
+    let grid = Grid::new(Position::from_int(100, 50), StepSize::from_pitch_yaw(10, 0).unwrap());
+    assert_eq!(Some(GridStep::from_int(3, 0)), grid.step_of(&Position::from_int(130, 50)));
+    assert_eq!(Some(GridStep::from_int(-2, 0)), grid.step_of(&Position::from_int(80, 50)));
+    assert_eq!(None, grid.step_of(&Position::from_int(135, 50)));
+    assert_eq!(None, grid.step_of(&Position::from_int(130, 60)));
+    assert_eq!(Ok(Position::from_int(130, 50)), grid.position(&GridStep::from_int(3, 0)));
+    assert_eq!(Err(FrameError::ValueOutOfRange), grid.position(&GridStep::from_int(-11, 0)));
+
+    // Steps are sent from the one before, the first from the origin. Ids are varints
+    let record = |id, rssi| Record::from_components(NetworkId::from_int(id), RSSI::from_int(rssi).unwrap());
+    let cmd = Cmd::RecordBatch { entries: vec![
+        GridRecords::new(GridStep::from_int(0, 0), vec![record(1, -40), record(300, -82)]),
+        GridRecords::new(GridStep::from_int(1, 0), vec![record(1, -41)]),
+        GridRecords::new(GridStep::from_int(0, 1), vec![])
+    ]};
+    let body = vec![
+        0x06,
+        0x00, 0x00, 0x02, 0x01, 0xD8, 0xAC, 0x02, 0xAE,
+        0x02, 0x00, 0x01, 0x01, 0xD7,
+        0x01, 0x02, 0x00
+    ];
+    assert_eq!(Ok(body.clone()), cmd.as_bytes());
+    assert_eq!(Ok(cmd), Cmd::parse_body(0xE, body.len(), &body));
+
+    // Counts and ids that don't fit what's there
+    assert_eq!(Err(FrameError::NotEnoughBytes), Cmd::parse_body(0xE, 6, &[0x06, 0x00, 0x00, 0x02, 0x01, 0xD8]));
+    assert_eq!(Err(FrameError::ValueOutOfRange), Cmd::parse_body(0xE, 10, &[0x06, 0x00, 0x00, 0x01, 0xFF, 0xFF, 0xFF, 0xFF, 0x7F, 0xD8]));
+    assert_eq!(Err(FrameError::RSSIValueOutOfRange), Cmd::parse_body(0xE, 6, &[0x06, 0x00, 0x00, 0x01, 0x01, 0x01]));
+
+    // A capture comes out the same batched or not, and batched takes far fewer bytes
+    let logger = Arc::new(Mutex::new(Logger::new()));
+    let legacy = SimulatorConfig {
+        protocol: ProtocolInfo::from_components(PROTOCOL_VERSION, PROTOCOL_VERSION, HOST_CAPABILITIES & !CAP_RECORD_BATCHES),
+        ..SimulatorConfig::default()
+    };
+
+    let mut captures = vec![];
+    for config in [SimulatorConfig::default(), legacy] {
+        let mut conn = Esp32Simulator::spawn(config);
+        let mut frame_stack = FrameStack::new();
+        proc_tx_handshake(&mut conn, &mut frame_stack, logger.clone()).unwrap();
+        proc_tx_reset(&mut conn, &mut frame_stack).unwrap();
+
+        captures.push(run_capture(&logger, &mut frame_stack, &mut conn).rssi_records);
+    }
+
+    let start     = Position::from_degrees(10.0, 0.0).unwrap();
+    let step_size = StepSize::from_degrees(20.0, 20.0).unwrap();
+    let positions = grid_positions(&start, &step_size);
+    assert_eq!(positions.len(), captures[0].len());
+    assert_eq!(captures[0], captures[1]);
+
+    let grid = Grid::new(start, step_size);
+    let legacy_size: usize = positions.iter()
+        .map(|position| {
+            let records = captures[0][position].clone();
+            Cmd::RecordRSSI { position: position.clone(), record_count: records.len() as u32, records }.as_bytes().unwrap().len()
+        })
+        .sum();
+    let batch_size: usize = positions.chunks(8)
+        .map(|chunk| {
+            let entries = chunk.iter().map(|position| GridRecords::new(grid.step_of(position).unwrap(), captures[0][position].clone())).collect();
+            Cmd::RecordBatch { entries }.as_bytes().unwrap().len()
+        })
+        .sum();
+    assert!(batch_size * 2 < legacy_size, "{} bytes batched against {}", batch_size, legacy_size);
+}
+
+#[test]
+fn test_network_info() {
+    use std::sync::{Arc, Mutex};
+    use crate::controller::esp32_backend::run_capture;
+    use crate::internal::frame_type::{AuthMode, Band, NetworkInfo, ProtocolInfo, SecondaryChannel, CAP_NETWORK_INFO, HOST_CAPABILITIES, PROTOCOL_VERSION};
+    use crate::internal::logger::Logger;
+    use crate::internal::procs::{proc_tx_handshake, proc_tx_reset};
+    use crate::internal::simulator::{Esp32Simulator, SimulatorConfig};
+
+    let bssid = BSSID::new([0x24, 0x0A, 0xC4, 0x00, 0x00, 0x01]);
+    let info  = NetworkInfo::from_components(bssid.clone(), 36, AuthMode::Wpa2Wpa3Psk, true).unwrap().with_secondary_channel(SecondaryChannel::Above);
+    let cmd   = Cmd::NetworkInfo { id: NetworkId::from_int(2), info: info.clone() };
+    let body  = vec![0x07, 0x00, 0x00, 0x00, 0x02, 0x24, 0x0A, 0xC4, 0x00, 0x00, 0x01, 0x24, 0x01, 0x07, 0x01];
+    assert_eq!(Ok(body.clone()), cmd.as_bytes());
+    assert_eq!(Ok(cmd), Cmd::parse_body(0xE, body.len(), &body));
+    assert_eq!((Band::Band5G, true), (info.band(), info.hidden()));
+    assert_eq!(json::json!({
+        "bssid": { "bytes": [0x24, 0x0A, 0xC4, 0x00, 0x00, 0x01] },
+        "channel": 36,
+        "band": "5GHz",
+        "secondary_channel": "above",
+        "auth_mode": "wpa2_wpa3_psk",
+        "hidden": true
+    }), json::to_value(&info).unwrap());
+
+    // Auth modes from newer firmware are kept as they came
+    let mut unknown = body.clone();
+    unknown[13] = 0x0C;
+    match Cmd::parse_body(0xE, unknown.len(), &unknown) {
+        Ok(Cmd::NetworkInfo { info, .. }) => assert_eq!(AuthMode::Other(0x0C), info.auth_mode()),
+        other => panic!("expected network info, got {:?}", other)
+    }
+
+    // Channels no band has, secondary channels the ESP32 doesn't report and bodies of the wrong length
+    let mut channel = body.clone();
+    channel[11] = 0;
+    assert_eq!(Err(FrameError::ValueOutOfRange), Cmd::parse_body(0xE, channel.len(), &channel));
+    let mut secondary = body.clone();
+    secondary[12] = 3;
+    assert_eq!(Err(FrameError::ValueOutOfRange), Cmd::parse_body(0xE, secondary.len(), &secondary));
+    assert_eq!(Err(FrameError::LengthValueOutOfRange), Cmd::parse_body(0xE, body.len() - 1, &body));
+
+    // A capture keeps what the scan said about every access point, unless the firmware can't tell
+    let logger = Arc::new(Mutex::new(Logger::new()));
+    let legacy = SimulatorConfig {
+        protocol: ProtocolInfo::from_components(PROTOCOL_VERSION, PROTOCOL_VERSION, HOST_CAPABILITIES & !CAP_NETWORK_INFO),
+        ..SimulatorConfig::default()
+    };
+
+    for (config, expected) in [(SimulatorConfig::default(), 3), (legacy, 0)] {
+        let mut conn = Esp32Simulator::spawn(config);
+        let mut frame_stack = FrameStack::new();
+        proc_tx_handshake(&mut conn, &mut frame_stack, logger.clone()).unwrap();
+        proc_tx_reset(&mut conn, &mut frame_stack).unwrap();
+
+        let capture = run_capture(&logger, &mut frame_stack, &mut conn);
+        assert_eq!(expected, capture.networks.values().map(Vec::len).sum::<usize>());
+
+        if expected > 0 {
+            let lab = &capture.networks[&NetworkId::from_int(1)];
+            assert_eq!(vec![(1, Band::Band2G4), (36, Band::Band5G)], lab.iter().map(|info| (info.channel(), info.band())).collect::<Vec<_>>());
+            assert_eq!(AuthMode::Open, capture.networks[&NetworkId::from_int(2)][0].auth_mode());
+        }
+    }
+}
+
+#[test]
+fn test_device_error() {
+    use std::sync::{Arc, Mutex};
+    use crate::controller::esp32_backend::{run_capture, CaptureOutcome};
+    use crate::internal::frame_ops::rx_frame_blocking_expect;
+    use crate::internal::frame_type::{DeviceError, DeviceErrorCode, ErrorAction};
+    use crate::internal::logger::Logger;
+    use crate::internal::procs::{proc_tx_handshake, proc_tx_reset};
+    use crate::internal::simulator::{grid_positions, Esp32Simulator, SimulatorConfig};
+
+    let error = DeviceError::new(DeviceErrorCode::StepperStall, Position::from_int(1, 2), "yaw".to_string());
+    let cmd   = Cmd::DeviceError { error: error.clone() };
+    let body  = vec![0x08, 0x01, 0x00, 0x00, 0x00, 0x01, 0x00, 0x00, 0x00, 0x02, b'y', b'a', b'w'];
+    assert_eq!(Ok(body.clone()), cmd.as_bytes());
+    assert_eq!(Ok(cmd), Cmd::parse_body(0xE, body.len(), &body));
+    assert_eq!(Err(FrameError::LengthValueOutOfRange), Cmd::parse_body(0xE, 9, &body));
+    assert_eq!(Err(FrameError::InvalidUTF8), Cmd::parse_body(0xE, 11, &[&body[..10], &[0xFF]].concat()));
+
+    // Codes from newer firmware are kept, and stop the capture
+    assert_eq!(DeviceErrorCode::Other(0x42), DeviceErrorCode::from_int(0x42));
+    assert_eq!(0x42, DeviceErrorCode::Other(0x42).as_int());
+    assert_eq!(ErrorAction::Abort, DeviceErrorCode::Other(0x42).action());
+    assert_eq!(ErrorAction::RetryPosition, DeviceErrorCode::StepperStall.action());
+    assert_eq!(ErrorAction::FailProject, DeviceErrorCode::SensorInitFailed.action());
+
+    // An ESP32 that can't get going says so instead of answering
+    let fault = DeviceError::new(DeviceErrorCode::SensorInitFailed, Position::from_int(0, 0), "MPU6050 not found".to_string());
+    let frame = Frame::from_cmd(Cmd::DeviceError { error: fault }, 0).unwrap();
+    let mut port = MockTransport::with_rx(&frame.as_bytes().unwrap());
+    assert_eq!(Err(FrameError::DeviceFault(DeviceErrorCode::SensorInitFailed)), rx_frame_blocking_expect(&mut FrameStack::new(), &mut port, Cmd::Ready.as_int().unwrap()));
+
+    // A stall gets the position measured again, the rest stop the capture
+    let logger = Arc::new(Mutex::new(Logger::new()));
+    let grid = grid_positions(&Position::from_degrees(10.0, 0.0).unwrap(), &StepSize::from_degrees(20.0, 20.0).unwrap());
+    let cases = [
+        (DeviceErrorCode::StepperStall    , CaptureOutcome::Complete),
+        (DeviceErrorCode::LowPower        , CaptureOutcome::Aborted ),
+        (DeviceErrorCode::SensorInitFailed, CaptureOutcome::Failed  ),
+    ];
+
+    for (code, outcome) in cases {
+        let mut conn = Esp32Simulator::spawn(SimulatorConfig { fault: Some((5, code)), ..SimulatorConfig::default() });
+        let mut frame_stack = FrameStack::new();
+        proc_tx_handshake(&mut conn, &mut frame_stack, logger.clone()).unwrap();
+        proc_tx_reset(&mut conn, &mut frame_stack).unwrap();
+
+        let capture = run_capture(&logger, &mut frame_stack, &mut conn);
+        assert_eq!(outcome, capture.outcome);
+        assert_eq!(vec![(code, &grid[5])], capture.errors.iter().map(|error| (error.code(), error.position())).collect::<Vec<_>>());
+
+        let measured = if outcome == CaptureOutcome::Complete { grid.len() } else { 5 };
+        assert_eq!(measured, capture.rssi_records.len());
+    }
+}
+
+#[test]
+fn test_capture_control() {
+    use std::sync::{mpsc, Arc, Mutex};
+    use std::time::{Duration, Instant};
+    use crate::controller::esp32_backend::{run_capture_with_control, CaptureData, CaptureOutcome};
+    use crate::internal::frame_type::{ProtocolInfo, CAP_CAPTURE_CONTROL, HOST_CAPABILITIES, PROTOCOL_VERSION};
+    use crate::internal::logger::Logger;
+    use crate::internal::procs::{proc_tx_handshake, proc_tx_reset};
+    use crate::internal::simulator::{grid_positions, Esp32Simulator, SimulatorConfig};
+    use crate::internal::threading_comm::Message;
+
+    for (cmd, sub_opcode) in [(Cmd::Pause, 0x09), (Cmd::Resume, 0x0A), (Cmd::Abort, 0x0B)] {
+        assert_eq!(Ok(vec![sub_opcode]), cmd.as_bytes());
+        assert_eq!(Ok(cmd), Cmd::parse_body(0xE, 1, &[sub_opcode]));
+        assert_eq!(Err(FrameError::LengthValueOutOfRange), Cmd::parse_body(0xE, 2, &[sub_opcode, 0x00]));
+    }
+
+    // Runs a capture on its own thread, taking orders from the returned end of the channel
+    let logger = Arc::new(Mutex::new(Logger::new()));
+    let spawn_capture = |config: SimulatorConfig| {
+        let mut conn = Esp32Simulator::spawn(config);
+        let mut frame_stack = FrameStack::new();
+        proc_tx_handshake(&mut conn, &mut frame_stack, logger.clone()).unwrap();
+        proc_tx_reset(&mut conn, &mut frame_stack).unwrap();
+
+        let (tx_web, rx_web) = mpsc::channel::<Message>();
+        let (tx_esp, rx_esp) = mpsc::channel::<Message>();
+        let logger = logger.clone();
+        let capture = std::thread::spawn(move || -> CaptureData {
+            run_capture_with_control(&logger, &mut frame_stack, &mut conn, Some((&rx_web, &tx_esp)))
+        });
+
+        (capture, tx_web, rx_esp)
+    };
+    let grid = grid_positions(&Position::from_degrees(10.0, 0.0).unwrap(), &StepSize::from_degrees(20.0, 20.0).unwrap());
+
+    // A pause holds the capture up, status requests are still answered meanwhile
+    let start = Instant::now();
+    let (capture, tx_web, rx_esp) = spawn_capture(SimulatorConfig { step_delay: Duration::from_millis(5), ..SimulatorConfig::default() });
+    std::thread::sleep(Duration::from_millis(100));
+    tx_web.send(Message::PauseCapture).unwrap();
+    tx_web.send(Message::BackendStatusRequest).unwrap();
+    assert!(Ok(Message::BackendReady(true)) == rx_esp.recv_timeout(Duration::from_secs(5)));
+    std::thread::sleep(Duration::from_secs(1));
+    tx_web.send(Message::ResumeCapture).unwrap();
+
+    let data = capture.join().unwrap();
+    assert_eq!(CaptureOutcome::Complete, data.outcome);
+    assert_eq!(grid.len(), data.rssi_records.len());
+    assert!(start.elapsed() >= Duration::from_millis(1100) + 5 * Duration::from_millis(grid.len() as u64));
+
+    // An abort keeps everything measured up to it, and only that
+    for protocol in [ProtocolInfo::host(), ProtocolInfo::from_components(PROTOCOL_VERSION, PROTOCOL_VERSION, HOST_CAPABILITIES & !CAP_CAPTURE_CONTROL)] {
+        let (capture, tx_web, _rx_esp) = spawn_capture(SimulatorConfig { protocol, step_delay: Duration::from_millis(10), ..SimulatorConfig::default() });
+        std::thread::sleep(Duration::from_millis(200));
+        tx_web.send(Message::AbortCapture).unwrap();
+
+        let data = capture.join().unwrap();
+        assert_eq!(CaptureOutcome::Aborted, data.outcome);
+        assert!((1..grid.len()).contains(&data.rssi_records.len()), "{} positions measured", data.rssi_records.len());
+        assert!(grid[..data.rssi_records.len()].iter().all(|position| data.rssi_records.contains_key(position)));
+    }
+}
+
+#[test]
+fn test_position_tracker() {
+    use std::sync::{Arc, Mutex};
+    use crate::controller::esp32_backend::{run_capture, CaptureData};
+    use crate::internal::frame_type::{Grid, ProtocolInfo};
+    use crate::internal::logger::Logger;
+    use crate::internal::position::{PositionMismatch, PositionTracker};
+    use crate::internal::procs::{proc_tx_handshake, proc_tx_reset};
+    use crate::internal::simulator::{grid_positions, Esp32Simulator, SimulatorConfig};
+
+    // The rig starts at the origin, and has to report from wherever it was sent first
+    let grid = Grid::new(Position::from_int(100, 0), StepSize::from_pitch_yaw(10, 20).unwrap());
+    let mut tracker = PositionTracker::new(Some(grid));
+    assert_eq!(Some(&Position::from_int(100, 0)), tracker.current());
+    assert_eq!(None, tracker.report(&Position::from_int(100, 0)));
+    assert_eq!(None, tracker.report(&Position::from_int(110, 20)));
+    assert_eq!(Some(PositionMismatch::OffGrid { reported: Position::from_int(115, 20) }), tracker.report(&Position::from_int(115, 20)));
+    assert_eq!(Some(&Position::from_int(115, 20)), tracker.current());
+
+    tracker.command(Position::from_int(120, 40));
+    assert_eq!(Some(PositionMismatch::Unexpected { expected: Position::from_int(120, 40), reported: Position::from_int(130, 40) }), tracker.report(&Position::from_int(130, 40)));
+    assert_eq!(None, tracker.report(&Position::from_int(120, 40)));
+
+    // Without a grid there's nothing to go on until the ESP32 says where it is
+    let mut tracker = PositionTracker::new(None);
+    assert_eq!(None, tracker.current());
+    assert_eq!(None, tracker.report(&Position::from_int(1, 2)));
+    assert_eq!(Some(&Position::from_int(1, 2)), tracker.current());
+
+    // An ESP32 that lost power asks where it is, and measures again from there what it hadn't sent yet
+    let logger = Arc::new(Mutex::new(Logger::new()));
+    let capture = |config: SimulatorConfig| -> CaptureData {
+        let mut conn = Esp32Simulator::spawn(config);
+        let mut frame_stack = FrameStack::new();
+        proc_tx_handshake(&mut conn, &mut frame_stack, logger.clone()).unwrap();
+        proc_tx_reset(&mut conn, &mut frame_stack).unwrap();
+
+        run_capture(&logger, &mut frame_stack, &mut conn)
+    };
+    let positions = grid_positions(&Position::from_degrees(10.0, 0.0).unwrap(), &StepSize::from_degrees(20.0, 20.0).unwrap());
+
+    for protocol in [ProtocolInfo::host(), ProtocolInfo::legacy()] {
+        let reference = capture(SimulatorConfig { protocol: protocol.clone(), ..SimulatorConfig::default() });
+        assert_eq!(positions.len(), reference.rssi_records.len());
+        assert!(reference.position_mismatches.is_empty());
+
+        for brownout in [0, 12] {
+            let data = capture(SimulatorConfig { protocol: protocol.clone(), brownout: Some(brownout), ..SimulatorConfig::default() });
+            assert_eq!(reference.rssi_records, data.rssi_records, "brownout at {}", brownout);
+            assert!(data.position_mismatches.is_empty(), "{:?}", data.position_mismatches);
+        }
+    }
+}
+
+#[test]
+fn test_session() {
+    use std::sync::{Arc, Mutex};
+    use crate::internal::frame_ops::{rx_frame_blocking, rx_frame_ref};
+    use crate::internal::frame_type::ProtocolInfo;
+    use crate::internal::session::SessionState;
+
+    // One way through, from the handshake to the end of the capture. Any step can be tried again
+    let path = [SessionState::Disconnected, SessionState::Handshaking, SessionState::Configuring, SessionState::Capturing, SessionState::Finished];
+    for step in path.windows(2) {
+        assert!(step[0].can_enter(step[1]));
+        assert!(!step[1].can_enter(step[0]) || step[0] == SessionState::Disconnected);
+        assert!(step[0].can_enter(step[0]));
+    }
+    assert!(SessionState::Capturing.can_enter(SessionState::Failed));
+    assert!(SessionState::Failed.can_enter(SessionState::Handshaking));
+    assert!(!SessionState::Handshaking.can_enter(SessionState::Capturing));
+    assert!(!SessionState::Disconnected.can_enter(SessionState::Finished));
+
+    // Records only come during the capture, link upkeep at any time, and host commands never
+    let records = vec![Record::from_components(NetworkId::from_int(1), RSSI::from_int(-40).unwrap())];
+    let record  = Cmd::RecordRSSI { position: Position::from_int(1, 2), record_count: 1, records };
+    assert!(SessionState::Capturing.accepts_cmd(&record));
+    assert!(!SessionState::Configuring.accepts_cmd(&record));
+    assert!(SessionState::Handshaking.accepts_cmd(&Cmd::Ack { frame_id: 0, info: Some(ProtocolInfo::host()) }));
+    assert!(!SessionState::Capturing.accepts_cmd(&Cmd::StartOfTransmission { info: Some(ProtocolInfo::host()) }));
+    assert!(SessionState::Finished.accepts_cmd(&Cmd::RequestAck { frame_id: 3 }));
+    assert!(!SessionState::Disconnected.accepts_cmd(&Cmd::RequestAck { frame_id: 3 }));
+    assert!(!SessionState::Capturing.accepts_cmd(&Cmd::SetParams { position: Position::from_int(0, 0), step_size: StepSize::from_pitch_yaw(1, 1).unwrap(), measurements_per_step: 1 }));
+
+    // Illegal transitions leave the session where it was
+    let mut frame_stack = FrameStack::new();
+    assert_eq!(Ok(()), frame_stack.enter_session_state(SessionState::Finished));
+    frame_stack.set_session(Some(Arc::new(Mutex::new(SessionState::Handshaking))));
+    assert_eq!(Err(FrameError::InvalidCommandSequence), frame_stack.enter_session_state(SessionState::Finished));
+    assert_eq!(Some(SessionState::Handshaking), frame_stack.session_state());
+    assert_eq!(Ok(()), frame_stack.enter_session_state(SessionState::Configuring));
+
+    // A frame that comes at the wrong time is skipped over, but still acked
+    let bytes = [
+        Frame::from_cmd(record.clone(), 0).unwrap().as_bytes().unwrap(),
+        Frame::from_cmd(Cmd::Ready, 1).unwrap().as_bytes().unwrap(),
+    ].concat();
+    let mut port = MockTransport::with_rx(&bytes);
+    assert_eq!(Cmd::Ready, *rx_frame_blocking(&mut frame_stack, &mut port).unwrap().get_cmd());
+    assert_eq!(1, frame_stack.rejected_frames());
+    assert_eq!(2, frame_stack.get_rx_frame_queue().len());
+
+    let mut frame_stack = FrameStack::new();
+    frame_stack.set_session(Some(Arc::new(Mutex::new(SessionState::Configuring))));
+    let mut port = MockTransport::with_rx(&bytes);
+    assert_eq!(Cmd::Ready, rx_frame_ref(&mut frame_stack, &mut port).unwrap().into_cmd().into_owned());
+    assert_eq!(1, frame_stack.rejected_frames());
+
+    // Once capturing, the same records go through
+    frame_stack.enter_session_state(SessionState::Capturing).unwrap();
+    let mut port = MockTransport::with_rx(&Frame::from_cmd(record.clone(), 2).unwrap().as_bytes().unwrap());
+    assert_eq!(record, rx_frame_ref(&mut frame_stack, &mut port).unwrap().into_cmd().into_owned());
+}
+
+#[test]
+fn test_resume_capture() {
+    use std::sync::{Arc, Mutex};
+    use crate::controller::esp32_backend::{resume_capture, run_capture, CaptureOutcome};
+    use crate::internal::frame_type::ProtocolInfo;
+    use crate::internal::logger::Logger;
+    use crate::internal::procs::{proc_tx_handshake, proc_tx_reset};
+    use crate::internal::session::SessionState;
+    use crate::internal::simulator::{Esp32Simulator, SimulatorConfig};
+
+    let logger = Arc::new(Mutex::new(Logger::new()));
+    for protocol in [ProtocolInfo::host(), ProtocolInfo::legacy()] {
+        let session = Arc::new(Mutex::new(SessionState::Disconnected));
+        let start = |config: SimulatorConfig| {
+            let mut conn = Esp32Simulator::spawn(config);
+            let mut frame_stack = FrameStack::new();
+            proc_tx_handshake(&mut conn, &mut frame_stack, logger.clone()).unwrap();
+            proc_tx_reset(&mut conn, &mut frame_stack).unwrap();
+            *session.lock().unwrap() = SessionState::Configuring;
+            frame_stack.set_session(Some(session.clone()));
+            frame_stack.enter_session_state(SessionState::Capturing).unwrap();
+
+            (frame_stack, conn)
+        };
+
+        let (mut frame_stack, mut conn) = start(SimulatorConfig { protocol: protocol.clone(), ..SimulatorConfig::default() });
+        let reference = run_capture(&logger, &mut frame_stack, &mut conn);
+
+        // The line drops partway through, what came in before it is kept
+        let (mut frame_stack, mut conn) = start(SimulatorConfig { protocol: protocol.clone(), disconnect: Some(20), ..SimulatorConfig::default() });
+        let mut data = run_capture(&logger, &mut frame_stack, &mut conn);
+        assert_eq!(CaptureOutcome::Interrupted, data.outcome);
+        assert_eq!(SessionState::Disconnected, *session.lock().unwrap());
+        assert!((1..=20).contains(&data.rssi_records.len()), "{} positions measured", data.rssi_records.len());
+        assert!(data.last_recorded.as_ref().is_some_and(|position| data.rssi_records.contains_key(position)));
+
+        // Over a new line, the ESP32 goes on from the last position it fully recorded and nothing is measured twice
+        let mut conn = Esp32Simulator::spawn(SimulatorConfig { protocol: protocol.clone(), ..SimulatorConfig::default() });
+        let mut frame_stack = FrameStack::new();
+        frame_stack.set_session(Some(session.clone()));
+        resume_capture(&logger, &mut frame_stack, &mut conn, None, &[], &mut data).unwrap();
+        assert_eq!(CaptureOutcome::Complete, data.outcome);
+        assert_eq!(SessionState::Finished, *session.lock().unwrap());
+        assert_eq!(reference.rssi_records, data.rssi_records);
+        assert!(data.position_mismatches.is_empty(), "{:?}", data.position_mismatches);
+    }
+}
+
+#[test]
+fn test_capture_link_loss() {
+    use std::sync::{Arc, Mutex};
+    use crate::controller::esp32_backend::{run_capture, CaptureOutcome};
+    use crate::internal::frame_type::ProtocolInfo;
+    use crate::internal::logger::Logger;
+    use crate::internal::session::SessionState;
+
+    // Fails every read once what it was given is drained, like a port that went away without closing
+    struct FailingLine(MockTransport);
+    impl std::io::Read for FailingLine {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            match self.0.read(buf) {
+                Err(e) if e.kind() == std::io::ErrorKind::TimedOut => Err(std::io::Error::from(std::io::ErrorKind::Other)),
+                result => result
+            }
+        }
+    }
+    impl std::io::Write for FailingLine {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.write(buf)
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+    impl FrameTransport for FailingLine {
+        fn set_read_timeout(&mut self, _: std::time::Duration) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    let logger = Arc::new(Mutex::new(Logger::new()));
+    let rssi = |x, y, id| Frame::from_cmd(Cmd::RecordRSSI { position: Position::from_int(x, y), record_count: 1, records: vec![Record::from_components(NetworkId::from_int(1), RSSI::from_int(-82).unwrap())] }, id).unwrap().as_bytes().unwrap();
+    let capture = |bytes: Vec<u8>| {
+        let session = Arc::new(Mutex::new(SessionState::Capturing));
+        let mut frame_stack = FrameStack::new();
+        frame_stack.set_protocol(ProtocolInfo::legacy());
+        frame_stack.set_session(Some(session.clone()));
+        let data = run_capture(&logger, &mut frame_stack, &mut FailingLine(MockTransport::with_rx(&bytes)));
+        let state = *session.lock().unwrap();
+        (data, state)
+    };
+
+    // Firmware without heartbeats keeps its link up, a line that keeps failing still ends the capture. The
+    // position it dropped in the middle of is left to be measured again
+    let (data, state) = capture([rssi(0, 0, 1), rssi(0, 1, 2), rssi(0, 1, 3)].concat());
+    assert_eq!(CaptureOutcome::Interrupted, data.outcome);
+    assert_eq!(SessionState::Disconnected, state);
+    assert_eq!(Some(Position::from_int(0, 0)), data.last_recorded);
+    assert_eq!(vec![&Position::from_int(0, 0)], data.rssi_records.keys().collect::<Vec<_>>());
+
+    // Nothing is known to be fully recorded before the next position starts
+    let (data, _) = capture(rssi(0, 0, 1));
+    assert_eq!(CaptureOutcome::Interrupted, data.outcome);
+    assert_eq!(None, data.last_recorded);
+    assert!(data.rssi_records.is_empty());
+
+    // Or before the end of the transmission
+    let (data, state) = capture([rssi(0, 0, 1), Frame::from_cmd(Cmd::EndOfTransmission, 2).unwrap().as_bytes().unwrap()].concat());
+    assert_eq!(CaptureOutcome::Complete, data.outcome);
+    assert_eq!(SessionState::Finished, state);
+    assert_eq!(Some(Position::from_int(0, 0)), data.last_recorded);
+}
+
+#[test]
+fn test_serial_settings() {
+    use std::io::{self, Read, Write};
+    use std::sync::{Arc, Mutex};
+    use std::time::Duration;
+    use crate::internal::config::{load_config_from, ConfigError, SerialConfig, TransportConfig};
+    use crate::internal::frame_type::ProtocolInfo;
+    use crate::internal::logger::Logger;
+    use crate::internal::procs::proc_tx_handshake_fallback;
+    use crate::internal::simulator::{Esp32Simulator, SimulatedLink, SimulatorConfig};
+    use crate::internal::transport::{FrameTransport, READ_TIMEOUT};
+
+    // Line settings are all optional, the ones given have to be something a port can be set to
+    let path = std::env::temp_dir().join(format!("serial_config_{}.json", std::process::id()));
+    let config = |esp32: &str| {
+        std::fs::write(&path, format!(r#"{{ "config": {{ "esp32": {esp32}, "esp32_cam": {{ "ip": "10.42.0.65" }} }} }}"#)).unwrap();
+        load_config_from(&path)
+    };
+    let settings = |esp32: &str| match config(esp32).unwrap().esp32_transport().clone() {
+        TransportConfig::Serial { settings, .. } => settings,
+        transport => panic!("{} isn't a serial port", transport)
+    };
+
+    assert_eq!(SerialConfig::default(), settings(r#"{ "port": "/dev/ttyUSB0" }"#));
+    let serial = settings(r#"{ "port": "/dev/ttyUSB0", "baud_rate": 921600, "fallback_baud_rates": [115200, 57600], "parity": "even",
+                               "stop_bits": 2, "flow_control": "hardware", "read_timeout_ms": 5000 }"#);
+    assert_eq!(SerialConfig {
+        baud_rate          : 921600,
+        fallback_baud_rates: vec![115200, 57600],
+        parity             : serial::ParityEven,
+        stop_bits          : serial::Stop2,
+        flow_control       : serial::FlowHardware,
+        read_timeout       : Duration::from_secs(5)
+    }, serial);
+    assert_eq!(vec![921600, 115200, 57600], serial.baud_rates());
+    assert_eq!(READ_TIMEOUT, config(r#"{ "port": "/dev/ttyUSB0" }"#).unwrap().esp32_transport().read_timeout());
+
+    // A bad one is reported by name, instead of the line quietly running at the defaults
+    for (esp32, field) in [(r#"{ "port": "/dev/ttyUSB0", "baud_rate": 12345 }"#                      , "baud_rate"),
+                           (r#"{ "port": "/dev/ttyUSB0", "baud_rate": "115200" }"#                   , "baud_rate"),
+                           (r#"{ "port": "/dev/ttyUSB0", "fallback_baud_rates": [57600, 300] }"#     , "fallback_baud_rates"),
+                           (r#"{ "port": "/dev/ttyUSB0", "fallback_baud_rates": [57600, 115200] }"#  , "fallback_baud_rates"),
+                           (r#"{ "port": "/dev/ttyUSB0", "parity": "mark" }"#                        , "parity"),
+                           (r#"{ "port": "/dev/ttyUSB0", "stop_bits": 3 }"#                          , "stop_bits"),
+                           (r#"{ "port": "/dev/ttyUSB0", "flow_control": "rts" }"#                   , "flow_control"),
+                           (r#"{ "port": "/dev/ttyUSB0", "read_timeout_ms": 0 }"#                    , "read_timeout_ms")] {
+        assert_eq!(Some(ConfigError::InvalidField(field)), config(esp32).err(), "{}", esp32);
+    }
+    std::fs::remove_file(&path).unwrap();
+
+    // An ESP32 that only makes sense of the line at one rate, the others read as silence
+    struct BaudGate {
+        link     : SimulatedLink,
+        baud_rate: u32,
+        rates    : Vec<u32>
+    }
+
+    impl BaudGate {
+        fn open(&self) -> bool {
+            self.baud_rate == 57600
         }
     }
+
+    impl Read for BaudGate {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            if !self.open() {
+                return Err(io::Error::from(io::ErrorKind::TimedOut));
+            }
+            self.link.read(buf)
+        }
+    }
+
+    impl Write for BaudGate {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            if !self.open() {
+                return Ok(buf.len());
+            }
+            self.link.write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            self.link.flush()
+        }
+    }
+
+    impl FrameTransport for BaudGate {
+        fn set_read_timeout(&mut self, timeout: Duration) -> io::Result<()> {
+            self.link.set_read_timeout(timeout)
+        }
+
+        fn set_baud_rate(&mut self, baud_rate: u32) -> io::Result<()> {
+            self.baud_rate = baud_rate;
+            self.rates.push(baud_rate);
+            Ok(())
+        }
+    }
+
+    // The handshake goes down the list until the ESP32 answers, and the line is left at that rate
+    let logger = Arc::new(Mutex::new(Logger::new()));
+    let mut port = BaudGate { link: Esp32Simulator::spawn(SimulatorConfig::default()), baud_rate: 115200, rates: vec![] };
+    let mut frame_stack = FrameStack::new();
+    let protocol = proc_tx_handshake_fallback(&mut port, &mut frame_stack, logger.clone(), &[115200, 57600, 9600]).unwrap();
+    assert_eq!(ProtocolInfo::host().version(), protocol.version());
+    assert_eq!(vec![115200, 57600], port.rates);
+    assert_eq!(57600, port.baud_rate);
+
+    // It gives up once none of them worked
+    let mut port = BaudGate { link: Esp32Simulator::spawn(SimulatorConfig::default()), baud_rate: 115200, rates: vec![] };
+    assert_eq!(Err(FrameError::TransmissionTimedOut), proc_tx_handshake_fallback(&mut port, &mut FrameStack::new(), logger.clone(), &[115200, 9600]));
+    assert_eq!(vec![115200, 9600], port.rates);
+}
+
+#[test]
+fn test_port_discovery() {
+    use std::path::PathBuf;
+    use crate::internal::config::{load_config_from, ConfigError, SerialConfig, TransportConfig};
+    use crate::internal::discovery::{candidate_ports_in, discover_port, unclaimed_ports, DiscoveredPort, ProbeResult};
+    use crate::internal::framing::Framing;
+
+    // Ports named by id come first, the tty they link to isn't listed twice, and other ttys aren't listed at all
+    let dev = std::env::temp_dir().join(format!("discovery_dev_{}", std::process::id()));
+    std::fs::create_dir_all(dev.join("serial/by-id")).unwrap();
+    for tty in ["ttyUSB1", "ttyUSB0", "ttyACM0", "ttyS0", "null"] {
+        std::fs::write(dev.join(tty), []).unwrap();
+    }
+    std::os::unix::fs::symlink("../../ttyACM0", dev.join("serial/by-id/usb-Espressif_USB_JTAG_serial_debug_unit-if00")).unwrap();
+    assert_eq!(vec![
+        dev.join("serial/by-id/usb-Espressif_USB_JTAG_serial_debug_unit-if00"),
+        dev.join("ttyUSB0"),
+        dev.join("ttyUSB1")
+    ], candidate_ports_in(&dev));
+
+    // Ports another rig is configured on are left to it, whichever name it knows them by
+    let claimed = [dev.join("ttyACM0"), dev.join("ttyUSB1")];
+    assert_eq!(vec![dev.join("ttyUSB0")], unclaimed_ports(candidate_ports_in(&dev), &claimed));
+    std::fs::remove_dir_all(&dev).unwrap();
+
+    // Each port is probed with a SoT until one answers, the ones that don't are reported all the same
+    let candidates: Vec<PathBuf> = ["/dev/ttyUSB0", "/dev/ttyUSB1", "/dev/ttyACM0"].iter().map(PathBuf::from).collect();
+    let sot = Frame::from_cmd(Cmd::StartOfTransmission { info: None }, 0).unwrap().as_bytes().unwrap();
+    let open = |path: &std::path::Path| match path.to_str() {
+        Some("/dev/ttyUSB0") => Err(std::io::Error::from(std::io::ErrorKind::PermissionDenied)),
+        Some("/dev/ttyACM0") => Ok(MockTransport::with_rx(&Frame::from_cmd(Cmd::Ack { frame_id: 0, info: None }, 0).unwrap().as_bytes().unwrap())),
+        _ => Ok(MockTransport::default())
+    };
+
+    let (found, port) = discover_port(&candidates, None, &[115200], Framing::Raw, open);
+    assert_eq!(vec![
+        DiscoveredPort { path: candidates[0].clone(), result: ProbeResult::Unavailable },
+        DiscoveredPort { path: candidates[1].clone(), result: ProbeResult::Silent      },
+        DiscoveredPort { path: candidates[2].clone(), result: ProbeResult::Answered    },
+    ], found);
+    let (path, port) = port.unwrap();
+    assert_eq!(candidates[2], path);
+    assert_eq!(sot, port.tx);
+
+    // The one that answered last time is tried first, nothing answering leaves us empty handed
+    let (found, port) = discover_port(&candidates, Some(&candidates[2]), &[115200], Framing::Raw, open);
+    assert_eq!(vec![DiscoveredPort { path: candidates[2].clone(), result: ProbeResult::Answered }], found);
+    assert!(port.is_some());
+    let (found, port) = discover_port(&candidates[..2], Some(&candidates[2]), &[115200, 57600], Framing::Raw, open);
+    assert_eq!(2, found.len());
+    assert!(port.is_none());
+
+    // A serial transport without a port looks for one
+    let path = std::env::temp_dir().join(format!("discovery_config_{}.json", std::process::id()));
+    let transport = |esp32: &str| {
+        std::fs::write(&path, format!(r#"{{ "config": {{ "esp32": {esp32}, "esp32_cam": {{ "ip": "10.42.0.65" }} }} }}"#)).unwrap();
+        load_config_from(&path).map(|config| config.esp32_transport().clone())
+    };
+    let discover = Ok(TransportConfig::Discover { settings: SerialConfig::default() });
+    assert_eq!(discover, transport(r#"{ "transport": "serial" }"#));
+    assert_eq!(discover, transport(r#"{ "port": "auto" }"#));
+    assert_eq!(Ok(TransportConfig::Serial { port: String::from("/dev/ttyUSB0"), settings: SerialConfig::default() }), transport(r#"{ "port": "/dev/ttyUSB0" }"#));
+    assert_eq!(Err(ConfigError::InvalidField("port")), transport(r#"{ "port": 0 }"#));
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn test_rig_registry() {
+    use std::sync::{Arc, Mutex};
+    use std::time::{Duration, Instant};
+    use rocket::tokio::runtime::Runtime;
+    use crate::controller::esp32_backend::launch_esp32_backends;
+    use crate::internal::config::{load_rig_configs_from, ConfigError, DEFAULT_RIG_ID, TransportConfig};
+    use crate::internal::logger::Logger;
+    use crate::internal::registry::{Rig, RigRegistry};
+    use crate::internal::session::SessionState;
+    use crate::internal::threading_comm::{Esp32Status, Message};
+
+    // Each rig takes what it doesn't set from the ESP32 section, and needs an id and a port of its own
+    let path = std::env::temp_dir().join(format!("rig_config_{}.json", std::process::id()));
+    let configs = |esp32: &str| {
+        std::fs::write(&path, format!(r#"{{ "config": {{ "esp32": {esp32}, "esp32_cam": {{ "ip": "10.42.0.65" }} }} }}"#)).unwrap();
+        load_rig_configs_from(&path)
+    };
+
+    let rigs = configs(r#"{ "transport": "simulated", "window_size": 8, "rigs": [{ "id": "a" }, { "id": "b", "transport": "serial", "port": "/dev/ttyUSB1", "window_size": 4 }] }"#).unwrap();
+    assert_eq!(vec!["a", "b"], rigs.iter().map(|rig| rig.esp32_id()).collect::<Vec<_>>());
+    assert_eq!(&TransportConfig::Simulated, rigs[0].esp32_transport());
+    assert_eq!((8, 4), (rigs[0].esp32_arq().window_size, rigs[1].esp32_arq().window_size));
+
+    let rigs = configs(r#"{ "port": "/dev/ttyUSB0" }"#).unwrap();
+    assert_eq!(vec![DEFAULT_RIG_ID], rigs.iter().map(|rig| rig.esp32_id()).collect::<Vec<_>>());
+
+    // A rig that's wrong fails the lot, saying what's wrong with it
+    for (esp32, error) in [(r#"{ "rigs": [] }"#                                                                          , ConfigError::InvalidField("rigs")),
+                           (r#"{ "rigs": [{ "id": "" }] }"#                                                              , ConfigError::InvalidField("id")),
+                           (r#"{ "rigs": [{ "port": "/dev/ttyUSB0" }, { "port": "/dev/ttyUSB1" }] }"#                    , ConfigError::DuplicateId(String::from(DEFAULT_RIG_ID))),
+                           (r#"{ "rigs": [{ "id": "a", "port": "/dev/ttyUSB0" }, { "id": "b", "port": "/dev/ttyUSB0" }] }"#, ConfigError::DuplicatePort(String::from("/dev/ttyUSB0"))),
+                           (r#"{ "rigs": [{ "id": "a" }, { "id": "b", "port": "auto" }] }"#                              , ConfigError::MultipleDiscover),
+                           (r#"{ "rigs": [{ "id": "a", "window_size": 0 }] }"#                                           , ConfigError::InvalidField("window_size"))] {
+        assert_eq!(Some(error), configs(esp32).err(), "{}", esp32);
+    }
+
+    // The rig that looks for its ESP32 keeps off the ports the others are configured on
+    let rigs = configs(r#"{ "rigs": [{ "id": "a" }, { "id": "b", "port": "/dev/ttyUSB1" }, { "id": "c", "transport": "simulated" }] }"#).unwrap();
+    assert_eq!(vec![std::path::PathBuf::from("/dev/ttyUSB1")], rigs[0].esp32_claimed_ports());
+    assert!(rigs[1].esp32_claimed_ports().is_empty());
+
+    // Without a config file there's the one rig, looked for on every serial port
+    let rigs = load_rig_configs_from(&path.with_extension("missing")).unwrap();
+    assert_eq!(vec![DEFAULT_RIG_ID], rigs.iter().map(|rig| rig.esp32_id()).collect::<Vec<_>>());
+
+    // Rigs are looked up by id, the first one stands in when none is named
+    let rig = |id| {
+        let (tx, rx) = std::sync::mpsc::channel::<Message>();
+        Rig::new(id, tx, rx, Arc::new(Mutex::new(Esp32Status::default())))
+    };
+    let mut registry = RigRegistry::new();
+    assert!(registry.get(None).is_none());
+    assert!( registry.register(rig("a")));
+    assert!( registry.register(rig("b")));
+    assert!(!registry.register(rig("a")));
+    assert_eq!(2, registry.rigs().len());
+    assert_eq!(Some("a"), registry.get(None).map(Rig::id));
+    assert_eq!(Some("b"), registry.get(Some("b")).map(Rig::id));
+    assert!(registry.get(Some("c")).is_none());
+
+    // Every rig gets a backend of its own, and a capture only runs on the one it's sent to
+    let rigs = configs(r#"{ "transport": "simulated", "rigs": [{ "id": "a" }, { "id": "b" }] }"#).unwrap();
+    std::fs::remove_file(&path).unwrap();
+    let logger = Arc::new(Mutex::new(Logger::new()));
+    let runtime = Runtime::new().unwrap();
+    let _runtime = runtime.enter();
+    let registry = launch_esp32_backends(logger, rigs);
+    let session = |id| registry.get(Some(id)).unwrap().status().lock().unwrap().session_state();
+
+    let start = Instant::now();
+    while session("a") != SessionState::Configuring || session("b") != SessionState::Configuring {
+        assert!(start.elapsed() < Duration::from_secs(10));
+        std::thread::sleep(Duration::from_millis(10));
+    }
+
+    let capture = |id| {
+        let rig = registry.get(Some(id)).unwrap();
+        rig.sender().send(Message::StartCapture(crate::model::types::Project::default())).unwrap();
+        assert!(Ok(Message::BackendReady(false)) == rig.receiver().lock().unwrap().recv_timeout(Duration::from_secs(60)));
+    };
+
+    capture("b");
+    assert_eq!(SessionState::Configuring, session("a"));
+    capture("a");
+
+    // A rig is back to taking orders once it's done with a capture, and says it's ready for the next
+    let ready = |id| {
+        let rig = registry.get(Some(id)).unwrap();
+        rig.sender().send(Message::BackendStatusRequest).unwrap();
+        Ok(Message::BackendReady(true)) == rig.receiver().lock().unwrap().recv_timeout(Duration::from_secs(5))
+    };
+    let start = Instant::now();
+    while session("b") != SessionState::Configuring {
+        assert!(start.elapsed() < Duration::from_secs(10));
+        std::thread::sleep(Duration::from_millis(10));
+    }
+    assert!(ready("b"));
+
+    // Shutting down ends every session, those waiting on an order too, and waits for them to wind down
+    let start = Instant::now();
+    runtime.block_on(registry.shutdown());
+    assert!(start.elapsed() < Duration::from_secs(10));
+    assert!(registry.rigs().iter().all(|rig| rig.status().lock().unwrap().protocol().is_none()));
+}
+
+#[test]
+fn test_capture_contents() {
+    use crate::controller::esp32_backend::{capture_contents, CaptureData};
+    use crate::internal::frame_type::{AuthMode, NetworkInfo, SecondaryChannel};
+
+    // What a scan said about each access point is stored with the project, and reads back the same
+    let lab  = NetworkInfo::from_components(BSSID::from_bytes(&[0x24, 0x0A, 0xC4, 0x00, 0x00, 0x01]).unwrap(), 6, AuthMode::from_int(3), false).unwrap();
+    let lab5 = NetworkInfo::from_components(BSSID::from_bytes(&[0x24, 0x0A, 0xC4, 0x00, 0x00, 0x02]).unwrap(), 36, AuthMode::from_int(3), true).unwrap()
+        .with_secondary_channel(SecondaryChannel::Above);
+    let mut data = CaptureData::default();
+    data.networks.insert(NetworkId::from_int(1), vec![lab, lab5]);
+
+    let stored = capture_contents(&data, vec![]).to_string();
+    let contents: json::Value = json::from_str(&stored).unwrap();
+    let networks = contents["networks"].as_array().unwrap();
+    assert_eq!(1, networks.len());
+    assert_eq!(json::json!(NetworkId::from_int(1)), networks[0]["id"]);
+    assert_eq!(json::json!(data.networks[&NetworkId::from_int(1)]), networks[0]["info"]);
+    assert_eq!(json::json!(36), networks[0]["info"][1]["channel"]);
+    assert_eq!(json::json!(true), networks[0]["info"][1]["hidden"]);
+    assert_eq!(json::json!("complete"), contents["status"]);
+}
+
+/// Any command `Frame::from_cmd` takes, with fields in the ranges the protocol allows
+#[cfg(test)]
+fn arb_cmd() -> impl proptest::strategy::Strategy<Value = Cmd> {
+    use proptest::prelude::*;
+    use crate::internal::frame_type::{AuthMode, DeviceError, DeviceErrorCode, GridRecords, GridStep, NetworkInfo, ProtocolInfo, SecondaryChannel};
+
+    let position  = (any::<u32>(), any::<u32>()).prop_map(|(pitch, yaw)| Position::from_int(pitch, yaw));
+    let step_size = (any::<u32>(), any::<u32>()).prop_map(|(pitch, yaw)| StepSize::from_pitch_yaw(pitch, yaw).unwrap());
+    let network   = any::<u32>().prop_map(NetworkId::from_int);
+    let record    = (any::<u32>(), -127..=0_i8).prop_map(|(id, rssi)| Record::from_components(NetworkId::from_int(id), RSSI::from_int(rssi).unwrap()));
+    let net_info  = (any::<[u8; 6]>(), prop_oneof![1..=14_u8, 32..=177_u8], 0..=2_u8, any::<u8>(), any::<bool>())
+        .prop_map(|(bssid, channel, secondary, auth_mode, hidden)| {
+            let secondary = [SecondaryChannel::None, SecondaryChannel::Above, SecondaryChannel::Below][secondary as usize];
+            NetworkInfo::from_components(BSSID::new(bssid), channel, AuthMode::from_int(auth_mode), hidden).unwrap().with_secondary_channel(secondary)
+        });
+    let entry     = (any::<i64>(), any::<i64>(), proptest::collection::vec(record.clone(), 0..8))
+        .prop_map(|(pitch, yaw, records)| GridRecords::new(GridStep::from_int(pitch, yaw), records));
+    let info      = (any::<u8>(), any::<u8>(), any::<u32>()).prop_map(|(version, min_version, capabilities)| ProtocolInfo::from_components(version, min_version, capabilities));
+
+    prop_oneof![
+        Just(Cmd::Reset),
+        Just(Cmd::Ready),
+        Just(Cmd::RequestPosition),
+        Just(Cmd::EndOfTransmission),
+        Just(Cmd::Heartbeat),
+        Just(Cmd::Pause),
+        Just(Cmd::Resume),
+        Just(Cmd::Abort),
+        proptest::option::of(info.clone()).prop_map(|info| Cmd::StartOfTransmission { info }),
+        (any::<u32>(), proptest::option::of(info)).prop_map(|(frame_id, info)| Cmd::Ack { frame_id, info }),
+        any::<u32>().prop_map(|frame_id| Cmd::RequestAck { frame_id }),
+        (any::<u32>(), any::<u32>()).prop_map(|(frame_id_start, frame_id_end)| Cmd::RequestRetransmit { frame_id_start, frame_id_end }),
+        (network.clone(), "[ -~]{0,32}").prop_map(|(id, ssid)| Cmd::AddSSID { id, ssid: SSID::new(ssid) }),
+        (network.clone(), any::<[u8; 6]>()).prop_map(|(id, bssid)| Cmd::AddBSSID { id, bssid: BSSID::new(bssid) }),
+        (network, net_info).prop_map(|(id, info)| Cmd::NetworkInfo { id, info }),
+        (any::<u8>(), position.clone(), "[ -~]{0,64}")
+            .prop_map(|(code, position, context)| Cmd::DeviceError { error: DeviceError::new(DeviceErrorCode::from_int(code), position, context) }),
+        (position.clone(), proptest::collection::vec(record, 1..32))
+            .prop_map(|(position, records)| Cmd::RecordRSSI { position, record_count: records.len() as u32, records }),
+        position.clone().prop_map(|position| Cmd::SetPosition { position }),
+        (position.clone(), step_size, any::<u8>())
+            .prop_map(|(position, step_size, measurements_per_step)| Cmd::SetParams { position, step_size, measurements_per_step }),
+        (position.clone(), "[ -~]{0,32}", any::<u32>())
+            .prop_map(|(position, format, size)| Cmd::TransmitPicture { position, body: json::json!({ "format": format, "size": size }) }),
+        (0..=4_u8, "[ -~]{0,64}")
+            .prop_map(|(severity, msg)| Cmd::TransmitLogs { logs: json::json!({ "logs": [{ "severity": severity, "msg": msg }] }) }),
+        (0..=0xD_u8, 0..0x8000_u16, any::<bool>(), proptest::collection::vec(any::<u8>(), 0..64))
+            .prop_map(|(cmd_nibble, sequence, last, payload)| Cmd::Fragment { cmd_nibble, sequence, last, payload }),
+        (position, any::<u32>(), any::<u32>(), proptest::collection::vec(any::<u8>(), 0..64))
+            .prop_map(|(position, offset, total_size, data)| Cmd::PictureChunk { position, offset, total_size, data }),
+        proptest::collection::vec(entry, 0..8).prop_map(|entries| Cmd::RecordBatch { entries }),
+    ]
+}
+
+#[cfg(test)]
+proptest::proptest! {
+    #[test]
+    fn prop_frame_roundtrip(cmd in arb_cmd(), frame_id in proptest::prelude::any::<u32>()) {
+        let frame = Frame::from_cmd(cmd, frame_id).unwrap();
+        let bytes = frame.as_bytes().unwrap();
+
+        proptest::prop_assert_eq!(Ok((frame, bytes.len() as u16)), Frame::parse(&bytes));
+    }
+
+    #[test]
+    fn prop_frame_parse_truncated(cmd in arb_cmd(), cut in proptest::prelude::any::<proptest::sample::Index>()) {
+        let bytes = Frame::from_cmd(cmd, 1).unwrap().as_bytes().unwrap();
+        let cut = 1 + cut.index(bytes.len() - 1);
+
+        proptest::prop_assert_eq!(Err(FrameError::NotEnoughBytes), Frame::parse(&bytes[..cut]));
+    }
+
+    #[test]
+    fn prop_framing_roundtrip(bytes in proptest::collection::vec(proptest::prelude::any::<u8>(), 0..1024)) {
+        use crate::internal::framing::Framing;
+
+        for framing in [Framing::Cobs, Framing::Slip] {
+            // SLIP packets start with a delimiter too, which leaves an empty packet in front
+            let encoded = framing.encode(&bytes);
+            let start = match framing.next_packet(&encoded) {
+                Some((Some(packet), consumed)) if packet.is_empty() && consumed < encoded.len() => consumed,
+                _ => 0
+            };
+
+            proptest::prop_assert!(encoded.len() <= framing.max_packet_size());
+            proptest::prop_assert_eq!(Some((Some(bytes.clone()), encoded.len() - start)), framing.next_packet(&encoded[start..]));
+        }
+    }
+
+    #[test]
+    fn prop_frame_parse_total(bytes in proptest::collection::vec(proptest::prelude::any::<u8>(), 0..128)) {
+        let _ = Frame::parse(&bytes);
+        let _ = Frame::scan (&bytes);
+    }
+
+    #[test]
+    fn prop_cmd_parse_body_total(cmd_nibble in 0..=0xF_u8, length in 0..0x40_usize, data in proptest::collection::vec(proptest::prelude::any::<u8>(), 0..0x40)) {
+        let _ = Cmd::parse_body(cmd_nibble, length, &data);
+    }
 }
-*/
\ No newline at end of file